@@ -0,0 +1,153 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use virtual_machine::libs::{
+    console::BufferConsole,
+    loader::Image,
+    vm::{Engine, Vm},
+};
+
+/* instructions executed per measured iteration */
+const INSTRUCTIONS: u64 = 100_000;
+
+struct Workload {
+    name: &'static str,
+    origin: u16,
+    words: &'static [u16],
+    /* extra memory to initialise, as (address, words) */
+    data: &'static [(u16, &'static [u16])],
+}
+
+const WORKLOADS: &[Workload] = &[
+    /*
+        AND R1, R1, #0
+    LOOP ADD R1, R1, #1
+        ADD R2, R1, R1
+        AND R3, R2, #15
+        NOT R4, R3
+        BRnzp LOOP
+    */
+    Workload {
+        name: "alu_loop",
+        origin: 0x3000,
+        words: &[0x5260, 0x1261, 0x1441, 0x56AF, 0x98FF, 0x0FFB],
+        data: &[],
+    },
+    /*
+    OUTER LD  R1, SRCP
+          LD  R2, DSTP
+          LD  R3, COUNT
+    LOOP  LDR R4, R1, #0
+          STR R4, R2, #0
+          ADD R1, R1, #1
+          ADD R2, R2, #1
+          ADD R3, R3, #-1
+          BRp LOOP
+          BRnzp OUTER
+    SRCP  .FILL x4000
+    DSTP  .FILL x5000
+    COUNT .FILL #256
+    */
+    Workload {
+        name: "memory_copy",
+        origin: 0x3000,
+        words: &[
+            0x2209, 0x2409, 0x2609, 0x6840, 0x7880, 0x1261, 0x14A1, 0x16FF, 0x03FA, 0x0FF6, 0x4000,
+            0x5000, 0x0100,
+        ],
+        data: &[(0x4000, &[0xBEEF; 256])],
+    },
+    /*
+    MAIN  LD  R6, STACK
+          AND R0, R0, #0
+          ADD R0, R0, #12
+          JSR SUM
+          BRnzp MAIN
+    SUM   ADD R6, R6, #-1     ; push R7, R1
+          STR R7, R6, #0
+          ADD R6, R6, #-1
+          STR R1, R6, #0
+          ADD R1, R0, #0
+          BRz BASE
+          ADD R0, R0, #-1
+          JSR SUM             ; R0 = SUM(n - 1)
+          ADD R0, R0, R1
+    BASE  LDR R1, R6, #0      ; pop R1, R7
+          ADD R6, R6, #1
+          LDR R7, R6, #0
+          ADD R6, R6, #1
+          RET
+    STACK .FILL x6000
+    */
+    Workload {
+        name: "recursive_calls",
+        origin: 0x3000,
+        words: &[
+            0x2C12, 0x5020, 0x102C, 0x4801, 0x0FFB, 0x1DBF, 0x7F80, 0x1DBF, 0x7380, 0x1220, 0x0403,
+            0x103F, 0x4FF8, 0x1001, 0x6380, 0x1DA1, 0x6F80, 0x1DA1, 0xC1C0, 0x6000,
+        ],
+        data: &[],
+    },
+    /*
+    LOOP LEA R0, MSG
+         PUTS
+         BRnzp LOOP
+    MSG  .STRINGZ "hello, world\n"
+    */
+    Workload {
+        name: "puts_output",
+        origin: 0x3000,
+        words: &[
+            0xE002, 0xF022, 0x0FFD, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x2C, 0x20, 0x77, 0x6F, 0x72,
+            0x6C, 0x64, 0x0A, 0x00,
+        ],
+        data: &[],
+    },
+];
+
+fn machine(workload: &Workload, engine: Engine, console: &BufferConsole) -> Vm {
+    let mut vm = Vm::with_engine(engine);
+    vm.set_console(console.clone());
+
+    vm.load_image(&Image {
+        origin: workload.origin,
+        words: workload.words.to_vec(),
+    });
+    for (address, words) in workload.data {
+        for (offset, word) in words.iter().enumerate() {
+            vm.write_memory(address + offset as u16, *word);
+        }
+    }
+    vm.set_pc(workload.origin);
+    vm
+}
+
+/*
+reports instructions per second for each workload on each engine; compare
+against a saved run with `cargo bench -- --save-baseline <name>` followed by
+`cargo bench -- --baseline <name>`, which flags regressions
+*/
+fn interpreter(c: &mut Criterion) {
+    for workload in WORKLOADS {
+        let mut group = c.benchmark_group(workload.name);
+        group.throughput(Throughput::Elements(INSTRUCTIONS));
+
+        for engine in [Engine::Interpreter, Engine::Threaded] {
+            let console = BufferConsole::new();
+            let mut vm = machine(workload, engine, &console);
+
+            group.bench_function(BenchmarkId::from_parameter(format!("{engine:?}")), |b| {
+                b.iter(|| {
+                    vm.run(black_box(INSTRUCTIONS))
+                        .expect("benchmark workload faulted");
+                    console.take_output();
+                })
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use virtual_machine::libs::fuzz;
+
+fuzz_target!(|data: &[u8]| fuzz::load_object(data));
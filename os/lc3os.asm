@@ -0,0 +1,213 @@
+; LC-3 operating system image, booted with `run --os`
+;
+; the trap vector table at x0000 and the interrupt vector table at x0100
+; point at the service routines below. entries left zero here are pointed
+; at BAD_TRAP and BAD_INTERRUPT when the image is booted.
+;
+; TRAP enters a routine in supervisor mode with the caller's PSR and PC on
+; the supervisor stack and the return address in R7, so routines end with
+; RTI. they preserve every register but R0 for GETC and IN, and RTI
+; restores the caller's condition codes.
+
+        .ORIG x0000
+
+; trap vector table
+        .BLKW x20
+        .FILL TRAP_GETC         ; x20
+        .FILL TRAP_OUT          ; x21
+        .FILL TRAP_PUTS         ; x22
+        .FILL TRAP_IN           ; x23
+        .FILL TRAP_PUTSP        ; x24
+        .FILL TRAP_HALT         ; x25
+        .BLKW xDA
+
+; interrupt vector table
+        .FILL PRIVILEGE_VIOLATION       ; x00
+        .FILL ILLEGAL_OPCODE            ; x01
+        .FILL ACCESS_VIOLATION          ; x02
+        .FILL DIVIDE_BY_ZERO            ; x03
+        .BLKW xFC
+
+; GETC: read a character from the keyboard into R0, without echo
+TRAP_GETC
+        LDI R0, KBSR_ADDR
+        BRzp TRAP_GETC
+        LDI R0, KBDR_ADDR
+        RTI
+
+; OUT: write the character in R0 to the display
+TRAP_OUT
+        ADD R6, R6, #-1
+        STR R7, R6, #0
+        JSR PUTC
+        LDR R7, R6, #0
+        ADD R6, R6, #1
+        RTI
+
+; PUTS: write the string of one character per word at R0
+TRAP_PUTS
+        ADD R6, R6, #-1
+        STR R7, R6, #0
+        JSR PRINT
+        LDR R7, R6, #0
+        ADD R6, R6, #1
+        RTI
+
+; IN: prompt for a character, read it into R0 and echo it
+TRAP_IN
+        ADD R6, R6, #-2
+        STR R7, R6, #0
+        STR R1, R6, #1
+        LEA R0, IN_PROMPT
+        JSR PRINT
+IN_WAIT
+        LDI R0, KBSR_ADDR
+        BRzp IN_WAIT
+        LDI R0, KBDR_ADDR
+        JSR PUTC
+        ADD R1, R0, #0
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        JSR PUTC
+        ADD R0, R1, #0
+        LDR R7, R6, #0
+        LDR R1, R6, #1
+        ADD R6, R6, #2
+        RTI
+
+; PUTSP: write the string of two characters per word at R0, low byte first
+TRAP_PUTSP
+        ADD R6, R6, #-7
+        STR R0, R6, #0
+        STR R1, R6, #1
+        STR R2, R6, #2
+        STR R3, R6, #3
+        STR R4, R6, #4
+        STR R5, R6, #5
+        STR R7, R6, #6
+        ADD R1, R0, #0
+PUTSP_LOOP
+        LDR R2, R1, #0
+        BRz PUTSP_DONE
+        ADD R0, R2, #0
+        JSR PUTC
+        ; shift the high byte down a bit at a time
+        AND R0, R0, #0
+        LD R3, HIGH_BIT
+        AND R4, R4, #0
+        ADD R4, R4, #1
+PUTSP_SHIFT
+        AND R5, R2, R3
+        BRz PUTSP_ZERO
+        ADD R0, R0, R4
+PUTSP_ZERO
+        ADD R4, R4, R4
+        ADD R3, R3, R3
+        BRnp PUTSP_SHIFT
+        ADD R0, R0, #0
+        BRz PUTSP_NEXT
+        JSR PUTC
+PUTSP_NEXT
+        ADD R1, R1, #1
+        BRnzp PUTSP_LOOP
+PUTSP_DONE
+        LDR R0, R6, #0
+        LDR R1, R6, #1
+        LDR R2, R6, #2
+        LDR R3, R6, #3
+        LDR R4, R6, #4
+        LDR R5, R6, #5
+        LDR R7, R6, #6
+        ADD R6, R6, #7
+        RTI
+
+; HALT: stop the clock, with R0 holding the MCR while stopped. if the clock
+; is started again the caller continues
+TRAP_HALT
+        ADD R6, R6, #-3
+        STR R0, R6, #0
+        STR R1, R6, #1
+        STR R7, R6, #2
+        LEA R0, HALT_MESSAGE
+        JSR PRINT
+        LDI R0, MCR_ADDR
+        LD R1, CLOCK_MASK
+        AND R0, R0, R1
+        LDR R1, R6, #1
+        LDR R7, R6, #2
+        STI R0, MCR_ADDR
+        LDR R0, R6, #0
+        ADD R6, R6, #3
+        RTI
+
+; unknown trap vectors, exceptions and unexpected interrupts. the faults
+; report themselves and halt, with R0 and R7 lost if the program continues
+BAD_TRAP
+        LEA R0, BAD_TRAP_MESSAGE
+        BRnzp FAULT
+PRIVILEGE_VIOLATION
+        LEA R0, PRIVILEGE_MESSAGE
+        BRnzp FAULT
+ILLEGAL_OPCODE
+        LEA R0, ILLEGAL_MESSAGE
+        BRnzp FAULT
+ACCESS_VIOLATION
+        LEA R0, ACCESS_MESSAGE
+        BRnzp FAULT
+DIVIDE_BY_ZERO
+        LEA R0, DIVIDE_MESSAGE
+FAULT
+        JSR PRINT
+        BRnzp TRAP_HALT
+BAD_INTERRUPT
+        RTI
+
+; write the character in R0 to the display once it is ready
+PUTC
+        ADD R6, R6, #-1
+        STR R1, R6, #0
+PUTC_WAIT
+        LDI R1, DSR_ADDR
+        BRzp PUTC_WAIT
+        STI R0, DDR_ADDR
+        LDR R1, R6, #0
+        ADD R6, R6, #1
+        RET
+
+; write the string of one character per word at R0
+PRINT
+        ADD R6, R6, #-3
+        STR R0, R6, #0
+        STR R1, R6, #1
+        STR R7, R6, #2
+        ADD R1, R0, #0
+PRINT_LOOP
+        LDR R0, R1, #0
+        BRz PRINT_DONE
+        JSR PUTC
+        ADD R1, R1, #1
+        BRnzp PRINT_LOOP
+PRINT_DONE
+        LDR R0, R6, #0
+        LDR R1, R6, #1
+        LDR R7, R6, #2
+        ADD R6, R6, #3
+        RET
+
+KBSR_ADDR       .FILL xFE00
+KBDR_ADDR       .FILL xFE02
+DSR_ADDR        .FILL xFE04
+DDR_ADDR        .FILL xFE06
+MCR_ADDR        .FILL xFFFE
+CLOCK_MASK      .FILL x7FFF
+HIGH_BIT        .FILL x0100
+
+IN_PROMPT               .STRINGZ "Enter a character: "
+HALT_MESSAGE            .STRINGZ "Program halted\n"
+BAD_TRAP_MESSAGE        .STRINGZ "Unknown trap vector\n"
+PRIVILEGE_MESSAGE       .STRINGZ "Privilege mode violation\n"
+ILLEGAL_MESSAGE         .STRINGZ "Illegal opcode\n"
+ACCESS_MESSAGE          .STRINGZ "Access violation\n"
+DIVIDE_MESSAGE          .STRINGZ "Division by zero\n"
+
+        .END
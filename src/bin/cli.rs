@@ -1,121 +1,335 @@
-use std::{
-    io::{self, Read},
-    process::abort,
-    u16,
-};
+use std::{env, fs, process, time::Duration};
 
 use tracing::info;
 use virtual_machine::libs::{
-    constants::{MEMORY_MAX, PC_START},
-    instructions::{InstructionSet, Instructions},
-    types::{
-        ConditionalFlags, MemomryTrait, Memory, Opcodes, RegisterStorage, RegisterStorageTrait,
-        Registers,
-    },
+    console::StdConsole,
+    disk::{self, Disk},
+    files::HostFiles,
+    framebuffer, golden,
+    grade::{self, GradingScript},
+    instructions::Revision,
+    limits::{Limits, StopReason},
+    loader::Image,
+    numeric,
+    os::Os,
+    protection::Protection,
+    replay::{InputRecording, RecordingConsole, ReplayConsole},
+    snapshot::Snapshot,
+    timer,
+    translate::Translator,
+    vm::{Engine, Isa, Vm},
 };
 
+const USAGE: &str = "usage:
+    cli run [--engine interpreter|threaded] [--isa lc3|lc3b] [--max-instructions <n>]
+            [--timeout-ms <n>] [--max-output <bytes>] [--detect-loops]
+            [--protect] [--exceptions] [--timer]
+            [--framebuffer] [--screenshot <file.png|file.ppm>] [--show-framebuffer]
+            [--disk <image>] [--os | --os-source <os.asm>] [--files <directory>]
+            [--numeric-traps] [--arithmetic] [--revision legacy|strict]
+            [--save-snapshot <file>] [--record <file> | --replay <file>]
+            (<image.obj> | --resume <file>)
+    cli translate [--main] [--arithmetic] [--revision legacy|strict] [-o <output.rs>]
+            <image.obj>
+    cli test [--engine interpreter|threaded] <directory>
+    cli grade [--engine interpreter|threaded] [--json <report.json>] <image.obj> <script>";
+
 fn main() {
     tracing_subscriber::fmt::init();
     // RUST_LOG=virtual_machine=trace cargo run --bin cli
 
-    let mut register_storage: RegisterStorage = RegisterStorage::new();
-    let mut memory: Memory = Memory::new();
-
-    // since exactly one condition flag should be set at any given time, set the Z flag
-    let _set_initial_cond_to_zero =
-        register_storage.store(ConditionalFlags::ZRO as u16, Registers::COND as u16);
-
-    /* set the PC to starting position */
-    /* 0x3000 is the default */
-    let _set_default_mem_addr_in_pc = register_storage.store(PC_START, Registers::PC as u16);
-
-    // loop {
-    /* FETCH: Get the instruction from memory at the address pointed to by the PC */
-    // let pc = register_storage.load(Registers::PC).unwrap();
-    // let instr = memory.read(pc);
-
-    /* INCREMENT PC */
-    // register_storage.store(pc + 1, Registers::PC)?;
-
-    //     match Opcodes::from_u16(op) {
-    //         Some(Opcodes::ADD) => {
-    //             println!("Add Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::AND) => {
-    //             println!("And Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::NOT) => {
-    //             println!("NOT Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::BR) => {
-    //             println!("BR Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::JMP) => {
-    //             println!("JMP Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::JSR) => {
-    //             println!("JSR Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::LD) => {
-    //             println!("LD Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::LDI) => {
-    //             println!("LDI Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::LDR) => {
-    //             println!("LDR Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::LEA) => {
-    //             println!("LEA Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::ST) => {
-    //             println!("ST Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::STI) => {
-    //             println!("STI Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::STR) => {
-    //             println!("STR Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::TRAP) => {
-    //             println!("TRAP Op");
-    //             break;
-    //         },
-    //         Some(Opcodes::RES) | Some(Opcodes::RTI) | _ => {
-    //             println!("Bad Opcode");
-    // abort();
-    //             break;
-    //         }
-    //     }
-    // }
-
-    // let r0 = (instr >> 9)  & 0x7;
-    // let r1 = (instr >> 6)  & 0x7;
-    // let imm_flag = (instr >> 5)  & 0x1;
-    // println!("{instr :#01x} {r0} {r1} {imm_flag}");
-    // let bit_count: u32 = 5;
-    // let bits = 0b01011;
-    // let sign_extend_result = Instructions::sign_extend(bits, bit_count).unwrap();
-
-    // println!("{:016b}", sign_extend_result);
-    // println!("{:04x}", 0b0000111000000000);
-    // println!("{:04x}", 0b0000000111111111);
-    // println!("{:04x}", 0b0000000000000111);
-
-    // println!("{:b}", sign_extend_result);
-
-    println!("{register_storage:?}")
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("run") => run(&args[1..]),
+        Some("translate") => translate(&args[1..]),
+        Some("test") => test(&args[1..]),
+        Some("grade") => grade(&args[1..]),
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        }
+    }
+}
+
+fn run(args: &[String]) {
+    let mut engine = Engine::default();
+    let mut isa = Isa::default();
+    let mut image_path = None;
+    let mut resume = None;
+    let mut save_snapshot = None;
+    let mut limits = Limits::default();
+    let mut protect = false;
+    let mut exceptions = false;
+    let mut interval_timer = false;
+    let mut with_framebuffer = false;
+    let mut screenshot = None;
+    let mut show_framebuffer = false;
+    let mut disk_image = None;
+    let mut os = false;
+    let mut os_source = None;
+    let mut files_root = None;
+    let mut numeric_traps = false;
+    let mut arithmetic = false;
+    let mut revision = Revision::default();
+    let mut record = None;
+    let mut replay = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--engine" => {
+                engine = args
+                    .next()
+                    .and_then(|name| Engine::from_name(name))
+                    .unwrap_or_else(|| fail(USAGE));
+            }
+            "--isa" => {
+                isa = args
+                    .next()
+                    .and_then(|name| Isa::from_name(name))
+                    .unwrap_or_else(|| fail(USAGE));
+            }
+            "--resume" => resume = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--save-snapshot" => save_snapshot = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--record" => record = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--replay" => replay = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--max-instructions" => limits.max_instructions = Some(number(args.next())),
+            "--timeout-ms" => limits.timeout = Some(Duration::from_millis(number(args.next()))),
+            "--max-output" => limits.max_output_bytes = Some(number(args.next())),
+            "--detect-loops" => limits.detect_loops = true,
+            "--protect" => protect = true,
+            "--exceptions" => exceptions = true,
+            "--timer" => interval_timer = true,
+            "--framebuffer" => with_framebuffer = true,
+            "--screenshot" => screenshot = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--show-framebuffer" => show_framebuffer = true,
+            "--disk" => disk_image = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--os" => os = true,
+            "--os-source" => os_source = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--files" => files_root = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--numeric-traps" => numeric_traps = true,
+            "--arithmetic" => arithmetic = true,
+            "--revision" => {
+                revision = args
+                    .next()
+                    .and_then(|name| Revision::from_name(name))
+                    .unwrap_or_else(|| fail(USAGE));
+            }
+            path => image_path = Some(path),
+        }
+    }
+
+    let mut vm = Vm::with_engine(engine);
+    vm.set_isa(isa);
+    if protect {
+        vm.set_protection(Some(Protection::lc3()));
+    }
+    vm.set_exceptions(exceptions);
+    vm.set_arithmetic_extension(arithmetic);
+    vm.set_revision(revision);
+    if interval_timer {
+        timer::attach_timer(&mut vm.memory.bus).unwrap_or_else(|err| fail(&err.to_string()));
+    }
+    if let Some(path) = disk_image {
+        let disk = Disk::open(path).unwrap_or_else(|err| fail(&err.to_string()));
+        disk::attach_disk(&mut vm.memory.bus, disk).unwrap_or_else(|err| fail(&err.to_string()));
+    }
+    let framebuffer = (with_framebuffer || screenshot.is_some() || show_framebuffer).then(|| {
+        framebuffer::attach_framebuffer(&mut vm.memory.bus)
+            .unwrap_or_else(|err| fail(&err.to_string()))
+    });
+
+    match (record, replay) {
+        (Some(path), None) => vm.set_console(
+            RecordingConsole::with_sink(StdConsole, path)
+                .unwrap_or_else(|err| fail(&err.to_string())),
+        ),
+        (None, Some(path)) => {
+            let recording = InputRecording::load(path).unwrap_or_else(|err| fail(&err.to_string()));
+            vm.set_console(ReplayConsole::new(recording, StdConsole));
+        }
+        (Some(_), Some(_)) => fail("--record and --replay are mutually exclusive"),
+        (None, None) => {}
+    }
+
+    let os = match os_source {
+        Some(path) => {
+            let source = fs::read_to_string(path).unwrap_or_else(|err| fail(&err.to_string()));
+            Some(Os::assemble(&source).unwrap_or_else(|err| fail(&err.to_string())))
+        }
+        None => os.then(Os::bundled),
+    };
+    if let Some(os) = os {
+        os.boot(&mut vm)
+            .unwrap_or_else(|err| fail(&err.to_string()));
+    }
+
+    if let Some(root) = files_root {
+        HostFiles::new(root)
+            .unwrap_or_else(|err| fail(&err.to_string()))
+            .install(&mut vm);
+    }
+
+    if numeric_traps {
+        numeric::install(&mut vm);
+    }
+
+    match (resume, image_path) {
+        (Some(path), _) => {
+            let snapshot = Snapshot::load(path).unwrap_or_else(|err| fail(&err.to_string()));
+            snapshot.restore(&mut vm);
+        }
+        (None, Some(path)) => {
+            let image = Image::from_file(path).unwrap_or_else(|err| fail(&err.to_string()));
+            vm.load_image(&image);
+            vm.set_pc(image.origin);
+        }
+        (None, None) => fail(USAGE),
+    }
+
+    vm.set_limits(limits);
+    let stopped = vm.run_with_limits();
+
+    if let Some(framebuffer) = &framebuffer {
+        let framebuffer = framebuffer.borrow();
+        if let Some(path) = screenshot {
+            framebuffer
+                .save(path)
+                .unwrap_or_else(|err| fail(&err.to_string()));
+        }
+        if show_framebuffer {
+            print!("{}", framebuffer.to_terminal());
+        }
+    }
+
+    info!("executed {} instructions", vm.instruction_count);
+
+    /* a machine that faulted is saved too, to inspect where it stopped */
+    if let Some(path) = save_snapshot {
+        Snapshot::capture(&vm)
+            .save(path)
+            .unwrap_or_else(|err| fail(&err.to_string()));
+        info!("saved snapshot to {path}");
+    }
+
+    match stopped {
+        Ok(StopReason::Halted) => {}
+        Ok(reason) => eprintln!("stopped: {reason}"),
+        Err(err) => fail(&err.to_string()),
+    }
+}
+
+fn translate(args: &[String]) {
+    let mut with_main = false;
+    let mut arithmetic = false;
+    let mut revision = Revision::default();
+    let mut output = None;
+    let mut image_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--main" => with_main = true,
+            "--arithmetic" => arithmetic = true,
+            "--revision" => {
+                revision = args
+                    .next()
+                    .and_then(|name| Revision::from_name(name))
+                    .unwrap_or_else(|| fail(USAGE));
+            }
+            "-o" => output = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            path => image_path = Some(path),
+        }
+    }
+
+    let image_path = image_path.unwrap_or_else(|| fail(USAGE));
+    let image = Image::from_file(image_path).unwrap_or_else(|err| fail(&err.to_string()));
+
+    let translator = Translator::new(&image, revision, arithmetic);
+    let source = translator.emit(with_main);
+
+    match output {
+        Some(path) => {
+            fs::write(path, source).unwrap_or_else(|err| fail(&err.to_string()));
+            info!(
+                "translated {} blocks into {path}",
+                translator.blocks().count()
+            );
+        }
+        None => print!("{source}"),
+    }
+}
+
+fn test(args: &[String]) {
+    let mut engine = Engine::default();
+    let mut directory = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--engine" => {
+                engine = args
+                    .next()
+                    .and_then(|name| Engine::from_name(name))
+                    .unwrap_or_else(|| fail(USAGE));
+            }
+            path => directory = Some(path),
+        }
+    }
+
+    let directory = directory.unwrap_or_else(|| fail(USAGE));
+    let results =
+        golden::run_directory(directory, engine).unwrap_or_else(|err| fail(&err.to_string()));
+
+    print!("{}", golden::report(&results));
+    if !results.iter().all(golden::CaseResult::passed) {
+        process::exit(1);
+    }
+}
+
+fn grade(args: &[String]) {
+    let mut engine = Engine::default();
+    let mut json = None;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--engine" => {
+                engine = args
+                    .next()
+                    .and_then(|name| Engine::from_name(name))
+                    .unwrap_or_else(|| fail(USAGE));
+            }
+            "--json" => json = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            path => paths.push(path),
+        }
+    }
+
+    let [image_path, script_path] = paths[..] else {
+        fail(USAGE);
+    };
+    let image = Image::from_file(image_path).unwrap_or_else(|err| fail(&err.to_string()));
+    let script = GradingScript::load(script_path).unwrap_or_else(|err| fail(&err.to_string()));
+
+    let report = grade::grade(&image, &script, engine);
+
+    print!("{}", report.to_text());
+    if let Some(path) = json {
+        fs::write(path, report.to_json()).unwrap_or_else(|err| fail(&err.to_string()));
+    }
+    if report.score() < report.total() {
+        process::exit(1);
+    }
+}
+
+fn number(arg: Option<&String>) -> u64 {
+    arg.and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| fail(USAGE))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
 }
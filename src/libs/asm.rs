@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::libs::{
+    constants::MEMORY_MAX, instructions::Arithmetic, loader::Image, trap::Trap, types::Opcodes,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AsmError {
+    #[error("Assembler Error: line {line}: expected .ORIG before {found:?}")]
+    MissingOrig { line: usize, found: String },
+    #[error("Assembler Error: line {line}: unknown mnemonic {mnemonic:?}")]
+    UnknownMnemonic { line: usize, mnemonic: String },
+    #[error("Assembler Error: line {line}: only one .ORIG block is supported")]
+    MultipleOrig { line: usize },
+    #[error("Assembler Error: line {line}: invalid operand {operand:?}")]
+    InvalidOperand { line: usize, operand: String },
+    #[error("Assembler Error: line {line}: {mnemonic} takes {expected} operands")]
+    OperandCount {
+        line: usize,
+        mnemonic: String,
+        expected: usize,
+    },
+    #[error("Assembler Error: line {line}: {value} does not fit in {bits} bits")]
+    OutOfRange { line: usize, value: i32, bits: u32 },
+    #[error("Assembler Error: line {line}: label {label:?} is already defined")]
+    DuplicateLabel { line: usize, label: String },
+    #[error("Assembler Error: line {line}: undefined label {label:?}")]
+    UndefinedLabel { line: usize, label: String },
+    #[error("Assembler Error: the program does not fit in memory")]
+    TooLarge,
+}
+
+/* an assembled program and the addresses of its labels */
+pub struct Program {
+    pub image: Image,
+    pub symbols: HashMap<String, u16>,
+}
+
+/* one source line, split into its label, mnemonic and operands */
+struct Line {
+    number: usize,
+    label: Option<String>,
+    mnemonic: Option<String>,
+    operands: Vec<String>,
+}
+
+/*
+assemble LC-3 source with a single `.ORIG` block: the instructions of the
+ISA and its arithmetic extension (MUL, DIV, MOD, SHL, SHR, SRA), the trap
+aliases (GETC, OUT, PUTS, IN, PUTSP, HALT) and the .ORIG,
+.FILL, .BLKW, .STRINGZ and .END directives. numbers are decimal (`#10` or
+`10`), hex (`x1F`) or binary (`b101`); mnemonics and registers are case
+insensitive, labels are not
+*/
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(index, text)| parse_line(index + 1, text))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    /* first pass: the address of every label */
+    let mut symbols = HashMap::new();
+    let mut origin = None;
+    let mut address = 0usize;
+    for line in &lines {
+        let mnemonic = line.mnemonic.as_deref().map(str::to_ascii_uppercase);
+
+        if mnemonic.as_deref() == Some(".ORIG") {
+            if origin.is_some() {
+                return Err(AsmError::MultipleOrig { line: line.number });
+            }
+            let value = number(line, operand(line, 0)?)?;
+            origin = Some(value as u16);
+            address = value as u16 as usize;
+            continue;
+        }
+        if mnemonic.as_deref() == Some(".END") {
+            break;
+        }
+        if origin.is_none() {
+            let found = line.label.clone().or(mnemonic);
+            if let Some(found) = found {
+                return Err(AsmError::MissingOrig {
+                    line: line.number,
+                    found,
+                });
+            }
+            continue;
+        }
+
+        if let Some(label) = &line.label
+            && symbols.insert(label.clone(), address as u16).is_some()
+        {
+            return Err(AsmError::DuplicateLabel {
+                line: line.number,
+                label: label.clone(),
+            });
+        }
+        address += size(line)?;
+        if address > MEMORY_MAX {
+            return Err(AsmError::TooLarge);
+        }
+    }
+
+    /* second pass: encode */
+    let origin = origin.unwrap_or_default();
+    let mut words = Vec::new();
+    for line in &lines {
+        let Some(mnemonic) = &line.mnemonic else {
+            continue;
+        };
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        match mnemonic.as_str() {
+            ".ORIG" => {}
+            ".END" => break,
+            _ => {
+                let address = origin.wrapping_add(words.len() as u16);
+                encode(line, &mnemonic, address, &symbols, &mut words)?;
+            }
+        }
+    }
+
+    Ok(Program {
+        image: Image { origin, words },
+        symbols,
+    })
+}
+
+fn parse_line(number: usize, text: &str) -> Result<Line, AsmError> {
+    let mut tokens = tokenize(text).into_iter();
+    let mut line = Line {
+        number,
+        label: None,
+        mnemonic: None,
+        operands: Vec::new(),
+    };
+
+    let Some(first) = tokens.next() else {
+        return Ok(line);
+    };
+    if is_mnemonic(&first) {
+        line.mnemonic = Some(first);
+    } else {
+        let label = first.trim_end_matches(':').to_string();
+        if !is_label(&label) {
+            return Err(AsmError::UnknownMnemonic {
+                line: number,
+                mnemonic: first,
+            });
+        }
+        line.label = Some(label);
+        line.mnemonic = tokens.next();
+    }
+    line.operands = tokens.collect();
+
+    if let Some(mnemonic) = &line.mnemonic
+        && !is_mnemonic(mnemonic)
+    {
+        return Err(AsmError::UnknownMnemonic {
+            line: number,
+            mnemonic: mnemonic.clone(),
+        });
+    }
+    Ok(line)
+}
+
+/* split on whitespace and commas up to a comment, keeping string literals whole */
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break,
+            '"' => {
+                current.push(c);
+                while let Some(c) = chars.next() {
+                    current.push(c);
+                    match c {
+                        '\\' => current.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            c if c.is_whitespace() || c == ',' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn is_mnemonic(token: &str) -> bool {
+    let token = token.to_ascii_uppercase();
+    matches!(
+        token.as_str(),
+        "ADD"
+            | "AND"
+            | "NOT"
+            | "JMP"
+            | "RET"
+            | "JSR"
+            | "JSRR"
+            | "LD"
+            | "LDI"
+            | "LDR"
+            | "LEA"
+            | "ST"
+            | "STI"
+            | "STR"
+            | "RTI"
+            | "MUL"
+            | "DIV"
+            | "MOD"
+            | "SHL"
+            | "SHR"
+            | "SRA"
+            | "TRAP"
+            | "GETC"
+            | "OUT"
+            | "PUTS"
+            | "IN"
+            | "PUTSP"
+            | "HALT"
+            | ".ORIG"
+            | ".FILL"
+            | ".BLKW"
+            | ".STRINGZ"
+            | ".END"
+    ) || branch_flags(&token).is_some()
+}
+
+fn is_label(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/* the NZP bits of a BR mnemonic; plain BR branches always */
+fn branch_flags(mnemonic: &str) -> Option<u16> {
+    let flags = mnemonic.strip_prefix("BR")?;
+    if flags.is_empty() {
+        return Some(0x7);
+    }
+
+    let mut nzp = 0;
+    let mut rest = flags;
+    for (flag, bit) in [('N', 0x4), ('Z', 0x2), ('P', 0x1)] {
+        if let Some(stripped) = rest.strip_prefix(flag) {
+            nzp |= bit;
+            rest = stripped;
+        }
+    }
+    rest.is_empty().then_some(nzp)
+}
+
+/* words a line occupies */
+fn size(line: &Line) -> Result<usize, AsmError> {
+    let Some(mnemonic) = &line.mnemonic else {
+        return Ok(0);
+    };
+    Ok(match mnemonic.to_ascii_uppercase().as_str() {
+        ".BLKW" => number(line, operand(line, 0)?)? as u16 as usize,
+        ".STRINGZ" => string(line, operand(line, 0)?)?.len() + 1,
+        _ => 1,
+    })
+}
+
+fn operand(line: &Line, index: usize) -> Result<&str, AsmError> {
+    line.operands
+        .get(index)
+        .map(String::as_str)
+        .ok_or_else(|| operand_count(line, index + 1))
+}
+
+fn operand_count(line: &Line, expected: usize) -> AsmError {
+    AsmError::OperandCount {
+        line: line.number,
+        mnemonic: line.mnemonic.clone().unwrap_or_default(),
+        expected,
+    }
+}
+
+fn invalid(line: &Line, operand: &str) -> AsmError {
+    AsmError::InvalidOperand {
+        line: line.number,
+        operand: operand.to_string(),
+    }
+}
+
+fn number(line: &Line, token: &str) -> Result<i32, AsmError> {
+    let (negative, digits) = match token.strip_prefix('#').unwrap_or(token) {
+        digits if digits.starts_with('-') => (true, &digits[1..]),
+        digits => (false, digits),
+    };
+    let value = match digits.chars().next() {
+        Some('x' | 'X') => i32::from_str_radix(&digits[1..], 16),
+        Some('b' | 'B') => i32::from_str_radix(&digits[1..], 2),
+        _ => digits.parse(),
+    }
+    .map_err(|_| invalid(line, token))?;
+
+    let value = if negative { -value } else { value };
+    if !(i16::MIN as i32..=u16::MAX as i32).contains(&value) {
+        return Err(AsmError::OutOfRange {
+            line: line.number,
+            value,
+            bits: 16,
+        });
+    }
+    Ok(value)
+}
+
+/* a number operand where a label may also appear */
+fn literal(line: &Line, token: &str) -> Result<i32, AsmError> {
+    number(line, token).map_err(|err| match err {
+        AsmError::InvalidOperand { .. } if is_label(token) => AsmError::UndefinedLabel {
+            line: line.number,
+            label: token.to_string(),
+        },
+        err => err,
+    })
+}
+
+fn register(line: &Line, token: &str) -> Result<u16, AsmError> {
+    match token.as_bytes() {
+        [b'R' | b'r', digit @ b'0'..=b'7'] => Ok(u16::from(digit - b'0')),
+        _ => Err(invalid(line, token)),
+    }
+}
+
+fn string(line: &Line, token: &str) -> Result<Vec<u8>, AsmError> {
+    let inner = token
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .ok_or_else(|| invalid(line, token))?;
+
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '"')) => c,
+                _ => return Err(invalid(line, token)),
+            },
+            c => c,
+        };
+        if !c.is_ascii() {
+            return Err(invalid(line, token));
+        }
+        bytes.push(c as u8);
+    }
+    Ok(bytes)
+}
+
+/* a value that must fit in `bits` as a two's complement number */
+fn signed(line: &Line, value: i32, bits: u32) -> Result<u16, AsmError> {
+    let limit = 1 << (bits - 1);
+    if !(-limit..limit).contains(&value) {
+        return Err(AsmError::OutOfRange {
+            line: line.number,
+            value,
+            bits,
+        });
+    }
+    Ok(value as u16 & ((1 << bits) - 1))
+}
+
+/* a label or number operand for a PC-relative field of `bits` */
+fn pc_offset(
+    line: &Line,
+    token: &str,
+    address: u16,
+    symbols: &HashMap<String, u16>,
+    bits: u32,
+) -> Result<u16, AsmError> {
+    let offset = match symbols.get(token) {
+        Some(target) => i32::from(target.wrapping_sub(address.wrapping_add(1)) as i16),
+        None => literal(line, token)?,
+    };
+    signed(line, offset, bits)
+}
+
+fn encode(
+    line: &Line,
+    mnemonic: &str,
+    address: u16,
+    symbols: &HashMap<String, u16>,
+    words: &mut Vec<u16>,
+) -> Result<(), AsmError> {
+    let expect = |count: usize| {
+        if line.operands.len() == count {
+            Ok(())
+        } else {
+            Err(operand_count(line, count))
+        }
+    };
+    let reg = |index: usize| register(line, operand(line, index)?);
+    let op = |opcode: Opcodes| (opcode as u16) << 12;
+
+    let word = match mnemonic {
+        ".FILL" => {
+            expect(1)?;
+            let token = operand(line, 0)?;
+            match symbols.get(token) {
+                Some(value) => *value,
+                None => literal(line, token)? as u16,
+            }
+        }
+        ".BLKW" => {
+            expect(1)?;
+            let count = number(line, operand(line, 0)?)? as u16 as usize;
+            words.resize(words.len() + count, 0);
+            return Ok(());
+        }
+        ".STRINGZ" => {
+            expect(1)?;
+            words.extend(string(line, operand(line, 0)?)?.into_iter().map(u16::from));
+            words.push(0);
+            return Ok(());
+        }
+        "ADD" | "AND" => {
+            expect(3)?;
+            let opcode = if mnemonic == "ADD" {
+                Opcodes::ADD
+            } else {
+                Opcodes::AND
+            };
+            let base = op(opcode) | reg(0)? << 9 | reg(1)? << 6;
+            let last = operand(line, 2)?;
+            match register(line, last) {
+                Ok(sr2) => base | sr2,
+                Err(_) => base | 1 << 5 | signed(line, number(line, last)?, 5)?,
+            }
+        }
+        "NOT" => {
+            expect(2)?;
+            op(Opcodes::NOT) | reg(0)? << 9 | reg(1)? << 6 | 0x3F
+        }
+        "JMP" => {
+            expect(1)?;
+            op(Opcodes::JMP) | reg(0)? << 6
+        }
+        "RET" => {
+            expect(0)?;
+            op(Opcodes::JMP) | 7 << 6
+        }
+        "JSR" => {
+            expect(1)?;
+            op(Opcodes::JSR) | 1 << 11 | pc_offset(line, operand(line, 0)?, address, symbols, 11)?
+        }
+        "JSRR" => {
+            expect(1)?;
+            op(Opcodes::JSR) | reg(0)? << 6
+        }
+        "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+            expect(2)?;
+            let opcode = match mnemonic {
+                "LD" => Opcodes::LD,
+                "LDI" => Opcodes::LDI,
+                "LEA" => Opcodes::LEA,
+                "ST" => Opcodes::ST,
+                _ => Opcodes::STI,
+            };
+            op(opcode) | reg(0)? << 9 | pc_offset(line, operand(line, 1)?, address, symbols, 9)?
+        }
+        "LDR" | "STR" => {
+            expect(3)?;
+            let opcode = if mnemonic == "LDR" {
+                Opcodes::LDR
+            } else {
+                Opcodes::STR
+            };
+            op(opcode)
+                | reg(0)? << 9
+                | reg(1)? << 6
+                | signed(line, number(line, operand(line, 2)?)?, 6)?
+        }
+        "RTI" => {
+            expect(0)?;
+            op(Opcodes::RTI)
+        }
+        "MUL" | "DIV" | "MOD" | "SHL" | "SHR" | "SRA" => {
+            expect(3)?;
+            let operation = match mnemonic {
+                "MUL" => Arithmetic::MUL,
+                "DIV" => Arithmetic::DIV,
+                "MOD" => Arithmetic::MOD,
+                "SHL" => Arithmetic::SHL,
+                "SHR" => Arithmetic::SHR,
+                _ => Arithmetic::SRA,
+            };
+            op(Opcodes::RES) | reg(0)? << 9 | reg(1)? << 6 | (operation as u16) << 3 | reg(2)?
+        }
+        "TRAP" => {
+            expect(1)?;
+            let token = operand(line, 0)?;
+            let vector = number(line, token)?;
+            if !(0..=0xFF).contains(&vector) {
+                return Err(AsmError::OutOfRange {
+                    line: line.number,
+                    value: vector,
+                    bits: 8,
+                });
+            }
+            op(Opcodes::TRAP) | vector as u16
+        }
+        "GETC" | "OUT" | "PUTS" | "IN" | "PUTSP" | "HALT" => {
+            expect(0)?;
+            let vector = match mnemonic {
+                "GETC" => Trap::GETC,
+                "OUT" => Trap::OUT,
+                "PUTS" => Trap::PUTS,
+                "IN" => Trap::IN,
+                "PUTSP" => Trap::PUTSP,
+                _ => Trap::HALT,
+            };
+            op(Opcodes::TRAP) | vector as u16
+        }
+        _ => {
+            let nzp = branch_flags(mnemonic).ok_or_else(|| AsmError::UnknownMnemonic {
+                line: line.number,
+                mnemonic: mnemonic.to_string(),
+            })?;
+            expect(1)?;
+            op(Opcodes::BR) | nzp << 9 | pc_offset(line, operand(line, 0)?, address, symbols, 9)?
+        }
+    };
+
+    words.push(word);
+    Ok(())
+}
@@ -0,0 +1,407 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use thiserror::Error;
+use tracing::info;
+
+use crate::libs::{
+    console::Console,
+    constants::{DDR, DSR, KBDR, KBSR},
+    error::Fault,
+    types::Opcodes,
+};
+
+#[derive(Debug, Error)]
+pub enum BusError {
+    #[error("Bus Error: empty address range {start:#06x}-{end:#06x}")]
+    EmptyRange { start: u16, end: u16 },
+    #[error("Bus Error: {name} at {start:#06x}-{end:#06x} overlaps {other}")]
+    Overlap {
+        name: String,
+        start: u16,
+        end: u16,
+        other: String,
+    },
+}
+
+/* a request for service through the interrupt vector table */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupt {
+    pub vector: u16,
+    /* priority level 0-7; only interrupts above the running program's level are taken */
+    pub priority: u16,
+}
+
+/*
+a memory-mapped device. `read` and `write` receive the offset of the address
+from the start of the device's range. reads may have side effects, such as
+a keyboard clearing its ready bit; `peek` returns the same value without them
+*/
+pub trait Device {
+    fn name(&self) -> &str;
+    fn peek(&self, offset: u16) -> u16;
+    fn write(&mut self, offset: u16, value: u16);
+
+    fn read(&mut self, offset: u16) -> u16 {
+        self.peek(offset)
+    }
+
+    /* called once for every instruction the machine executes, with the cycles it took */
+    fn tick(&mut self, _cycles: u64) {}
+
+    /*
+    direct memory access, offered after every tick. `ram` bypasses the bus;
+    returns whether the device wrote to it
+    */
+    fn dma(&mut self, _ram: &mut [u16]) -> bool {
+        false
+    }
+
+    /* the device's interrupt line, asserted while it returns `Some` */
+    fn interrupt(&self) -> Option<Interrupt> {
+        None
+    }
+
+    /*
+    the device's registers and internal state as words, for snapshots.
+    stateless devices return `None`
+    */
+    fn save(&self) -> Option<Vec<u16>> {
+        None
+    }
+
+    /* take back the state `save` returned */
+    fn restore(&mut self, _state: &[u16]) {}
+}
+
+/*
+the cycles `instr` takes: one, plus one for every memory access beyond the
+fetch. TRAP reads the trap vector table and RTI pops two words
+*/
+pub fn cycles(instr: u16) -> u64 {
+    match Opcodes::from_u16(instr >> 12) {
+        Some(Opcodes::LD | Opcodes::ST | Opcodes::LDR | Opcodes::STR | Opcodes::TRAP) => 2,
+        Some(Opcodes::LDI | Opcodes::STI | Opcodes::RTI) => 3,
+        _ => 1,
+    }
+}
+
+struct Mapping {
+    start: u16,
+    end: u16,
+    device: Rc<RefCell<dyn Device>>,
+}
+
+/*
+devices attached to memory, each owning the addresses `start..=end`.
+addresses without a device are backed by plain RAM
+*/
+#[derive(Default)]
+pub struct Bus {
+    mappings: Vec<Mapping>,
+}
+
+impl fmt::Debug for Bus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.mappings.iter().map(|mapping| {
+                format!(
+                    "{} {:#06x}-{:#06x}",
+                    mapping.device.borrow().name(),
+                    mapping.start,
+                    mapping.end
+                )
+            }))
+            .finish()
+    }
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /*
+    map `device` at `start..=end`. the returned handle shares the device with
+    the bus, so its state can be inspected while the machine runs
+    */
+    pub fn attach<D: Device + 'static>(
+        &mut self,
+        start: u16,
+        end: u16,
+        device: D,
+    ) -> Result<Rc<RefCell<D>>, BusError> {
+        if end < start {
+            return Err(BusError::EmptyRange { start, end });
+        }
+        if let Some(other) = self
+            .mappings
+            .iter()
+            .find(|mapping| start <= mapping.end && mapping.start <= end)
+        {
+            return Err(BusError::Overlap {
+                name: device.name().to_string(),
+                start,
+                end,
+                other: other.device.borrow().name().to_string(),
+            });
+        }
+
+        info!("attaching {} at {start:#06x}-{end:#06x}", device.name());
+        let device = Rc::new(RefCell::new(device));
+        self.mappings.push(Mapping {
+            start,
+            end,
+            device: Rc::clone(&device) as Rc<RefCell<dyn Device>>,
+        });
+        Ok(device)
+    }
+
+    /* remove every device, leaving plain RAM */
+    pub fn detach_all(&mut self) {
+        self.mappings.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    pub fn is_mapped(&self, address: u16) -> bool {
+        self.mapping(address).is_some()
+    }
+
+    /*
+    the device's value at `address`, `None` if RAM backs it. a device that is
+    borrowed through its handle cannot answer, which faults the access
+    */
+    #[inline]
+    pub fn read(&self, address: u16) -> Option<Result<u16, Fault>> {
+        let mapping = self.mapping(address)?;
+        Some(match mapping.device.try_borrow_mut() {
+            Ok(mut device) => Ok(device.read(address - mapping.start)),
+            Err(_) => Err(Fault::Mmio(address)),
+        })
+    }
+
+    /* `read` without the device's side effects */
+    pub fn peek(&self, address: u16) -> Option<Result<u16, Fault>> {
+        let mapping = self.mapping(address)?;
+        Some(match mapping.device.try_borrow() {
+            Ok(device) => Ok(device.peek(address - mapping.start)),
+            Err(_) => Err(Fault::Mmio(address)),
+        })
+    }
+
+    /* write to the device at `address`, `None` if RAM backs it */
+    #[inline]
+    pub fn write(&self, address: u16, value: u16) -> Option<Result<(), Fault>> {
+        let mapping = self.mapping(address)?;
+        Some(match mapping.device.try_borrow_mut() {
+            Ok(mut device) => {
+                device.write(address - mapping.start, value);
+                Ok(())
+            }
+            Err(_) => Err(Fault::Mmio(address)),
+        })
+    }
+
+    pub fn tick(&self, cycles: u64) {
+        for mapping in &self.mappings {
+            mapping.device.borrow_mut().tick(cycles);
+        }
+    }
+
+    /* let every device access `ram`, returning whether any wrote to it */
+    pub fn dma(&self, ram: &mut [u16]) -> bool {
+        let mut written = false;
+        for mapping in &self.mappings {
+            written |= mapping.device.borrow_mut().dma(ram);
+        }
+        written
+    }
+
+    /* the highest-priority asserted interrupt above `priority`, if any */
+    pub fn pending_interrupt(&self, priority: u16) -> Option<Interrupt> {
+        self.mappings
+            .iter()
+            .filter_map(|mapping| mapping.device.borrow().interrupt())
+            .filter(|interrupt| interrupt.priority > priority)
+            .max_by_key(|interrupt| interrupt.priority)
+    }
+
+    /* the saved state of every device that has any, by device name, in mapping order */
+    pub fn save(&self) -> Vec<(String, Vec<u16>)> {
+        self.mappings
+            .iter()
+            .filter_map(|mapping| {
+                let device = mapping.device.borrow();
+                Some((device.name().to_string(), device.save()?))
+            })
+            .collect()
+    }
+
+    /*
+    restore `states` from `save`, each into the next attached device of the
+    same name. states without a device are dropped
+    */
+    pub fn restore(&self, states: &[(String, Vec<u16>)]) {
+        let mut states: Vec<_> = states.iter().collect();
+        for mapping in &self.mappings {
+            let mut device = mapping.device.borrow_mut();
+            if let Some(index) = states.iter().position(|(name, _)| name == device.name()) {
+                device.restore(&states.remove(index).1);
+            }
+        }
+        for (name, _) in states {
+            info!("no {name} attached, dropping its saved state");
+        }
+    }
+
+    fn mapping(&self, address: u16) -> Option<&Mapping> {
+        self.mappings
+            .iter()
+            .find(|mapping| (mapping.start..=mapping.end).contains(&address))
+    }
+}
+
+/* KBSR bit 15: a character is waiting in KBDR */
+const READY: u16 = 1 << 15;
+/* KBSR bit 14: raise an interrupt while a character is waiting */
+const INTERRUPT_ENABLE: u16 = 1 << 14;
+
+/* the keyboard interrupt, at priority level 4 */
+pub const KEYBOARD_INTERRUPT: Interrupt = Interrupt {
+    vector: 0x80,
+    priority: 4,
+};
+
+/*
+the keyboard status and data registers, KBSR and KBDR, fed from a console.
+polling KBSR asks the console for input; reading KBDR takes the character
+and clears the ready bit. map it at `KBSR..=KBDR + 1`
+*/
+pub struct Keyboard<C: Console> {
+    console: C,
+    status: u16,
+    data: u16,
+}
+
+impl<C: Console> Keyboard<C> {
+    pub fn new(console: C) -> Self {
+        Self {
+            console,
+            status: 0,
+            data: 0,
+        }
+    }
+
+    fn poll(&mut self) {
+        if self.status & READY == 0
+            && let Some(byte) = self.console.poll_byte()
+        {
+            self.data = u16::from(byte);
+            self.status |= READY;
+        }
+    }
+}
+
+impl<C: Console> Device for Keyboard<C> {
+    fn name(&self) -> &str {
+        "keyboard"
+    }
+
+    /* KBSR at offset 0, KBDR at offset 2 */
+    fn peek(&self, offset: u16) -> u16 {
+        match offset {
+            0 => self.status,
+            2 => self.data,
+            _ => 0,
+        }
+    }
+
+    /* polling KBSR asks the console for input; taking KBDR clears the ready bit */
+    fn read(&mut self, offset: u16) -> u16 {
+        if offset == 0 {
+            self.poll();
+        }
+        let value = self.peek(offset);
+        if offset == 2 {
+            self.status &= !READY;
+        }
+        value
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        /* only the interrupt enable bit is writable */
+        if offset == 0 {
+            self.status = (self.status & READY) | (value & INTERRUPT_ENABLE);
+        }
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        /* with interrupts enabled the program does not poll, so the device does */
+        if self.status & INTERRUPT_ENABLE != 0 {
+            self.poll();
+        }
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        let asserted = READY | INTERRUPT_ENABLE;
+        (self.status & asserted == asserted).then_some(KEYBOARD_INTERRUPT)
+    }
+
+    fn save(&self) -> Option<Vec<u16>> {
+        Some(vec![self.status, self.data])
+    }
+
+    fn restore(&mut self, state: &[u16]) {
+        if let [status, data] = *state {
+            self.status = status;
+            self.data = data;
+        }
+    }
+}
+
+/*
+the display status and data registers, DSR and DDR, writing to a console.
+the display is always ready; map it at `DSR..=DDR + 1`
+*/
+pub struct Display<C: Console> {
+    console: C,
+}
+
+impl<C: Console> Display<C> {
+    pub fn new(console: C) -> Self {
+        Self { console }
+    }
+}
+
+impl<C: Console> Device for Display<C> {
+    fn name(&self) -> &str {
+        "display"
+    }
+
+    /* DSR at offset 0, DDR at offset 2 */
+    fn peek(&self, offset: u16) -> u16 {
+        match offset {
+            0 => READY,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        if offset == 2 {
+            self.console.write_byte(value as u8);
+            self.console.flush();
+        }
+    }
+}
+
+/* attach a keyboard and display sharing `console` at their standard addresses */
+pub fn attach_console_devices<C: Console + Clone + 'static>(
+    bus: &mut Bus,
+    console: C,
+) -> Result<(), BusError> {
+    bus.attach(KBSR, KBDR + 1, Keyboard::new(console.clone()))?;
+    bus.attach(DSR, DDR + 1, Display::new(console))?;
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    io::{self, Read, Write},
+    rc::Rc,
+    sync::{
+        Mutex, OnceLock,
+        mpsc::{self, Receiver},
+    },
+    thread,
+};
+
+/* buffered console contents, as captured in a snapshot */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsoleState {
+    pub input: Vec<u8>,
+    pub output: Vec<u8>,
+}
+
+/* character I/O used by the trap routines */
+pub trait Console {
+    /* next input byte, `None` when no input is available */
+    fn read_byte(&mut self) -> Option<u8>;
+    /*
+    next input byte for a device polling for it. unlike a `read_byte` that
+    comes up empty, a poll that finds nothing is not an input event
+    */
+    fn poll_byte(&mut self) -> Option<u8> {
+        self.read_byte()
+    }
+    fn write_byte(&mut self, byte: u8);
+    fn flush(&mut self) {}
+
+    /* instructions completed before the one about to use the console */
+    fn set_instruction_count(&mut self, _count: u64) {}
+
+    /* pending input and produced output, for consoles that keep them */
+    fn state(&self) -> Option<ConsoleState> {
+        None
+    }
+    fn restore(&mut self, _state: ConsoleState) {}
+
+    fn write_str(&mut self, text: &str) {
+        text.bytes().for_each(|byte| self.write_byte(byte));
+    }
+}
+
+/*
+stdin, read a byte at a time by a thread of its own so that devices can poll
+it without blocking. started on first use and shared by every `StdConsole`
+*/
+fn stdin_bytes() -> &'static Mutex<Receiver<u8>> {
+    static INPUT: OnceLock<Mutex<Receiver<u8>>> = OnceLock::new();
+
+    INPUT.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 1];
+            while io::stdin().read_exact(&mut buffer).is_ok() {
+                if sender.send(buffer[0]).is_err() {
+                    break;
+                }
+            }
+        });
+        Mutex::new(receiver)
+    })
+}
+
+/* the host terminal */
+#[derive(Debug, Default)]
+pub struct StdConsole;
+
+impl Console for StdConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        stdin_bytes().lock().ok()?.recv().ok()
+    }
+
+    fn poll_byte(&mut self) -> Option<u8> {
+        stdin_bytes().lock().ok()?.try_recv().ok()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        let _ = io::stdout().write_all(&[byte]);
+    }
+
+    fn flush(&mut self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buffers {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+/*
+in-memory console for tests and embedding. clones share the same buffers,
+so a handle kept by the caller sees what the machine wrote
+*/
+#[derive(Debug, Clone, Default)]
+pub struct BufferConsole(Rc<RefCell<Buffers>>);
+
+impl BufferConsole {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_input(input: &[u8]) -> Self {
+        let console = Self::new();
+        console.push_input(input);
+        console
+    }
+
+    pub fn push_input(&self, input: &[u8]) {
+        self.0.borrow_mut().input.extend(input);
+    }
+
+    pub fn output(&self) -> Vec<u8> {
+        self.0.borrow().output.clone()
+    }
+
+    pub fn take_output(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.borrow_mut().output)
+    }
+}
+
+impl Console for BufferConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.0.borrow_mut().input.pop_front()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.0.borrow_mut().output.push(byte);
+    }
+
+    fn state(&self) -> Option<ConsoleState> {
+        let buffers = self.0.borrow();
+        Some(ConsoleState {
+            input: buffers.input.iter().copied().collect(),
+            output: buffers.output.clone(),
+        })
+    }
+
+    fn restore(&mut self, state: ConsoleState) {
+        let mut buffers = self.0.borrow_mut();
+        buffers.input = state.input.into();
+        buffers.output = state.output;
+    }
+}
+
+/* counts the bytes written through a console, for output limits */
+pub struct CountingConsole<C: Console> {
+    inner: C,
+    written: Rc<Cell<u64>>,
+}
+
+impl<C: Console> CountingConsole<C> {
+    pub fn new(inner: C, written: Rc<Cell<u64>>) -> Self {
+        Self { inner, written }
+    }
+}
+
+impl<C: Console> Console for CountingConsole<C> {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.inner.read_byte()
+    }
+
+    fn poll_byte(&mut self) -> Option<u8> {
+        self.inner.poll_byte()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.written.set(self.written.get() + 1);
+        self.inner.write_byte(byte);
+    }
+
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+
+    fn set_instruction_count(&mut self, count: u64) {
+        self.inner.set_instruction_count(count);
+    }
+
+    fn state(&self) -> Option<ConsoleState> {
+        self.inner.state()
+    }
+
+    fn restore(&mut self, state: ConsoleState) {
+        self.inner.restore(state);
+    }
+}
+
+/*
+a console shared between the machine and its devices. clones forward to the
+same console
+*/
+#[derive(Clone)]
+pub struct SharedConsole(Rc<RefCell<Box<dyn Console>>>);
+
+impl SharedConsole {
+    pub fn new(console: Box<dyn Console>) -> Self {
+        Self(Rc::new(RefCell::new(console)))
+    }
+}
+
+impl Console for SharedConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.0.borrow_mut().read_byte()
+    }
+
+    fn poll_byte(&mut self) -> Option<u8> {
+        self.0.borrow_mut().poll_byte()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.0.borrow_mut().write_byte(byte);
+    }
+
+    fn flush(&mut self) {
+        self.0.borrow_mut().flush();
+    }
+
+    fn set_instruction_count(&mut self, count: u64) {
+        self.0.borrow_mut().set_instruction_count(count);
+    }
+
+    fn state(&self) -> Option<ConsoleState> {
+        self.0.borrow().state()
+    }
+
+    fn restore(&mut self, state: ConsoleState) {
+        self.0.borrow_mut().restore(state);
+    }
+}
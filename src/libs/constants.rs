@@ -1,2 +1,32 @@
 pub const MEMORY_MAX: usize = 1 << 16;
 pub const PC_START: u16 = 0x3000;
+/* trap service routine addresses, indexed by trap vector */
+pub const TRAP_VECTOR_TABLE: u16 = 0x0000;
+/* exception and interrupt service routine addresses, indexed by vector */
+pub const INTERRUPT_VECTOR_TABLE: u16 = 0x0100;
+/* the LC-3b's interrupt vector table, of word entries two bytes apart */
+pub const LC3B_INTERRUPT_VECTOR_TABLE: u16 = 0x0200;
+/* initial supervisor stack pointer; the stack grows down into system space */
+pub const SUPERVISOR_STACK_START: u16 = 0x3000;
+/* keyboard and display device registers */
+pub const KBSR: u16 = 0xFE00;
+pub const KBDR: u16 = 0xFE02;
+pub const DSR: u16 = 0xFE04;
+pub const DDR: u16 = 0xFE06;
+/* interval timer control, reload and counter registers */
+pub const TMCR: u16 = 0xFE08;
+pub const TMRR: u16 = 0xFE0A;
+pub const TMCNT: u16 = 0xFE0C;
+/* video memory, one 15-bit RGB word per pixel */
+pub const FRAMEBUFFER: u16 = 0xC000;
+pub const FRAMEBUFFER_WIDTH: usize = 128;
+pub const FRAMEBUFFER_HEIGHT: usize = 124;
+/* disk control, status, sector, DMA address and data registers */
+pub const DKCR: u16 = 0xFE10;
+pub const DKSR: u16 = 0xFE12;
+pub const DKSEC: u16 = 0xFE14;
+pub const DKADR: u16 = 0xFE16;
+pub const DKDATA: u16 = 0xFE18;
+/* machine control register; the machine runs while its clock-enable bit is set */
+pub const MCR: u16 = 0xFFFE;
+pub const CLOCK_ENABLE: u16 = 1 << 15;
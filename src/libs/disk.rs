@@ -0,0 +1,316 @@
+use std::{
+    cell::RefCell,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use tracing::info;
+
+use crate::libs::{
+    bus::{Bus, BusError, Device, Interrupt},
+    constants::{DKCR, DKDATA},
+};
+
+pub const SECTOR_WORDS: usize = 256;
+const SECTOR_BYTES: u64 = SECTOR_WORDS as u64 * 2;
+
+/* DKCR bits 1-0: the command to start */
+pub const READ: u16 = 1;
+pub const WRITE: u16 = 2;
+/* DKCR bit 2: transfer between the sector and memory at DKADR instead of the buffer */
+pub const DMA: u16 = 1 << 2;
+/* DKCR bits 10-8: priority level of the completion interrupt */
+pub const PRIORITY_SHIFT: u16 = 8;
+/* DKCR bit 14: interrupt on completion */
+pub const INTERRUPT_ENABLE: u16 = 1 << 14;
+
+/* DKSR bit 15: no command is running */
+pub const READY: u16 = 1 << 15;
+/* DKSR bit 14: a command completed; cleared by reading DKSR */
+pub const DONE: u16 = 1 << 14;
+/* DKSR bit 0: the last command failed on the host */
+pub const ERROR: u16 = 1;
+
+pub const DISK_INTERRUPT_VECTOR: u16 = 0x82;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Read,
+    Write,
+}
+
+/*
+a block device over a host image file of 256-word sectors, stored as
+big-endian words. sectors past the end of the file read as zeros, and
+writing them grows the file.
+
+a program selects the sector in DKSEC and starts a command through DKCR.
+without DMA, READ fills the sector buffer and WRITE stores it; the buffer is
+accessed word by word through DKDATA, starting over with every command. with
+DMA the sector is transferred to or from memory at DKADR instead. commands
+complete `latency` instructions after the one issuing them, setting READY
+and DONE and raising an interrupt if enabled to
+*/
+#[derive(Debug)]
+pub struct Disk {
+    file: File,
+    latency: u64,
+    control: u16,
+    status: u16,
+    sector: u16,
+    address: u16,
+    buffer: [u16; SECTOR_WORDS],
+    index: usize,
+    pending: Option<(Command, u64)>,
+}
+
+impl Disk {
+    /* open an existing image for reading and writing */
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        info!("opening disk image {}", path.display());
+
+        Ok(Self {
+            file: OpenOptions::new().read(true).write(true).open(path)?,
+            latency: 0,
+            control: 0,
+            status: READY,
+            sector: 0,
+            address: 0,
+            buffer: [0; SECTOR_WORDS],
+            index: 0,
+            pending: None,
+        })
+    }
+
+    /* instructions between issuing a command and its completion */
+    pub fn set_latency(&mut self, instructions: u64) {
+        self.latency = instructions;
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn priority(&self) -> u16 {
+        (self.control >> PRIORITY_SHIFT) & 0x7
+    }
+
+    fn start(&mut self, control: u16) {
+        if self.pending.is_some() {
+            return;
+        }
+
+        self.control = control;
+        let command = match control & 0x3 {
+            READ => Command::Read,
+            WRITE => Command::Write,
+            _ => return,
+        };
+        self.status = 0;
+        self.index = 0;
+        /* the tick for the issuing instruction comes first */
+        self.pending = Some((command, self.latency + 1));
+    }
+
+    /* run the pending command, returning whether memory was written */
+    fn complete(&mut self, command: Command, ram: &mut [u16]) -> bool {
+        let dma = self.control & DMA != 0;
+        let address = self.address as usize;
+        let mut written = false;
+
+        let result = match command {
+            Command::Read => {
+                let result = self.read_sector();
+                if result.is_ok() && dma {
+                    for (offset, word) in self.buffer.iter().enumerate() {
+                        ram[(address + offset) % ram.len()] = *word;
+                    }
+                    written = true;
+                }
+                result
+            }
+            Command::Write => {
+                if dma {
+                    for (offset, word) in self.buffer.iter_mut().enumerate() {
+                        *word = ram[(address + offset) % ram.len()];
+                    }
+                }
+                self.write_sector()
+            }
+        };
+
+        self.status = READY | DONE;
+        if let Err(err) = result {
+            info!("disk {command:?} of sector {} failed: {err}", self.sector);
+            self.status |= ERROR;
+        }
+        written
+    }
+
+    fn read_sector(&mut self) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(SECTOR_BYTES as usize);
+        self.file
+            .seek(SeekFrom::Start(u64::from(self.sector) * SECTOR_BYTES))?;
+        (&mut self.file)
+            .take(SECTOR_BYTES)
+            .read_to_end(&mut bytes)?;
+        bytes.resize(SECTOR_BYTES as usize, 0);
+
+        for (word, pair) in self.buffer.iter_mut().zip(bytes.chunks_exact(2)) {
+            *word = u16::from_be_bytes([pair[0], pair[1]]);
+        }
+        Ok(())
+    }
+
+    fn write_sector(&mut self) -> io::Result<()> {
+        let bytes: Vec<u8> = self
+            .buffer
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect();
+        self.file
+            .seek(SeekFrom::Start(u64::from(self.sector) * SECTOR_BYTES))?;
+        self.file.write_all(&bytes)?;
+        self.file.flush()
+    }
+}
+
+impl Device for Disk {
+    fn name(&self) -> &str {
+        "disk"
+    }
+
+    /* DKCR, DKSR, DKSEC, DKADR and DKDATA at offsets 0, 2, 4, 6 and 8 */
+    fn peek(&self, offset: u16) -> u16 {
+        match offset {
+            0 => self.control,
+            2 => self.status,
+            4 => self.sector,
+            6 => self.address,
+            8 => self.buffer[self.index],
+            _ => 0,
+        }
+    }
+
+    /* reading DKSR acknowledges a finished transfer; DKDATA advances through the buffer */
+    fn read(&mut self, offset: u16) -> u16 {
+        let value = self.peek(offset);
+        match offset {
+            2 => self.status &= !DONE,
+            8 => self.index = (self.index + 1) % SECTOR_WORDS,
+            _ => {}
+        }
+        value
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        match offset {
+            0 => self.start(value),
+            4 => self.sector = value,
+            6 => self.address = value,
+            8 => {
+                self.buffer[self.index] = value;
+                self.index = (self.index + 1) % SECTOR_WORDS;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        if let Some((_, remaining)) = &mut self.pending {
+            *remaining = remaining.saturating_sub(1);
+        }
+    }
+
+    fn dma(&mut self, ram: &mut [u16]) -> bool {
+        match self.pending {
+            Some((command, 0)) => {
+                self.pending = None;
+                self.complete(command, ram)
+            }
+            _ => false,
+        }
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        (self.status & DONE != 0 && self.control & INTERRUPT_ENABLE != 0).then(|| Interrupt {
+            vector: DISK_INTERRUPT_VECTOR,
+            priority: self.priority(),
+        })
+    }
+
+    /*
+    the registers, the buffer position, the pending command (0 for none, or
+    the command bits) with its remaining instructions as four words, and the
+    buffer. the image file itself is the host's to keep
+    */
+    fn save(&self) -> Option<Vec<u16>> {
+        let (command, remaining) = match self.pending {
+            Some((Command::Read, remaining)) => (READ, remaining),
+            Some((Command::Write, remaining)) => (WRITE, remaining),
+            None => (0, 0),
+        };
+
+        let mut state = vec![
+            self.control,
+            self.status,
+            self.sector,
+            self.address,
+            self.index as u16,
+            command,
+        ];
+        state.extend(
+            remaining
+                .to_be_bytes()
+                .chunks(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]])),
+        );
+        state.extend(self.buffer);
+        Some(state)
+    }
+
+    fn restore(&mut self, state: &[u16]) {
+        let [
+            control,
+            status,
+            sector,
+            address,
+            index,
+            command,
+            r0,
+            r1,
+            r2,
+            r3,
+            ref buffer @ ..,
+        ] = *state
+        else {
+            return;
+        };
+        if buffer.len() != SECTOR_WORDS {
+            return;
+        }
+
+        self.control = control;
+        self.status = status;
+        self.sector = sector;
+        self.address = address;
+        self.index = index as usize % SECTOR_WORDS;
+        let remaining = [r0, r1, r2, r3]
+            .iter()
+            .fold(0u64, |value, word| value << 16 | u64::from(*word));
+        self.pending = match command {
+            READ => Some((Command::Read, remaining)),
+            WRITE => Some((Command::Write, remaining)),
+            _ => None,
+        };
+        self.buffer.copy_from_slice(buffer);
+    }
+}
+
+/* attach `disk` at the standard disk registers */
+pub fn attach_disk(bus: &mut Bus, disk: Disk) -> Result<Rc<RefCell<Disk>>, BusError> {
+    bus.attach(DKCR, DKDATA + 1, disk)
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::libs::{
+    instructions::InstructionSetError,
+    protection::{AccessKind, Violation},
+    types::{Opcodes, RegisterError},
+};
+
+/* why the machine stopped executing */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum Fault {
+    #[error("invalid register {0}")]
+    InvalidRegister(u16),
+    #[error("illegal opcode")]
+    IllegalOpcode,
+    #[error("unknown trap vector {0:#04x}")]
+    UnknownTrap(u16),
+    #[error("trap handler for vector {0:#04x} failed")]
+    TrapFailed(u16),
+    #[error("division by zero")]
+    DivideByZero,
+    #[error("invalid sign-extension width {0}")]
+    InvalidBitCount(u32),
+    #[error("memory-mapped I/O fault at {0:#06x}")]
+    Mmio(u16),
+    #[error("access violation: {kind} of {address:#06x}")]
+    AccessViolation { address: u16, kind: AccessKind },
+    #[error("step limit of {0} instructions reached")]
+    StepLimit(u64),
+}
+
+impl From<InstructionSetError> for Fault {
+    fn from(err: InstructionSetError) -> Self {
+        match err {
+            InstructionSetError::InvalidBitCount(count) => Fault::InvalidBitCount(count),
+            InstructionSetError::RegisterError(RegisterError::InvalidRegister(register)) => {
+                Fault::InvalidRegister(register)
+            }
+            InstructionSetError::BadOpcode(_) => Fault::IllegalOpcode,
+            InstructionSetError::UnknownTrap(vector) => Fault::UnknownTrap(vector),
+            InstructionSetError::DivideByZero(_) => Fault::DivideByZero,
+        }
+    }
+}
+
+impl From<Violation> for Fault {
+    fn from(violation: Violation) -> Self {
+        Fault::AccessViolation {
+            address: violation.address,
+            kind: violation.kind,
+        }
+    }
+}
+
+/*
+an execution error of the machine, with the instruction it happened at.
+`pc` is the address the instruction was fetched from
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub struct VmError {
+    pub pc: u16,
+    pub instr: u16,
+    pub opcode: Option<Opcodes>,
+    pub cause: Fault,
+}
+
+impl VmError {
+    pub fn new(pc: u16, instr: u16, cause: impl Into<Fault>) -> Self {
+        Self {
+            pc,
+            instr,
+            opcode: Opcodes::from_u16(instr >> 12),
+            cause: cause.into(),
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "VM Error: {} at pc {:#06x} (instruction {:#06x}",
+            self.cause, self.pc, self.instr
+        )?;
+        if let Some(opcode) = self.opcode {
+            write!(f, ", {opcode:?}")?;
+        }
+        f.write_str(")")
+    }
+}
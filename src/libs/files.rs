@@ -0,0 +1,302 @@
+use std::{
+    cell::RefCell,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use tracing::info;
+
+use crate::libs::{
+    error::Fault,
+    protection::{AccessKind, Violation},
+    trap::TrapContext,
+    types::Registers,
+    vm::Vm,
+};
+
+/*
+trap vectors of the file routines. arguments are passed in R0-R2 and the
+result is left in R0 with the condition codes set from it: a handle, count
+or zero on success, a negative `FileError` code on failure.
+
+OPEN   R0 = address of the path, one character per word; R1 = mode
+CLOSE  R0 = handle
+READB  R0 = handle, R1 = buffer, R2 = count; one byte per word
+WRITEB R0 = handle, R1 = buffer, R2 = count; the low byte of each word
+READW  R0 = handle, R1 = buffer, R2 = count; big-endian words
+WRITEW R0 = handle, R1 = buffer, R2 = count; big-endian words
+SEEK   R0 = handle, R1 = signed byte offset, R2 = 0 from the start, 1 from the
+       current position, 2 from the end
+
+counts are at most `MAX_TRANSFER`, so a transferred count is never negative
+*/
+pub const OPEN: u8 = 0x30;
+pub const CLOSE: u8 = 0x31;
+pub const READB: u8 = 0x32;
+pub const WRITEB: u8 = 0x33;
+pub const READW: u8 = 0x34;
+pub const WRITEW: u8 = 0x35;
+pub const SEEK: u8 = 0x36;
+
+/* OPEN modes */
+pub const MODE_READ: u16 = 0;
+/* create or truncate */
+pub const MODE_WRITE: u16 = 1;
+/* create, writing at the end */
+pub const MODE_APPEND: u16 = 2;
+/* an existing file */
+pub const MODE_READ_WRITE: u16 = 3;
+
+/* files a program may have open at once */
+pub const MAX_OPEN_FILES: usize = 16;
+/* longest path OPEN accepts, in characters */
+pub const MAX_PATH: usize = 255;
+/* most words a single read or write transfers */
+pub const MAX_TRANSFER: u16 = 0x7FFF;
+
+/* failures reported to the program, as negative codes in R0 */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileError {
+    NotFound = -1,
+    /* outside the sandbox, refused by the host, or a buffer the program may not access */
+    Denied = -2,
+    BadHandle = -3,
+    /* a malformed path, mode, seek origin or count */
+    Invalid = -4,
+    TooManyFiles = -5,
+    Io = -6,
+}
+
+impl FileError {
+    pub fn code(self) -> u16 {
+        self as i16 as u16
+    }
+}
+
+impl From<Violation> for FileError {
+    fn from(_: Violation) -> Self {
+        FileError::Denied
+    }
+}
+
+impl From<io::Error> for FileError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => FileError::NotFound,
+            io::ErrorKind::PermissionDenied => FileError::Denied,
+            io::ErrorKind::InvalidInput => FileError::Invalid,
+            _ => FileError::Io,
+        }
+    }
+}
+
+/*
+file access for LC-3 programs, confined to the files below `root`. paths
+are relative to it; absolute paths, `..` and symlinks leading out of it are
+denied
+*/
+#[derive(Debug)]
+pub struct HostFiles {
+    root: PathBuf,
+    files: Vec<Option<File>>,
+}
+
+impl HostFiles {
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            root: fs::canonicalize(root)?,
+            files: Vec::new(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /* register the file routines on `vm`, sharing this file table */
+    pub fn install(self, vm: &mut Vm) {
+        info!("serving files from {}", self.root.display());
+        let files = Rc::new(RefCell::new(self));
+
+        let routines: [(u8, Routine); 7] = [
+            (OPEN, HostFiles::open),
+            (CLOSE, HostFiles::close),
+            (READB, |files, context| files.read(context, false)),
+            (WRITEB, |files, context| files.write(context, false)),
+            (READW, |files, context| files.read(context, true)),
+            (WRITEW, |files, context| files.write(context, true)),
+            (SEEK, HostFiles::seek),
+        ];
+        for (vector, routine) in routines {
+            let files = Rc::clone(&files);
+            vm.register_trap(vector, move |context: &mut TrapContext| {
+                let result = routine(&mut files.borrow_mut(), context);
+                context.set_register(Registers::R0, result.unwrap_or_else(FileError::code));
+                context.update_flags(Registers::R0);
+                Ok::<(), Fault>(())
+            });
+        }
+    }
+
+    /* the host path of `path`, if it stays inside the sandbox */
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, FileError> {
+        let relative = Path::new(path);
+        if path.is_empty() {
+            return Err(FileError::Invalid);
+        }
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(FileError::Denied);
+        }
+
+        /* symlinks are followed, so check where the path really leads */
+        let full = self.root.join(relative);
+        let parent = full.parent().ok_or(FileError::Invalid)?;
+        if !fs::canonicalize(parent)?.starts_with(&self.root) {
+            return Err(FileError::Denied);
+        }
+
+        /*
+        a path that does not exist yet may be created, but anything that does
+        (a dangling symlink included) has to resolve inside the root
+        */
+        match fs::symlink_metadata(&full) {
+            Ok(_) => match fs::canonicalize(&full) {
+                Ok(target) if target.starts_with(&self.root) => Ok(full),
+                _ => Err(FileError::Denied),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(full),
+            Err(_) => Err(FileError::Denied),
+        }
+    }
+
+    fn open(&mut self, context: &mut TrapContext) -> Result<u16, FileError> {
+        let path = read_path(context)?;
+        let mut options = OpenOptions::new();
+        match context.register(Registers::R1) {
+            MODE_READ => options.read(true),
+            MODE_WRITE => options.write(true).create(true).truncate(true),
+            MODE_APPEND => options.append(true).create(true),
+            MODE_READ_WRITE => options.read(true).write(true),
+            _ => return Err(FileError::Invalid),
+        };
+
+        let slot = match self.files.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None if self.files.len() < MAX_OPEN_FILES => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+            None => return Err(FileError::TooManyFiles),
+        };
+
+        let file = options.open(self.resolve(&path)?)?;
+        info!("opened {path} as handle {slot}");
+        self.files[slot] = Some(file);
+        Ok(slot as u16)
+    }
+
+    fn close(&mut self, context: &mut TrapContext) -> Result<u16, FileError> {
+        let handle = context.register(Registers::R0) as usize;
+        match self.files.get_mut(handle).and_then(Option::take) {
+            Some(_) => Ok(0),
+            None => Err(FileError::BadHandle),
+        }
+    }
+
+    fn file(&mut self, context: &TrapContext) -> Result<&mut File, FileError> {
+        let handle = context.register(Registers::R0) as usize;
+        self.files
+            .get_mut(handle)
+            .and_then(Option::as_mut)
+            .ok_or(FileError::BadHandle)
+    }
+
+    fn read(&mut self, context: &mut TrapContext, words: bool) -> Result<u16, FileError> {
+        let buffer = context.register(Registers::R1);
+        let count = transfer_count(context)? as usize;
+        let width = if words { 2 } else { 1 };
+
+        /* refuse the whole read before consuming anything from the file */
+        for offset in 0..count as u16 {
+            context.check(buffer.wrapping_add(offset), AccessKind::Write)?;
+        }
+
+        let mut bytes = Vec::with_capacity(count * width);
+        self.file(context)?
+            .take((count * width) as u64)
+            .read_to_end(&mut bytes)?;
+
+        /* a final odd byte of a word read is its high byte */
+        let values: Vec<u16> = if words {
+            bytes
+                .chunks(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]))
+                .collect()
+        } else {
+            bytes.into_iter().map(u16::from).collect()
+        };
+        for (offset, value) in values.iter().enumerate() {
+            context.write(buffer.wrapping_add(offset as u16), *value)?;
+        }
+        Ok(values.len() as u16)
+    }
+
+    fn write(&mut self, context: &mut TrapContext, words: bool) -> Result<u16, FileError> {
+        let buffer = context.register(Registers::R1);
+        let count = transfer_count(context)?;
+
+        let mut bytes = Vec::new();
+        for offset in 0..count {
+            let value = context.read(buffer.wrapping_add(offset))?;
+            if words {
+                bytes.extend(value.to_be_bytes());
+            } else {
+                bytes.push(value as u8);
+            }
+        }
+        self.file(context)?.write_all(&bytes)?;
+        Ok(count)
+    }
+
+    fn seek(&mut self, context: &mut TrapContext) -> Result<u16, FileError> {
+        let offset = context.register(Registers::R1) as i16;
+        let position = match context.register(Registers::R2) {
+            0 if offset >= 0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset.into()),
+            2 => SeekFrom::End(offset.into()),
+            _ => return Err(FileError::Invalid),
+        };
+        self.file(context)?.seek(position)?;
+        Ok(0)
+    }
+}
+
+/* the count in R2, refused if the result could be mistaken for an error code */
+fn transfer_count(context: &TrapContext) -> Result<u16, FileError> {
+    match context.register(Registers::R2) {
+        count if count > MAX_TRANSFER => Err(FileError::Invalid),
+        count => Ok(count),
+    }
+}
+
+type Routine = fn(&mut HostFiles, &mut TrapContext) -> Result<u16, FileError>;
+
+/* the zero-terminated path at R0 */
+fn read_path(context: &mut TrapContext) -> Result<String, FileError> {
+    let start = context.register(Registers::R0);
+    let mut path = String::new();
+
+    for offset in 0..=MAX_PATH as u16 {
+        match context.read(start.wrapping_add(offset))? {
+            0 => return Ok(path),
+            value if value < 0x80 => path.push(value as u8 as char),
+            _ => return Err(FileError::Invalid),
+        }
+    }
+    Err(FileError::Invalid)
+}
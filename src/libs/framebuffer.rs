@@ -0,0 +1,241 @@
+use std::{cell::RefCell, fmt::Write as _, fs, io, path::Path, rc::Rc};
+
+use thiserror::Error;
+use tracing::info;
+
+use crate::libs::{
+    bus::{Bus, BusError, Device},
+    constants::{FRAMEBUFFER, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_WIDTH},
+};
+
+#[derive(Debug, Error)]
+pub enum FramebufferError {
+    #[error("Framebuffer Error: unknown image format {0:?}, expected .png or .ppm")]
+    UnknownFormat(String),
+    #[error("Framebuffer Error: {0}")]
+    Io(#[from] io::Error),
+}
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/* deflate stored blocks hold at most this many bytes */
+const STORED_BLOCK_MAX: usize = 0xFFFF;
+
+/*
+video memory: one word per pixel, rows left to right and top to bottom,
+with 15-bit RGB in each word (bits 14-10 red, 9-5 green, 4-0 blue). the
+pixels can be exported as PPM or PNG, or drawn on a terminal
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u16>,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new(FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT)
+    }
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: u16) {
+        self.pixels[y * self.width + x] = value;
+    }
+
+    /* 8-bit red, green and blue of the pixel at `x`, `y` */
+    pub fn rgb(&self, x: usize, y: usize) -> [u8; 3] {
+        let value = self.pixel(x, y);
+        let channel = |shift: u16| {
+            let bits = ((value >> shift) & 0x1F) as u8;
+            bits << 3 | bits >> 2
+        };
+        [channel(10), channel(5), channel(0)]
+    }
+
+    /* binary PPM (P6) */
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                out.extend(self.rgb(x, y));
+            }
+        }
+        out
+    }
+
+    /* 8-bit RGB PNG, stored without compression */
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.height * (1 + self.width * 3));
+        for y in 0..self.height {
+            /* filter type: none */
+            raw.push(0);
+            for x in 0..self.width {
+                raw.extend(self.rgb(x, y));
+            }
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        /* bit depth 8, colour type RGB, deflate, adaptive filtering, no interlace */
+        header.extend([8, 2, 0, 0, 0]);
+
+        let mut out = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut out, b"IHDR", &header);
+        write_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    /*
+    the pixels as lines of upper half blocks with 24-bit ANSI colours, the
+    foreground drawing one row and the background the row below it
+    */
+    pub fn to_terminal(&self) -> String {
+        let mut out = String::new();
+
+        for y in (0..self.height).step_by(2) {
+            for x in 0..self.width {
+                let [r, g, b] = self.rgb(x, y);
+                let _ = write!(out, "\x1b[38;2;{r};{g};{b}m");
+                if y + 1 < self.height {
+                    let [r, g, b] = self.rgb(x, y + 1);
+                    let _ = write!(out, "\x1b[48;2;{r};{g};{b}m");
+                } else {
+                    out.push_str("\x1b[49m");
+                }
+                out.push('▀');
+            }
+            out.push_str("\x1b[0m\n");
+        }
+        out
+    }
+
+    /* export to `path`, as PNG or PPM by its extension */
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FramebufferError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        let bytes = match extension.as_str() {
+            "png" => self.to_png(),
+            "ppm" => self.to_ppm(),
+            _ => return Err(FramebufferError::UnknownFormat(path.display().to_string())),
+        };
+        fs::write(path, bytes)?;
+        info!("saved framebuffer to {}", path.display());
+        Ok(())
+    }
+}
+
+impl Device for Framebuffer {
+    fn name(&self) -> &str {
+        "framebuffer"
+    }
+
+    fn peek(&self, offset: u16) -> u16 {
+        self.pixels.get(offset as usize).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        if let Some(pixel) = self.pixels.get_mut(offset as usize) {
+            *pixel = value;
+        }
+    }
+
+    fn save(&self) -> Option<Vec<u16>> {
+        Some(self.pixels.clone())
+    }
+
+    fn restore(&mut self, state: &[u16]) {
+        if state.len() == self.pixels.len() {
+            self.pixels.copy_from_slice(state);
+        }
+    }
+}
+
+/* attach a 128x124 framebuffer at its standard address */
+pub fn attach_framebuffer(bus: &mut Bus) -> Result<Rc<RefCell<Framebuffer>>, BusError> {
+    let size = (FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT) as u16;
+    bus.attach(FRAMEBUFFER, FRAMEBUFFER + size - 1, Framebuffer::default())
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+/* a zlib stream of uncompressed deflate blocks */
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    /* deflate with a 32K window, no preset dictionary, fastest compression */
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(STORED_BLOCK_MAX).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
@@ -0,0 +1,127 @@
+use crate::libs::{
+    console::BufferConsole,
+    constants::MEMORY_MAX,
+    instructions::{InstructionSet, Instructions, Revision},
+    loader::Image,
+    types::{MemomryTrait, Memory, Opcodes, RegisterStorage, RegisterStorageTrait},
+    vm::{Engine, Vm},
+};
+
+/*
+fuzz target bodies, shared by the libFuzzer harnesses in `fuzz/` and the
+built-in random driver in `tests/fuzz.rs`. each accepts arbitrary bytes and
+must never panic or hang; errors returned by the machine are expected
+*/
+
+/* instructions a single `run_image` input may execute */
+pub const STEP_LIMIT: u64 = 10_000;
+
+fn words(bytes: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+}
+
+/*
+the first byte picks the engine and the size of the console input taken from
+the tail; the rest is an image (origin first) that is run from its origin
+*/
+pub fn run_image(data: &[u8]) {
+    let Some((&control, rest)) = data.split_first() else {
+        return;
+    };
+
+    let engine = if control & 1 == 0 {
+        Engine::Interpreter
+    } else {
+        Engine::Threaded
+    };
+    let input_len = ((control >> 1) as usize).min(rest.len());
+    let (image, input) = rest.split_at(rest.len() - input_len);
+
+    let mut words = words(image);
+    let Some(origin) = words.next() else {
+        return;
+    };
+    let words = words.take(MEMORY_MAX - origin as usize).collect();
+
+    let mut vm = Vm::with_engine(engine);
+    vm.set_console(BufferConsole::with_input(input));
+    vm.load_image(&Image { origin, words });
+    vm.set_pc(origin);
+
+    for _ in 0..STEP_LIMIT {
+        if vm.halted() || vm.step().is_err() {
+            break;
+        }
+    }
+}
+
+/* arbitrary bytes as an `.obj` file; anything accepted must load and round-trip */
+pub fn load_object(data: &[u8]) {
+    let Ok(image) = Image::from_bytes(data) else {
+        return;
+    };
+
+    assert_eq!(image.to_bytes(), data);
+    image.load_into(&mut Memory::new());
+}
+
+/*
+the first byte enables the arithmetic extension (bit 0) and selects the strict
+revision (bit 1), the next 16 bytes seed R0-R7. every following word is
+handed to each `InstructionSet` method in turn against the same machine
+state, then dispatched the way the machine would under those settings
+*/
+pub fn execute_words(data: &[u8]) {
+    let Some((&control, data)) = data.split_first() else {
+        return;
+    };
+    let arithmetic_extension = control & 1 == 1;
+    let revision = if control & 2 == 0 {
+        Revision::Legacy
+    } else {
+        Revision::Strict
+    };
+
+    let mut register_storage = RegisterStorage::new();
+    let mut memory = Memory::new();
+    let mut console = BufferConsole::new();
+
+    let (seed, program) = data.split_at(data.len().min(16));
+    for (register, value) in words(seed).enumerate() {
+        register_storage.locations[register] = value;
+    }
+
+    for instr in words(program) {
+        let rs = &mut register_storage;
+
+        let _ = Instructions::sign_extend(instr, u32::from(instr >> 11));
+        let _ = Instructions::add(rs, instr);
+        let _ = Instructions::and(rs, instr);
+        let _ = Instructions::ldi(rs, &memory, instr);
+        let _ = Instructions::not(rs, instr);
+        let _ = Instructions::branch(rs, instr);
+        let _ = Instructions::jump(rs, instr);
+        let _ = Instructions::jump_register(rs, instr);
+        let _ = Instructions::load(rs, &memory, instr);
+        let _ = Instructions::load_register(rs, &memory, instr);
+        let _ = Instructions::load_effective_address(rs, instr);
+        let _ = Instructions::effective_address(rs, instr);
+        let _ = Instructions::store(rs, &mut memory, instr);
+        let _ = Instructions::store_indirect(rs, &mut memory, instr);
+        let _ = Instructions::store_register(rs, &mut memory, instr);
+        let _ = Instructions::return_from_subroutine(rs);
+        let _ = Instructions::arithmetic(rs, instr);
+
+        /* `trap` only looks at the vector, whatever the opcode */
+        let _ = Instructions::trap(rs, &mut memory, &mut console, instr);
+        let _ = match Opcodes::from_u16(instr >> 12) {
+            Some(Opcodes::RES) if arithmetic_extension => Instructions::arithmetic(rs, instr),
+            Some(Opcodes::LEA) if !revision.lea_sets_flags() => {
+                Instructions::effective_address(rs, instr)
+            }
+            _ => Instructions::execute(rs, &mut memory, &mut console, instr),
+        };
+    }
+}
@@ -0,0 +1,517 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use thiserror::Error;
+use tracing::info;
+
+use crate::libs::{
+    console::BufferConsole,
+    error::VmError,
+    instructions::{Arithmetic, Revision},
+    limits::{LimitTracker, Limits, StopReason},
+    loader::{Image, LoaderError},
+    types::{Opcodes, RegisterStorage, Registers},
+    vm::{Engine, Vm},
+};
+
+/* instruction limit for specs that do not set one */
+pub const DEFAULT_MAX_INSTRUCTIONS: u64 = 1_000_000;
+
+/* executed instructions kept for the report of a failing program */
+pub const TRACE_LEN: usize = 8;
+
+#[derive(Debug, Error)]
+pub enum GoldenError {
+    #[error("Spec Error: line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("Spec Error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/*
+how a program is set up and what it is expected to do. specs are text, one
+directive per line, `#` starts a comment:
+
+    set-register R1 #10
+    set-memory x4000 0x0041
+    input "42\n"
+    output "Hello, World!\n"
+    output "second line\n"
+    max-instructions 5000
+    timeout-ms 200
+    max-output 4096
+    detect-loops
+    arithmetic-extension
+    revision strict
+    register R0 0x0005
+    memory x4000 #-1
+
+`input` and `output` strings are concatenated in order and accept the
+escapes \n \t \r \0 \\ \" and \xHH. numbers are hex (`0x`/`x`), decimal or
+`#`-prefixed decimal. without an `output` directive stdout is not checked
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spec {
+    pub setup_registers: Vec<(Registers, u16)>,
+    pub setup_memory: Vec<(u16, u16)>,
+    pub input: Vec<u8>,
+    pub output: Option<Vec<u8>>,
+    pub max_instructions: u64,
+    pub timeout: Option<Duration>,
+    pub max_output_bytes: Option<u64>,
+    pub detect_loops: bool,
+    pub arithmetic_extension: bool,
+    pub revision: Revision,
+    pub registers: Vec<(Registers, u16)>,
+    pub memory: Vec<(u16, u16)>,
+}
+
+impl Default for Spec {
+    fn default() -> Self {
+        Self {
+            setup_registers: Vec::new(),
+            setup_memory: Vec::new(),
+            input: Vec::new(),
+            output: None,
+            max_instructions: DEFAULT_MAX_INSTRUCTIONS,
+            timeout: None,
+            max_output_bytes: None,
+            detect_loops: false,
+            arithmetic_extension: false,
+            revision: Revision::default(),
+            registers: Vec::new(),
+            memory: Vec::new(),
+        }
+    }
+}
+
+impl Spec {
+    pub fn parse(text: &str) -> Result<Self, GoldenError> {
+        let mut spec = Self::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (directive, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            spec.apply(directive, rest)
+                .map_err(|message| GoldenError::Parse {
+                    line: index + 1,
+                    message,
+                })?;
+        }
+
+        Ok(spec)
+    }
+
+    /* apply one directive; shared with the grading script parser */
+    pub(crate) fn apply(&mut self, directive: &str, rest: &str) -> Result<(), String> {
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        let string = || parse_string(rest).ok_or("bad string");
+        let assignment = |usage: &str| match args[..] {
+            [target, value] => Ok((target, parse_word(value).ok_or("bad value")?)),
+            _ => Err(format!("expected `{directive} {usage} <value>`")),
+        };
+        let register = |name| parse_register(name).ok_or("unknown register");
+        let address = |text| parse_word(text).ok_or("bad address");
+
+        match directive {
+            "input" => self.input.extend(string()?),
+            "output" => self.output.get_or_insert_with(Vec::new).extend(string()?),
+            "max-instructions" | "timeout-ms" | "max-output" => {
+                let count: u64 = match args[..] {
+                    [count] => count.parse().map_err(|_| "bad count")?,
+                    _ => return Err(format!("expected `{directive} <count>`")),
+                };
+                match directive {
+                    "max-instructions" => self.max_instructions = count,
+                    "timeout-ms" => self.timeout = Some(Duration::from_millis(count)),
+                    _ => self.max_output_bytes = Some(count),
+                }
+            }
+            "detect-loops" if args.is_empty() => self.detect_loops = true,
+            "arithmetic-extension" if args.is_empty() => self.arithmetic_extension = true,
+            "revision" => {
+                self.revision = match args[..] {
+                    [name] => Revision::from_name(name).ok_or("unknown revision")?,
+                    _ => return Err("expected `revision <legacy|strict>`".to_string()),
+                }
+            }
+            "set-register" | "register" => {
+                let (name, value) = assignment("<register>")?;
+                let target = if directive == "register" {
+                    &mut self.registers
+                } else {
+                    &mut self.setup_registers
+                };
+                target.push((register(name)?, value));
+            }
+            "set-memory" | "memory" => {
+                let (at, value) = assignment("<address>")?;
+                let target = if directive == "memory" {
+                    &mut self.memory
+                } else {
+                    &mut self.setup_memory
+                };
+                target.push((address(at)?, value));
+            }
+            _ => return Err(format!("unknown directive `{directive}`")),
+        }
+
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GoldenError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+fn parse_register(name: &str) -> Option<Registers> {
+    let index = match name.to_ascii_uppercase().as_str() {
+        "PC" => Registers::PC as u16,
+        "COND" => Registers::COND as u16,
+        register => register
+            .strip_prefix('R')?
+            .parse()
+            .ok()
+            .filter(|r| *r < 8)?,
+    };
+    RegisterStorage::get_register(index).ok()
+}
+
+fn parse_word(text: &str) -> Option<u16> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('x'))
+        .or_else(|| text.strip_prefix('X'));
+    if let Some(hex) = hex {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+
+    /* negative decimals are stored as two's complement */
+    let decimal: i32 = text.strip_prefix('#').unwrap_or(text).parse().ok()?;
+    (-0x8000..=0xFFFF)
+        .contains(&decimal)
+        .then_some(decimal as u16)
+}
+
+fn parse_string(text: &str) -> Option<Vec<u8>> {
+    let body = text.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut bytes = Vec::new();
+    let mut chars = body.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        bytes.push(match chars.next()? {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            '0' => 0,
+            '\\' => b'\\',
+            '"' => b'"',
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                u8::from_str_radix(&hex, 16).ok()?
+            }
+            _ => return None,
+        });
+    }
+
+    Some(bytes)
+}
+
+/* one way a program missed its spec */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    Load(String),
+    Fault(VmError),
+    /* stopped by a limit before reaching HALT */
+    Stopped(StopReason),
+    Output {
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    Register {
+        register: Registers,
+        expected: u16,
+        actual: u16,
+    },
+    Memory {
+        address: u16,
+        expected: u16,
+        actual: u16,
+    },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Load(error) => write!(f, "could not load image: {error}"),
+            Failure::Fault(err) => write!(f, "faulted: {err}"),
+            Failure::Stopped(reason) => write!(f, "{reason}"),
+            Failure::Output { expected, actual } => {
+                write!(f, "output differs:\n{}", diff(expected, actual))
+            }
+            Failure::Register {
+                register,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "register {register:?} is {actual:#06x}, expected {expected:#06x}"
+            ),
+            Failure::Memory {
+                address,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "memory[{address:#06x}] is {actual:#06x}, expected {expected:#06x}"
+            ),
+        }
+    }
+}
+
+/* line-by-line comparison, showing the first few lines that differ */
+fn diff(expected: &[u8], actual: &[u8]) -> String {
+    const SHOWN: usize = 5;
+
+    let expected = String::from_utf8_lossy(expected);
+    let actual = String::from_utf8_lossy(actual);
+    let expected: Vec<&str> = expected.split_inclusive('\n').collect();
+    let actual: Vec<&str> = actual.split_inclusive('\n').collect();
+
+    let mut out = String::new();
+    let differing = (0..expected.len().max(actual.len()))
+        .filter(|line| expected.get(*line) != actual.get(*line))
+        .collect::<Vec<_>>();
+
+    for line in differing.iter().take(SHOWN) {
+        let _ = writeln!(out, "    line {}:", line + 1);
+        let _ = writeln!(out, "      - {:?}", expected.get(*line).unwrap_or(&""));
+        let _ = writeln!(out, "      + {:?}", actual.get(*line).unwrap_or(&""));
+    }
+    if differing.len() > SHOWN {
+        let _ = writeln!(out, "    ... {} more lines", differing.len() - SHOWN);
+    }
+    out.trim_end().to_string()
+}
+
+/*
+an executed instruction: its address and word, and whether the reserved
+opcode ran as the arithmetic extension
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub instr: u16,
+    pub arithmetic_extension: bool,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#06x}: {:#06x}", self.pc, self.instr)?;
+        match Opcodes::from_u16(self.instr >> 12) {
+            Some(Opcodes::RES)
+                if self.arithmetic_extension
+                    && let Some(operation) = Arithmetic::from_u16((self.instr >> 3) & 0x7) =>
+            {
+                write!(f, " {operation:?}")
+            }
+            Some(opcode) => write!(f, " {opcode:?}"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    pub name: String,
+    pub instructions: u64,
+    pub failures: Vec<Failure>,
+    /* the last `TRACE_LEN` instructions executed, oldest first */
+    pub trace: Vec<TraceEntry>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/*
+run `image` from its origin in a fresh machine, set up and fed input as the
+spec says, until it halts or reaches a limit; then check the output, including
+what the HALT routine prints, registers and memory
+*/
+pub fn run_case(name: &str, image: &Image, spec: &Spec, engine: Engine) -> CaseResult {
+    let console = BufferConsole::with_input(&spec.input);
+    let mut vm = Vm::with_engine(engine);
+    vm.set_console(console.clone());
+    vm.set_arithmetic_extension(spec.arithmetic_extension);
+    vm.set_revision(spec.revision);
+    vm.load_image(image);
+    vm.set_pc(image.origin);
+
+    for (address, value) in &spec.setup_memory {
+        vm.write_memory(*address, *value);
+    }
+    for (register, value) in &spec.setup_registers {
+        vm.register_storage.locations[*register as usize] = *value;
+    }
+
+    let limits = Limits {
+        max_instructions: Some(spec.max_instructions),
+        timeout: spec.timeout,
+        max_output_bytes: spec.max_output_bytes,
+        detect_loops: spec.detect_loops,
+    };
+    let mut tracker = LimitTracker::new(limits, &vm);
+    let mut trace = VecDeque::with_capacity(TRACE_LEN);
+    let mut failures = Vec::new();
+
+    loop {
+        match tracker.check(&vm) {
+            Some(StopReason::Halted) => break,
+            Some(reason) => {
+                failures.push(Failure::Stopped(reason));
+                break;
+            }
+            None => {}
+        }
+
+        let pc = vm.pc();
+        if trace.len() == TRACE_LEN {
+            trace.pop_front();
+        }
+        trace.push_back(TraceEntry {
+            pc,
+            instr: vm.memory.locations[pc as usize],
+            arithmetic_extension: spec.arithmetic_extension,
+        });
+
+        if let Err(err) = vm.step() {
+            failures.push(Failure::Fault(err));
+            break;
+        }
+    }
+
+    let actual = console.output();
+    if let Some(expected) = &spec.output
+        && *expected != actual
+    {
+        failures.push(Failure::Output {
+            expected: expected.clone(),
+            actual,
+        });
+    }
+    for (register, expected) in &spec.registers {
+        let actual = vm.register_storage.locations[*register as usize];
+        if actual != *expected {
+            failures.push(Failure::Register {
+                register: *register,
+                expected: *expected,
+                actual,
+            });
+        }
+    }
+    for (address, expected) in &spec.memory {
+        let actual = vm.memory.locations[*address as usize];
+        if actual != *expected {
+            failures.push(Failure::Memory {
+                address: *address,
+                expected: *expected,
+                actual,
+            });
+        }
+    }
+
+    CaseResult {
+        name: name.to_string(),
+        instructions: vm.instruction_count,
+        failures,
+        trace: trace.into(),
+    }
+}
+
+/*
+run every `<name>.spec` in `dir` against the image `<name>.obj` next to it,
+in file name order
+*/
+pub fn run_directory(
+    dir: impl AsRef<Path>,
+    engine: Engine,
+) -> Result<Vec<CaseResult>, GoldenError> {
+    let mut specs: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    specs.retain(|path| path.extension().is_some_and(|ext| ext == "spec"));
+    specs.sort();
+
+    info!("running {} golden-output programs", specs.len());
+
+    specs
+        .iter()
+        .map(|path| {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            let spec = Spec::load(path)?;
+
+            Ok(match Image::from_file(path.with_extension("obj")) {
+                Ok(image) => run_case(&name, &image, &spec, engine),
+                Err(err) => load_failure(&name, err),
+            })
+        })
+        .collect()
+}
+
+fn load_failure(name: &str, err: LoaderError) -> CaseResult {
+    CaseResult {
+        name: name.to_string(),
+        instructions: 0,
+        failures: vec![Failure::Load(err.to_string())],
+        trace: Vec::new(),
+    }
+}
+
+/* human-readable summary, one line per program plus the details of each failure */
+pub fn report(results: &[CaseResult]) -> String {
+    let mut out = String::new();
+
+    for result in results {
+        let status = if result.passed() { "PASS" } else { "FAIL" };
+        let _ = writeln!(
+            out,
+            "{status} {} ({} instructions)",
+            result.name, result.instructions
+        );
+        write_details(&mut out, result);
+    }
+
+    let passed = results.iter().filter(|result| result.passed()).count();
+    let _ = writeln!(out, "{passed}/{} passed", results.len());
+    out
+}
+
+/* the failures of a program and, if it ran, the instructions leading up to the end */
+pub(crate) fn write_details(out: &mut String, result: &CaseResult) {
+    for failure in &result.failures {
+        let _ = writeln!(out, "  {failure}");
+    }
+    if !result.passed() && !result.trace.is_empty() {
+        let _ = writeln!(out, "  last instructions:");
+        for entry in &result.trace {
+            let _ = writeln!(out, "    {entry}");
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::{fmt::Write as _, fs, io, path::Path};
+
+use thiserror::Error;
+use tracing::info;
+
+use crate::libs::{
+    golden::{self, CaseResult, Spec},
+    loader::Image,
+    vm::Engine,
+};
+
+#[derive(Debug, Error)]
+pub enum GradeError {
+    #[error("Grading Error: line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("Grading Error: script has no cases")]
+    NoCases,
+    #[error("Grading Error: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GradingCase {
+    pub name: String,
+    pub points: u32,
+    pub spec: Spec,
+}
+
+/*
+a list of test cases for one assignment. each `case <name> <points>` line
+starts a case, followed by golden spec directives (see `golden::Spec`).
+directives before the first case are defaults copied into every case:
+
+    max-instructions 10000
+    timeout-ms 500
+
+    case adds-small-numbers 5
+      set-register R0 #3
+      set-register R1 #4
+      register R2 #7
+
+    case prints-greeting 3
+      input "Ada\n"
+      output "Hello, Ada\nProgram halted\n"
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GradingScript {
+    pub cases: Vec<GradingCase>,
+}
+
+impl GradingScript {
+    pub fn parse(text: &str) -> Result<Self, GradeError> {
+        let mut defaults = Spec::default();
+        let mut cases: Vec<GradingCase> = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: String| GradeError::Parse {
+                line: index + 1,
+                message,
+            };
+            let (directive, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+            if directive == "case" {
+                let (name, points) = match rest.split_whitespace().collect::<Vec<_>>()[..] {
+                    [name, points] => (name, points.parse().ok()),
+                    _ => (rest, None),
+                };
+                let points =
+                    points.ok_or_else(|| error("expected `case <name> <points>`".into()))?;
+                cases.push(GradingCase {
+                    name: name.to_string(),
+                    points,
+                    spec: defaults.clone(),
+                });
+                continue;
+            }
+
+            let spec = match cases.last_mut() {
+                Some(case) => &mut case.spec,
+                None => &mut defaults,
+            };
+            spec.apply(directive, rest).map_err(error)?;
+        }
+
+        if cases.is_empty() {
+            return Err(GradeError::NoCases);
+        }
+        Ok(Self { cases })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GradeError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseGrade {
+    pub points: u32,
+    pub earned: u32,
+    pub result: CaseResult,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GradeReport {
+    pub cases: Vec<CaseGrade>,
+}
+
+/* run every case of `script` against `image`, each in its own fresh machine */
+pub fn grade(image: &Image, script: &GradingScript, engine: Engine) -> GradeReport {
+    info!("grading {} cases", script.cases.len());
+
+    let cases = script
+        .cases
+        .iter()
+        .map(|case| {
+            let result = golden::run_case(&case.name, image, &case.spec, engine);
+            CaseGrade {
+                points: case.points,
+                earned: if result.passed() { case.points } else { 0 },
+                result,
+            }
+        })
+        .collect();
+
+    GradeReport { cases }
+}
+
+impl GradeReport {
+    pub fn score(&self) -> u32 {
+        self.cases.iter().map(|case| case.earned).sum()
+    }
+
+    pub fn total(&self) -> u32 {
+        self.cases.iter().map(|case| case.points).sum()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+
+        for case in &self.cases {
+            let status = if case.result.passed() { "PASS" } else { "FAIL" };
+            let _ = writeln!(
+                out,
+                "{status} {} {}/{} ({} instructions)",
+                case.result.name, case.earned, case.points, case.result.instructions
+            );
+            golden::write_details(&mut out, &case.result);
+        }
+
+        let _ = writeln!(out, "score: {}/{}", self.score(), self.total());
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = format!(
+            "{{\"score\":{},\"total\":{},\"cases\":[",
+            self.score(),
+            self.total()
+        );
+
+        for (index, case) in self.cases.iter().enumerate() {
+            let result = &case.result;
+            let failures: Vec<String> = result
+                .failures
+                .iter()
+                .map(|failure| json_string(&failure.to_string()))
+                .collect();
+            let trace: Vec<String> = result
+                .trace
+                .iter()
+                .map(|entry| json_string(&entry.to_string()))
+                .collect();
+
+            let _ = write!(
+                out,
+                "{}{{\"name\":{},\"points\":{},\"earned\":{},\"passed\":{},\"instructions\":{},\"failures\":[{}],\"trace\":[{}]}}",
+                if index == 0 { "" } else { "," },
+                json_string(&result.name),
+                case.points,
+                case.earned,
+                result.passed(),
+                result.instructions,
+                failures.join(","),
+                trace.join(",")
+            );
+        }
+
+        out.push_str("]}");
+        out
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -1,10 +1,11 @@
 use thiserror::Error;
 
 use crate::libs::{
+    console::Console,
     trap::Trap,
     types::{
-        ConditionalFlags, MemomryTrait, Memory, RegisterError, RegisterStorage,
-        RegisterStorageTrait, Registers,
+        MemomryTrait, Memory, Opcodes, RegisterError, RegisterStorage, RegisterStorageTrait,
+        Registers,
     },
 };
 
@@ -14,6 +15,72 @@ pub enum InstructionSetError {
     InvalidBitCount(u32),
     #[error("Register Error: {0}")]
     RegisterError(#[from] RegisterError),
+    #[error("Instruction Error: Bad Opcode {0:#06x}")]
+    BadOpcode(u16),
+    #[error("Instruction Error: Unknown Trap Vector {0:#04x}")]
+    UnknownTrap(u16),
+    #[error("Instruction Error: Divide By Zero {0:#06x}")]
+    DivideByZero(u16),
+}
+
+/*
+operations of the arithmetic extension on the reserved opcode (1101):
+
+15-12 1101 | 11-9 DR | 8-6 SR1 | 5-3 operation | 2-0 SR2
+
+results are truncated to 16 bits and set the condition codes. shifting by
+16 or more shifts every bit out; DIV and MOD by zero fail with
+`DivideByZero`. operations 110 and 111 are illegal
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arithmetic {
+    MUL = 0, /* DR = SR1 * SR2 */
+    DIV,     /* DR = SR1 / SR2, signed, rounding toward zero */
+    MOD,     /* DR = SR1 % SR2, signed, with the sign of SR1 */
+    SHL,     /* DR = SR1 << SR2 */
+    SHR,     /* DR = SR1 >> SR2, shifting in zeros */
+    SRA,     /* DR = SR1 >> SR2, shifting in the sign bit */
+}
+
+impl Arithmetic {
+    pub fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            0 => Some(Arithmetic::MUL),
+            1 => Some(Arithmetic::DIV),
+            2 => Some(Arithmetic::MOD),
+            3 => Some(Arithmetic::SHL),
+            4 => Some(Arithmetic::SHR),
+            5 => Some(Arithmetic::SRA),
+            _ => None,
+        }
+    }
+}
+
+/*
+editions of the LC-3 that disagree on details. `Legacy` is the original
+ISA; `Strict` follows the revised one:
+
+- LEA loads DR without setting the condition codes
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Revision {
+    #[default]
+    Legacy,
+    Strict,
+}
+
+impl Revision {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "legacy" => Some(Revision::Legacy),
+            "strict" => Some(Revision::Strict),
+            _ => None,
+        }
+    }
+
+    pub fn lea_sets_flags(self) -> bool {
+        self == Revision::Legacy
+    }
 }
 
 pub trait InstructionSet {
@@ -55,6 +122,11 @@ pub trait InstructionSet {
         register_storage: &mut RegisterStorage,
         instr: u16,
     ) -> Result<(), InstructionSetError>;
+    /* LEA of the strict revision, leaving the condition codes alone */
+    fn effective_address(
+        register_storage: &mut RegisterStorage,
+        instr: u16,
+    ) -> Result<(), InstructionSetError>;
     // /* ST */
     fn store(
         register_storage: &mut RegisterStorage,
@@ -77,6 +149,11 @@ pub trait InstructionSet {
     fn return_from_subroutine(
         register_storage: &mut RegisterStorage,
     ) -> Result<(), InstructionSetError>;
+    /* MUL, DIV, MOD, SHL, SHR, SRA: the arithmetic extension */
+    fn arithmetic(
+        register_storage: &mut RegisterStorage,
+        instr: u16,
+    ) -> Result<(), InstructionSetError>;
     /* RTI */
     // fn return_from_interrupt(
     //     register_storage: &mut RegisterStorage,
@@ -85,7 +162,15 @@ pub trait InstructionSet {
     // ) -> Result<(), InstructionSetError>;
     fn trap(
         register_storage: &mut RegisterStorage,
-        memory: &impl MemomryTrait,
+        memory: &mut impl MemomryTrait,
+        console: &mut dyn Console,
+        instr: u16,
+    ) -> Result<(), InstructionSetError>;
+    /* decode the opcode in the top 4 bits and dispatch to its handler */
+    fn execute(
+        register_storage: &mut RegisterStorage,
+        memory: &mut Memory,
+        console: &mut dyn Console,
         instr: u16,
     ) -> Result<(), InstructionSetError>;
 }
@@ -126,16 +211,19 @@ impl InstructionSet for Instructions {
         let imm_flag = (instr >> 5) & 0x1;
 
         if imm_flag == 1 {
-            let imm5 = Self::sign_extend(instr & 0x1F, 5).unwrap();
-            let _update_destination_register_imm5 =
-                register_storage.store(register_storage.load(r1)? + imm5, r0)?;
+            let imm5 = Self::sign_extend(instr & 0x1F, 5)?;
+            register_storage.store(register_storage.load(r1)?.wrapping_add(imm5), r0)?;
         } else {
             let r2 = instr & 0x7;
-            let _update_destination_register =
-                register_storage.store(register_storage.load(r1)? + register_storage.load(r2)?, r0);
+            register_storage.store(
+                register_storage
+                    .load(r1)?
+                    .wrapping_add(register_storage.load(r2)?),
+                r0,
+            )?;
         }
 
-        let _ = register_storage.update_flags(r0);
+        register_storage.update_flags(r0)?;
 
         Ok(())
     }
@@ -152,16 +240,14 @@ impl InstructionSet for Instructions {
 
         if imm_flag == 1 {
             let imm5 = Self::sign_extend(instr & 0x1F, 5)?;
-            let _update_destination_reg =
-                register_storage.store(register_storage.load(r1)? & imm5, r0)?;
+            register_storage.store(register_storage.load(r1)? & imm5, r0)?;
         } else {
             let r2 = instr & 0x7;
 
-            let _update_destination_reg = register_storage
-                .store(register_storage.load(r1)? & register_storage.load(r2)?, r0)?;
+            register_storage.store(register_storage.load(r1)? & register_storage.load(r2)?, r0)?;
         }
 
-        let _ = register_storage.update_flags(r0);
+        register_storage.update_flags(r0)?;
 
         Ok(())
     }
@@ -180,13 +266,13 @@ impl InstructionSet for Instructions {
         let current_pc = register_storage.load(Registers::PC as u16)?;
 
         /* Calculate the memory address */
-        let mem_address = current_pc + pc_offset;
+        let mem_address = current_pc.wrapping_add(pc_offset);
 
         let value = memory.read(memory.read(mem_address));
 
-        let _update_destination_reg = register_storage.store(value, r0);
+        register_storage.store(value, r0)?;
 
-        let _ = register_storage.update_flags(r0);
+        register_storage.update_flags(r0)?;
 
         Ok(())
     }
@@ -195,8 +281,8 @@ impl InstructionSet for Instructions {
         let r0 = (instr >> 9) & 0x7;
         let r1 = (instr >> 6) & 0x7;
 
-        let _ = register_storage.store(!register_storage.load(r1)?, r0);
-        let _update_conditional_flag = register_storage.update_flags(r0)?;
+        register_storage.store(!register_storage.load(r1)?, r0)?;
+        register_storage.update_flags(r0)?;
 
         Ok(())
     }
@@ -210,7 +296,8 @@ impl InstructionSet for Instructions {
         let cond_flag = (instr >> 9) & 0x7;
 
         if cond_flag & register_storage.load(Registers::COND as u16)? != 0 {
-            register_storage.locations[Registers::PC as usize] += pc_offset;
+            let pc = register_storage.load(Registers::PC as u16)?;
+            register_storage.store(pc.wrapping_add(pc_offset), Registers::PC as u16)?;
         }
 
         Ok(())
@@ -218,8 +305,7 @@ impl InstructionSet for Instructions {
 
     fn jump(register_storage: &mut RegisterStorage, instr: u16) -> Result<(), InstructionSetError> {
         let r1 = (instr >> 6) & 0x7;
-        let _update_pc =
-            register_storage.store(register_storage.load(r1)?, Registers::PC as u16)?;
+        register_storage.store(register_storage.load(r1)?, Registers::PC as u16)?;
         Ok(())
     }
 
@@ -228,20 +314,18 @@ impl InstructionSet for Instructions {
         instr: u16,
     ) -> Result<(), InstructionSetError> {
         let pc = register_storage.load(Registers::PC as u16)?;
-        let _set_source_register_7 = register_storage.store(pc, Registers::R7 as u16)?;
 
-        if (instr >> 11) & 1 == 1 {
+        /* the target is read before R7 is written, so JSRR R7 jumps to the old R7 */
+        let target = if (instr >> 11) & 1 == 1 {
             /* JSR */
-            let _update_pc_for_jsr = register_storage.store(
-                pc + Self::sign_extend(instr & 0x7FF, 11)?,
-                Registers::PC as u16,
-            );
+            pc.wrapping_add(Self::sign_extend(instr & 0x7FF, 11)?)
         } else {
             /* JSRR */
-            let r1 = (instr >> 6) & 0x7;
-            let _update_pc_for_jssr =
-                register_storage.store(register_storage.load(r1)?, Registers::PC as u16);
-        }
+            register_storage.load((instr >> 6) & 0x7)?
+        };
+
+        register_storage.store(pc, Registers::R7 as u16)?;
+        register_storage.store(target, Registers::PC as u16)?;
         Ok(())
     }
 
@@ -252,10 +336,12 @@ impl InstructionSet for Instructions {
     ) -> Result<(), InstructionSetError> {
         let r0 = (instr >> 9) & 0x7;
         let pc_offset = Self::sign_extend(instr & 0x1FF, 9)?;
-        let memory_addr = register_storage.load(Registers::PC as u16)? + pc_offset;
-        let _update_destination_register = register_storage.store(memory.read(memory_addr), r0);
+        let memory_addr = register_storage
+            .load(Registers::PC as u16)?
+            .wrapping_add(pc_offset);
+        register_storage.store(memory.read(memory_addr), r0)?;
 
-        let _ = register_storage.update_flags(r0);
+        register_storage.update_flags(r0)?;
 
         Ok(())
     }
@@ -268,23 +354,36 @@ impl InstructionSet for Instructions {
         let r0 = (instr >> 9) & 0x7;
         let r1 = (instr >> 6) & 0x7;
         let offset = Self::sign_extend(instr & 0x3F, 6)?;
-        let memory_addr = register_storage.load(r1)? + offset;
-        let _update_destination_register = register_storage.store(memory.read(memory_addr), r1)?;
+        let memory_addr = register_storage.load(r1)?.wrapping_add(offset);
+        register_storage.store(memory.read(memory_addr), r0)?;
 
-        let _ = register_storage.update_flags(r0);
+        register_storage.update_flags(r0)?;
         Ok(())
     }
 
     fn load_effective_address(
         register_storage: &mut RegisterStorage,
         instr: u16,
+    ) -> Result<(), InstructionSetError> {
+        Self::effective_address(register_storage, instr)?;
+
+        register_storage.update_flags((instr >> 9) & 0x7)?;
+
+        Ok(())
+    }
+
+    fn effective_address(
+        register_storage: &mut RegisterStorage,
+        instr: u16,
     ) -> Result<(), InstructionSetError> {
         let r0 = (instr >> 9) & 0x7;
         let pc_offset = Self::sign_extend(instr & 0x1FF, 9)?;
-        let _update_destination_reg =
-            register_storage.store(register_storage.load(Registers::PC as u16)? + pc_offset, r0);
-
-        let _ = register_storage.update_flags(r0);
+        register_storage.store(
+            register_storage
+                .load(Registers::PC as u16)?
+                .wrapping_add(pc_offset),
+            r0,
+        )?;
 
         Ok(())
     }
@@ -296,7 +395,9 @@ impl InstructionSet for Instructions {
     ) -> Result<(), InstructionSetError> {
         let r0 = (instr >> 9) & 0x7;
         let pc_offset = Self::sign_extend(instr & 0x1FF, 9)?;
-        let memory_addr = register_storage.load(Registers::PC as u16)? + pc_offset;
+        let memory_addr = register_storage
+            .load(Registers::PC as u16)?
+            .wrapping_add(pc_offset);
 
         memory.write(memory_addr, register_storage.load(r0)?);
 
@@ -310,7 +411,9 @@ impl InstructionSet for Instructions {
     ) -> Result<(), InstructionSetError> {
         let r0 = (instr >> 9) & 0x7;
         let pc_offset = Self::sign_extend(instr & 0x1FF, 9)?;
-        let memory_addr = register_storage.load(Registers::PC as u16)? + pc_offset;
+        let memory_addr = register_storage
+            .load(Registers::PC as u16)?
+            .wrapping_add(pc_offset);
 
         memory.write(memory.read(memory_addr), register_storage.load(r0)?);
         Ok(())
@@ -324,7 +427,7 @@ impl InstructionSet for Instructions {
         let r0 = (instr >> 9) & 0x7;
         let r1 = (instr >> 6) & 0x7;
         let pc_offset = Self::sign_extend(instr & 0x3F, 6)?;
-        let memory_addr = register_storage.load(r1)? + pc_offset;
+        let memory_addr = register_storage.load(r1)?.wrapping_add(pc_offset);
 
         memory.write(memory_addr, register_storage.load(r0)?);
         Ok(())
@@ -333,7 +436,7 @@ impl InstructionSet for Instructions {
     fn return_from_subroutine(
         register_storage: &mut RegisterStorage,
     ) -> Result<(), InstructionSetError> {
-        let _update_pc_value_with_r7 = register_storage.store(
+        register_storage.store(
             register_storage.load(Registers::R7 as u16)?,
             Registers::PC as u16,
         )?;
@@ -343,11 +446,11 @@ impl InstructionSet for Instructions {
     // fn return_from_interrupt(register_storage: &mut RegisterStorage, memory: &impl MemomryTrait, instr: u16) -> Result<(), InstructionSetError> {
     //     if (instr >> 15) & 1 == 0 {
     //         let r6 = register_storage.load(Registers::R6).unwrap();
-    //         let _update_pc_with_value_from_memory_at_r6 = register_storage.store(memory.read(r6), Registers::PC);
-    //         let _increment_r6_by_1 = register_storage.store(r6 + 1, Registers::R6);
+    //         register_storage.store(memory.read(r6), Registers::PC)?;
+    //         register_storage.store(r6 + 1, Registers::R6)?;
     //         let r6 = register_storage.load(Registers::R6).unwrap();
     //         let TEMP = memory.read(r6);
-    //         let _increment_r6_by_1 = register_storage.store(r6 + 1, Registers::R6);
+    //         register_storage.store(r6 + 1, Registers::R6)?;
     //     }
 
     //     Ok(())
@@ -355,16 +458,70 @@ impl InstructionSet for Instructions {
 
     fn trap(
         register_storage: &mut RegisterStorage,
-        memory: &impl MemomryTrait,
+        memory: &mut impl MemomryTrait,
+        console: &mut dyn Console,
         instr: u16,
     ) -> Result<(), InstructionSetError> {
-        let _set_register_7 = register_storage.store(
+        register_storage.store(
             register_storage.load(Registers::PC as u16)?,
             Registers::R7 as u16,
         )?;
-        Trap::execute_trap_instruction(register_storage, memory, instr)?;
+        Trap::execute_trap_instruction(register_storage, memory, console, instr)?;
+        Ok(())
+    }
+
+    fn arithmetic(
+        register_storage: &mut RegisterStorage,
+        instr: u16,
+    ) -> Result<(), InstructionSetError> {
+        let r0 = (instr >> 9) & 0x7;
+        let a = register_storage.load((instr >> 6) & 0x7)?;
+        let b = register_storage.load(instr & 0x7)?;
+
+        let value = match Arithmetic::from_u16((instr >> 3) & 0x7) {
+            Some(Arithmetic::MUL) => a.wrapping_mul(b),
+            Some(Arithmetic::DIV | Arithmetic::MOD) if b == 0 => {
+                return Err(InstructionSetError::DivideByZero(instr));
+            }
+            Some(Arithmetic::DIV) => (a as i16).wrapping_div(b as i16) as u16,
+            Some(Arithmetic::MOD) => (a as i16).wrapping_rem(b as i16) as u16,
+            Some(Arithmetic::SHL) => a.checked_shl(b.into()).unwrap_or(0),
+            Some(Arithmetic::SHR) => a.checked_shr(b.into()).unwrap_or(0),
+            Some(Arithmetic::SRA) => (a as i16 >> b.min(15)) as u16,
+            None => return Err(InstructionSetError::BadOpcode(instr)),
+        };
+
+        register_storage.store(value, r0)?;
+        register_storage.update_flags(r0)?;
         Ok(())
     }
+
+    fn execute(
+        register_storage: &mut RegisterStorage,
+        memory: &mut Memory,
+        console: &mut dyn Console,
+        instr: u16,
+    ) -> Result<(), InstructionSetError> {
+        match Opcodes::from_u16(instr >> 12) {
+            Some(Opcodes::ADD) => Self::add(register_storage, instr),
+            Some(Opcodes::AND) => Self::and(register_storage, instr),
+            Some(Opcodes::NOT) => Self::not(register_storage, instr),
+            Some(Opcodes::BR) => Self::branch(register_storage, instr),
+            Some(Opcodes::JMP) => Self::jump(register_storage, instr),
+            Some(Opcodes::JSR) => Self::jump_register(register_storage, instr),
+            Some(Opcodes::LD) => Self::load(register_storage, memory, instr),
+            Some(Opcodes::LDI) => Self::ldi(register_storage, memory, instr),
+            Some(Opcodes::LDR) => Self::load_register(register_storage, memory, instr),
+            Some(Opcodes::LEA) => Self::load_effective_address(register_storage, instr),
+            Some(Opcodes::ST) => Self::store(register_storage, memory, instr),
+            Some(Opcodes::STI) => Self::store_indirect(register_storage, memory, instr),
+            Some(Opcodes::STR) => Self::store_register(register_storage, memory, instr),
+            Some(Opcodes::TRAP) => Self::trap(register_storage, memory, console, instr),
+            Some(Opcodes::RES) | Some(Opcodes::RTI) | None => {
+                Err(InstructionSetError::BadOpcode(instr))
+            }
+        }
+    }
 }
 
 // 1000000000000000
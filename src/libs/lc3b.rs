@@ -0,0 +1,256 @@
+use crate::libs::{
+    console::Console,
+    constants::MEMORY_MAX,
+    instructions::{InstructionSet, InstructionSetError, Instructions},
+    protection::{AccessKind, Privilege, Protection, Violation},
+    trap::Trap,
+    types::{MemomryTrait, Memory, RegisterStorage, RegisterStorageTrait, Registers},
+};
+
+/*
+the LC-3b: byte-addressable memory of 16-bit little-endian words, PC-relative
+offsets counted in words, LDB/STB and LDW/STW in place of the LC-3's loads
+and stores, XOR in place of NOT, SHF, and an LEA that leaves the condition
+codes alone.
+
+the machine keeps each word at the even address of its low byte, so device
+registers and the MCR stay where the LC-3 has them. word accesses and
+instruction fetches ignore bit 0 of the address
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    BR = 0,
+    ADD,
+    LDB,
+    STB,
+    JSR,
+    AND,
+    LDW,
+    STW,
+    RTI,
+    XOR,
+    JMP = 12,
+    SHF,
+    LEA,
+    TRAP,
+}
+
+impl Opcode {
+    pub fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            0 => Some(Opcode::BR),
+            1 => Some(Opcode::ADD),
+            2 => Some(Opcode::LDB),
+            3 => Some(Opcode::STB),
+            4 => Some(Opcode::JSR),
+            5 => Some(Opcode::AND),
+            6 => Some(Opcode::LDW),
+            7 => Some(Opcode::STW),
+            8 => Some(Opcode::RTI),
+            9 => Some(Opcode::XOR),
+            12 => Some(Opcode::JMP),
+            13 => Some(Opcode::SHF),
+            14 => Some(Opcode::LEA),
+            15 => Some(Opcode::TRAP),
+            _ => None,
+        }
+    }
+}
+
+pub fn read_byte(memory: &Memory, address: u16) -> u8 {
+    let word = memory.read(address & !1);
+    if address & 1 == 0 {
+        word as u8
+    } else {
+        (word >> 8) as u8
+    }
+}
+
+/* a byte store to a device register writes the byte as the whole register */
+pub fn write_byte(memory: &mut Memory, address: u16, byte: u8) {
+    let aligned = address & !1;
+    if memory.bus.is_mapped(aligned) {
+        memory.write(aligned, byte.into());
+        return;
+    }
+
+    let word = memory.locations[aligned as usize];
+    let word = if address & 1 == 0 {
+        word & 0xFF00 | u16::from(byte)
+    } else {
+        word & 0x00FF | u16::from(byte) << 8
+    };
+    memory.write(aligned, word);
+}
+
+pub fn read_word(memory: &Memory, address: u16) -> u16 {
+    memory.read(address & !1)
+}
+
+pub fn write_word(memory: &mut Memory, address: u16, value: u16) {
+    memory.write(address & !1, value);
+}
+
+fn sign_extend(instr: u16, bit_count: u32) -> u16 {
+    let bits = instr & ((1 << bit_count) - 1);
+    Instructions::sign_extend(bits, bit_count).unwrap_or(bits)
+}
+
+/* the address a LDB/STB (`words` false) or LDW/STW (`words` true) accesses */
+fn base_offset(register_storage: &RegisterStorage, instr: u16, words: bool) -> u16 {
+    let base = register_storage.locations[((instr >> 6) & 0x7) as usize];
+    let offset = sign_extend(instr, 6);
+    base.wrapping_add(if words { offset << 1 } else { offset })
+}
+
+/* check the fetch from `pc` and the data access of `instr` against `protection` */
+pub fn check(
+    protection: &Protection,
+    privilege: Privilege,
+    pc: u16,
+    instr: u16,
+    register_storage: &RegisterStorage,
+) -> Result<(), Violation> {
+    let check = |address, kind| {
+        if protection.allows(address, kind, privilege) {
+            Ok(())
+        } else {
+            Err(Violation { address, kind })
+        }
+    };
+
+    check(pc, AccessKind::Execute)?;
+    match Opcode::from_u16(instr >> 12) {
+        Some(Opcode::LDB) => check(
+            base_offset(register_storage, instr, false),
+            AccessKind::Read,
+        ),
+        Some(Opcode::LDW) => check(base_offset(register_storage, instr, true), AccessKind::Read),
+        Some(Opcode::STB) => check(
+            base_offset(register_storage, instr, false),
+            AccessKind::Write,
+        ),
+        Some(Opcode::STW) => check(
+            base_offset(register_storage, instr, true),
+            AccessKind::Write,
+        ),
+        _ => Ok(()),
+    }
+}
+
+/*
+execute an LC-3b instruction, with PC already past it. TRAP and RTI are
+left to the machine and fail here as illegal opcodes
+*/
+pub fn execute(
+    register_storage: &mut RegisterStorage,
+    memory: &mut Memory,
+    instr: u16,
+) -> Result<(), InstructionSetError> {
+    let dr = (instr >> 9) & 0x7;
+    let sr1 = register_storage.load((instr >> 6) & 0x7)?;
+    let pc = register_storage.load(Registers::PC as u16)?;
+    /* the second ALU operand: imm5 or SR2 */
+    let operand = || {
+        if (instr >> 5) & 0x1 == 1 {
+            Ok(sign_extend(instr, 5))
+        } else {
+            register_storage.load(instr & 0x7)
+        }
+    };
+
+    let result = match Opcode::from_u16(instr >> 12) {
+        Some(Opcode::ADD) => sr1.wrapping_add(operand()?),
+        Some(Opcode::AND) => sr1 & operand()?,
+        Some(Opcode::XOR) => sr1 ^ operand()?,
+        Some(Opcode::SHF) => {
+            let amount = u32::from(instr & 0xF);
+            match (instr >> 4) & 0x3 {
+                0b00 | 0b10 => sr1 << amount,
+                0b01 => sr1 >> amount,
+                _ => ((sr1 as i16) >> amount) as u16,
+            }
+        }
+        Some(Opcode::LDB) => {
+            let byte = read_byte(memory, base_offset(register_storage, instr, false));
+            sign_extend(byte.into(), 8)
+        }
+        Some(Opcode::LDW) => read_word(memory, base_offset(register_storage, instr, true)),
+        Some(Opcode::STB) => {
+            let address = base_offset(register_storage, instr, false);
+            write_byte(memory, address, register_storage.load(dr)? as u8);
+            return Ok(());
+        }
+        Some(Opcode::STW) => {
+            let address = base_offset(register_storage, instr, true);
+            write_word(memory, address, register_storage.load(dr)?);
+            return Ok(());
+        }
+        Some(Opcode::BR) => {
+            if (instr >> 9) & register_storage.load(Registers::COND as u16)? != 0 {
+                let target = pc.wrapping_add(sign_extend(instr, 9) << 1);
+                register_storage.store(target, Registers::PC as u16)?;
+            }
+            return Ok(());
+        }
+        Some(Opcode::JMP) => {
+            register_storage.store(sr1, Registers::PC as u16)?;
+            return Ok(());
+        }
+        Some(Opcode::JSR) => {
+            let target = if (instr >> 11) & 0x1 == 1 {
+                pc.wrapping_add(sign_extend(instr, 11) << 1)
+            } else {
+                sr1
+            };
+            register_storage.store(pc, Registers::R7 as u16)?;
+            register_storage.store(target, Registers::PC as u16)?;
+            return Ok(());
+        }
+        Some(Opcode::LEA) => {
+            register_storage.store(pc.wrapping_add(sign_extend(instr, 9) << 1), dr)?;
+            return Ok(());
+        }
+        Some(Opcode::RTI) | Some(Opcode::TRAP) | None => {
+            return Err(InstructionSetError::BadOpcode(instr));
+        }
+    };
+
+    register_storage.store(result, dr)?;
+    register_storage.update_flags(dr)?;
+    Ok(())
+}
+
+/*
+the native trap routines on the LC-3b. PUTS and PUTSP both write the byte
+string at R0, since a string packed two characters per word is laid out
+the same as one with a character per byte
+*/
+pub fn trap(
+    register_storage: &mut RegisterStorage,
+    memory: &mut Memory,
+    console: &mut dyn Console,
+    instr: u16,
+) -> Result<(), InstructionSetError> {
+    register_storage.store(
+        register_storage.load(Registers::PC as u16)?,
+        Registers::R7 as u16,
+    )?;
+
+    match Trap::from_u16(instr & 0xFF) {
+        Some(Trap::PUTS) | Some(Trap::PUTSP) => {
+            let mut address = register_storage.load(Registers::R0 as u16)?;
+            for _ in 0..MEMORY_MAX {
+                let byte = read_byte(memory, address);
+                if byte == 0 {
+                    break;
+                }
+                console.write_byte(byte);
+                address = address.wrapping_add(1);
+            }
+            console.flush();
+            Ok(())
+        }
+        _ => Trap::execute_trap_instruction(register_storage, memory, console, instr),
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use tracing::info;
+
+use crate::libs::{
+    types::{Opcodes, Registers},
+    vm::Vm,
+};
+
+/* how often the wall-clock limit is checked, in instructions */
+const CLOCK_INTERVAL: u64 = 1024;
+
+/* register states remembered for loop detection before the window starts over */
+const LOOP_WINDOW: usize = 4096;
+
+/* bounds on a run; `None` means unlimited */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub max_output_bytes: Option<u64>,
+    /* stop on branches to themselves and on repeated machine states */
+    pub detect_loops: bool,
+}
+
+/* why a limited run stopped */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Halted,
+    InstructionLimit(u64),
+    Timeout(Duration),
+    OutputLimit(u64),
+    /* the machine can never leave the loop containing `pc` */
+    InfiniteLoop { pc: u16 },
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Halted => f.write_str("halted"),
+            StopReason::InstructionLimit(limit) => {
+                write!(f, "did not halt within {limit} instructions")
+            }
+            StopReason::Timeout(limit) => {
+                write!(f, "did not halt within {}ms", limit.as_millis())
+            }
+            StopReason::OutputLimit(limit) => write!(f, "wrote more than {limit} bytes"),
+            StopReason::InfiniteLoop { pc } => write!(f, "stuck in an infinite loop at {pc:#06x}"),
+        }
+    }
+}
+
+/*
+enforces `Limits` on a machine, checked between batches of instructions (see
+`budget`). limits count from the moment the tracker is created.
+
+a repeated machine state is only recognised between memory writes and traps:
+as long as neither happens memory and console are unchanged, so equal
+registers mean the machine is back where it was and will go round forever.
+attached devices change what reads return and may interrupt any loop, so
+with a device on the bus nothing is flagged
+*/
+pub struct LimitTracker {
+    limits: Limits,
+    started: Instant,
+    first_instruction: u64,
+    first_output_byte: u64,
+    seen: HashSet<[u16; Registers::COUNT as usize]>,
+}
+
+impl LimitTracker {
+    pub fn new(limits: Limits, vm: &Vm) -> Self {
+        Self {
+            limits,
+            started: Instant::now(),
+            first_instruction: vm.instruction_count,
+            first_output_byte: vm.output_bytes(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /* the reason to stop before executing the next instruction, if any */
+    pub fn check(&mut self, vm: &Vm) -> Option<StopReason> {
+        if vm.halted() {
+            return Some(StopReason::Halted);
+        }
+
+        let executed = vm.instruction_count - self.first_instruction;
+        if let Some(limit) = self.limits.max_instructions
+            && executed >= limit
+        {
+            return Some(StopReason::InstructionLimit(limit));
+        }
+        if let Some(limit) = self.limits.timeout
+            && executed.is_multiple_of(CLOCK_INTERVAL)
+            && self.started.elapsed() > limit
+        {
+            return Some(StopReason::Timeout(limit));
+        }
+        if let Some(limit) = self.limits.max_output_bytes
+            && vm.output_bytes() - self.first_output_byte > limit
+        {
+            return Some(StopReason::OutputLimit(limit));
+        }
+
+        if self.limits.detect_loops {
+            return self.detect_loop(vm);
+        }
+        None
+    }
+
+    /*
+    how many instructions may run before the next check: up to the
+    instruction limit or the next wall-clock check, whichever comes first.
+    loop detection looks at every instruction
+    */
+    pub fn budget(&self, vm: &Vm) -> u64 {
+        if self.limits.detect_loops {
+            return 1;
+        }
+
+        let executed = vm.instruction_count - self.first_instruction;
+        let mut budget = CLOCK_INTERVAL - executed % CLOCK_INTERVAL;
+        if let Some(limit) = self.limits.max_instructions {
+            budget = budget.min(limit.saturating_sub(executed));
+        }
+        budget.max(1)
+    }
+
+    /* the total output past which a run has to stop for a check */
+    pub fn output_ceiling(&self) -> Option<u64> {
+        self.limits
+            .max_output_bytes
+            .map(|limit| self.first_output_byte.saturating_add(limit))
+    }
+
+    fn detect_loop(&mut self, vm: &Vm) -> Option<StopReason> {
+        if !vm.memory.bus.is_empty() {
+            self.seen.clear();
+            return None;
+        }
+
+        let pc = vm.pc();
+        let instr = vm.memory.locations[pc as usize];
+        let registers = &vm.register_storage.locations;
+
+        match Opcodes::from_u16(instr >> 12) {
+            /* BR to itself with a condition that holds: nothing changes, ever */
+            Some(Opcodes::BR)
+                if instr & 0x1FF == 0x1FF
+                    && (instr >> 9) & registers[Registers::COND as usize] != 0 =>
+            {
+                return self.stuck(pc);
+            }
+            /* JMP through a register holding its own address */
+            Some(Opcodes::JMP) if registers[((instr >> 6) & 0x7) as usize] == pc => {
+                return self.stuck(pc);
+            }
+            /*
+            memory or console may change (exceptions push onto the supervisor
+            stack), so earlier states say nothing about later ones
+            */
+            Some(
+                Opcodes::ST
+                | Opcodes::STI
+                | Opcodes::STR
+                | Opcodes::TRAP
+                | Opcodes::RTI
+                | Opcodes::RES,
+            ) => {
+                self.seen.clear();
+                return None;
+            }
+            _ => {}
+        }
+
+        if self.seen.len() == LOOP_WINDOW {
+            self.seen.clear();
+        }
+        if !self.seen.insert(*registers) {
+            return self.stuck(pc);
+        }
+        None
+    }
+
+    fn stuck(&self, pc: u16) -> Option<StopReason> {
+        info!("infinite loop detected at {pc:#06x}");
+        Some(StopReason::InfiniteLoop { pc })
+    }
+}
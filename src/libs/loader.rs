@@ -0,0 +1,94 @@
+use std::{fs, io, path::Path};
+
+use thiserror::Error;
+use tracing::info;
+
+use crate::libs::{
+    constants::MEMORY_MAX,
+    types::{MemomryTrait, Memory},
+};
+
+#[derive(Debug, Error)]
+pub enum LoaderError {
+    #[error("Loader Error: image is empty")]
+    EmptyImage,
+    #[error("Loader Error: image has an odd number of bytes ({0})")]
+    OddLength(usize),
+    #[error("Loader Error: {words} words at origin {origin:#06x} do not fit in memory")]
+    ImageTooLarge { origin: u16, words: usize },
+    #[error("Loader Error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/*
+an LC-3 `.obj` image is a sequence of big-endian words:
+the first word is the origin, the rest are placed in memory starting there
+*/
+pub struct Image {
+    pub origin: u16,
+    pub words: Vec<u16>,
+}
+
+impl Image {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoaderError> {
+        if bytes.is_empty() {
+            return Err(LoaderError::EmptyImage);
+        }
+        if !bytes.len().is_multiple_of(2) {
+            return Err(LoaderError::OddLength(bytes.len()));
+        }
+
+        let mut words = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+
+        let origin = words.next().ok_or(LoaderError::EmptyImage)?;
+        let words: Vec<u16> = words.collect();
+
+        if origin as usize + words.len() > MEMORY_MAX {
+            return Err(LoaderError::ImageTooLarge {
+                origin,
+                words: words.len(),
+            });
+        }
+
+        Ok(Self { origin, words })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, LoaderError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        std::iter::once(self.origin)
+            .chain(self.words.iter().copied())
+            .flat_map(u16::to_be_bytes)
+            .collect()
+    }
+
+    pub fn load_into(&self, memory: &mut Memory) {
+        info!(
+            "loading {} words at origin {:#06x}",
+            self.words.len(),
+            self.origin
+        );
+
+        for (offset, word) in self.words.iter().enumerate() {
+            memory.write(self.origin.wrapping_add(offset as u16), *word);
+        }
+    }
+
+    /* load into LC-3b memory, where `origin` is a byte address and words are two bytes apart */
+    pub fn load_into_byte_addressed(&self, memory: &mut Memory) {
+        info!(
+            "loading {} words at byte origin {:#06x}",
+            self.words.len(),
+            self.origin
+        );
+
+        let origin = self.origin & !1;
+        for (offset, word) in self.words.iter().enumerate() {
+            memory.write(origin.wrapping_add(2 * offset as u16), *word);
+        }
+    }
+}
@@ -6,4 +6,50 @@ pub mod constants;
 
 pub mod instructions;
 
+pub mod lc3b;
+
+pub mod error;
+
 pub mod trap;
+
+pub mod console;
+
+pub mod bus;
+
+pub mod timer;
+
+pub mod framebuffer;
+
+pub mod disk;
+
+pub mod loader;
+
+pub mod asm;
+
+pub mod os;
+
+pub mod files;
+
+pub mod numeric;
+
+pub mod observer;
+
+pub mod replay;
+
+pub mod snapshot;
+
+pub mod threaded;
+
+pub mod vm;
+
+pub mod limits;
+
+pub mod protection;
+
+pub mod translate;
+
+pub mod golden;
+
+pub mod grade;
+
+pub mod fuzz;
@@ -0,0 +1,91 @@
+use crate::libs::{error::Fault, trap::TrapContext, types::Registers, vm::Vm};
+
+/* R0 as a signed decimal */
+pub const PRINT_DECIMAL: u8 = 0x26;
+/* R0 as an unsigned decimal */
+pub const PRINT_UNSIGNED: u8 = 0x27;
+/* R0 as four hex digits with an `x` prefix */
+pub const PRINT_HEX: u8 = 0x28;
+/* a decimal number from -32768 to 65535, with an optional `-` */
+pub const READ_DECIMAL: u8 = 0x29;
+/* up to four hex digits, with an optional `x` prefix */
+pub const READ_HEX: u8 = 0x2A;
+
+const VECTORS: [u8; 5] = [
+    PRINT_DECIMAL,
+    PRINT_UNSIGNED,
+    PRINT_HEX,
+    READ_DECIMAL,
+    READ_HEX,
+];
+
+/*
+register the numeric routines on `vm`; machines without them keep to the
+standard traps. the print routines write R0 without a newline. the read
+routines skip leading whitespace, consume input up to and including the
+next whitespace and leave the number in R0 with the condition codes set
+from it; malformed or out of range input reads as 0
+*/
+pub fn install(vm: &mut Vm) {
+    vm.register_trap(PRINT_DECIMAL, |context: &mut TrapContext| {
+        let text = (context.register(Registers::R0) as i16).to_string();
+        print(context, &text)
+    });
+    vm.register_trap(PRINT_UNSIGNED, |context: &mut TrapContext| {
+        let text = context.register(Registers::R0).to_string();
+        print(context, &text)
+    });
+    vm.register_trap(PRINT_HEX, |context: &mut TrapContext| {
+        let text = format!("x{:04X}", context.register(Registers::R0));
+        print(context, &text)
+    });
+    vm.register_trap(READ_DECIMAL, |context: &mut TrapContext| {
+        read(context, parse_decimal)
+    });
+    vm.register_trap(READ_HEX, |context: &mut TrapContext| {
+        read(context, parse_hex)
+    });
+}
+
+/* remove whatever is registered at the numeric vectors of `vm` */
+pub fn uninstall(vm: &mut Vm) {
+    for vector in VECTORS {
+        vm.unregister_trap(vector);
+    }
+}
+
+fn print(context: &mut TrapContext, text: &str) -> Result<(), Fault> {
+    context.console.write_str(text);
+    context.console.flush();
+    Ok(())
+}
+
+fn read(context: &mut TrapContext, parse: fn(&str) -> Option<u16>) -> Result<(), Fault> {
+    let mut word = String::new();
+    while let Some(byte) = context.console.read_byte() {
+        if !byte.is_ascii_whitespace() {
+            word.push(byte as char);
+        } else if !word.is_empty() {
+            break;
+        }
+    }
+
+    context.set_register(Registers::R0, parse(&word).unwrap_or(0));
+    context.update_flags(Registers::R0);
+    Ok(())
+}
+
+pub fn parse_decimal(word: &str) -> Option<u16> {
+    let value: i32 = word.parse().ok()?;
+    (i32::from(i16::MIN)..=i32::from(u16::MAX))
+        .contains(&value)
+        .then_some(value as u16)
+}
+
+pub fn parse_hex(word: &str) -> Option<u16> {
+    let digits = word.strip_prefix(['x', 'X']).unwrap_or(word);
+    if digits.is_empty() || digits.len() > 4 || digits.starts_with('+') {
+        return None;
+    }
+    u16::from_str_radix(digits, 16).ok()
+}
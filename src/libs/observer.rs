@@ -0,0 +1,80 @@
+use std::{fmt, rc::Rc};
+
+/*
+hooks for register and memory events. every method defaults to a no-op, so an
+observer only implements the events it cares about.
+
+the hooks are only compiled in with the `instrument` cargo feature; without it
+`RegisterStorage` and `Memory` carry no observer and pay nothing per access
+*/
+pub trait Observer {
+    fn register_load(&self, _register: u16, _value: u16) {}
+    fn register_store(&self, _register: u16, _value: u16) {}
+    fn memory_read(&self, _address: u16, _value: u16) {}
+    fn memory_write(&self, _address: u16, _value: u16) {}
+}
+
+/* logs every access through `tracing`, as the storage types used to do unconditionally */
+#[cfg(feature = "trace-access")]
+pub struct TracingObserver;
+
+#[cfg(feature = "trace-access")]
+impl Observer for TracingObserver {
+    fn register_load(&self, register: u16, value: u16) {
+        tracing::info!("loading {value:#06x} from register: {register}");
+    }
+
+    fn register_store(&self, register: u16, value: u16) {
+        tracing::info!("storing {value:#06x} to register: {register}");
+    }
+
+    fn memory_read(&self, address: u16, value: u16) {
+        tracing::info!("reading {value:#06x} from memory address: {address:#06x}");
+    }
+
+    fn memory_write(&self, address: u16, value: u16) {
+        tracing::info!("storing {value:#06x} to memory address: {address:#06x}");
+    }
+}
+
+/* the observer attached to a storage type; shared so registers and memory can report to the same one */
+#[derive(Clone)]
+pub struct ObserverSlot(Option<Rc<dyn Observer>>);
+
+impl ObserverSlot {
+    pub fn new(observer: Option<Rc<dyn Observer>>) -> Self {
+        Self(observer)
+    }
+
+    #[inline]
+    pub fn get(&self) -> Option<&dyn Observer> {
+        self.0.as_deref()
+    }
+}
+
+impl Default for ObserverSlot {
+    fn default() -> Self {
+        Self(default_observer())
+    }
+}
+
+/* with `trace-access` every new machine starts out traced */
+#[cfg(feature = "trace-access")]
+fn default_observer() -> Option<Rc<dyn Observer>> {
+    Some(Rc::new(TracingObserver))
+}
+
+#[cfg(not(feature = "trace-access"))]
+fn default_observer() -> Option<Rc<dyn Observer>> {
+    None
+}
+
+impl fmt::Debug for ObserverSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "ObserverSlot(attached)"
+        } else {
+            "ObserverSlot(none)"
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+use tracing::info;
+
+use crate::libs::{
+    asm::{self, AsmError},
+    bus::{self, BusError},
+    constants::{INTERRUPT_VECTOR_TABLE, TRAP_VECTOR_TABLE},
+    loader::Image,
+    vm::{Isa, Vm},
+};
+
+#[derive(Debug, Error)]
+pub enum OsError {
+    #[error("OS Error: {0}")]
+    Asm(#[from] AsmError),
+    #[error("OS Error: {0}")]
+    Bus(#[from] BusError),
+    #[error("OS Error: the OS runs on the LC-3 only, not {0:?}")]
+    UnsupportedIsa(Isa),
+}
+
+/* source of the bundled operating system */
+pub const SOURCE: &str = include_str!("../../os/lc3os.asm");
+
+/*
+an operating system image for the machine to boot: trap and interrupt
+vector tables and the service routines they point at. zero entries of the
+tables are pointed at the `BAD_TRAP` and `BAD_INTERRUPT` routines, when the
+image defines them
+*/
+pub struct Os {
+    pub image: Image,
+    pub symbols: HashMap<String, u16>,
+}
+
+impl Os {
+    /* the OS in `os/lc3os.asm`, with trap routines for GETC, OUT, PUTS, IN, PUTSP and HALT */
+    pub fn bundled() -> Self {
+        Self::assemble(SOURCE).expect("the bundled OS assembles")
+    }
+
+    pub fn assemble(source: &str) -> Result<Self, AsmError> {
+        let program = asm::assemble(source)?;
+        Ok(Self {
+            image: program.image,
+            symbols: program.symbols,
+        })
+    }
+
+    /*
+    load the OS into `vm`, attach the keyboard and display it drives to the
+    machine's console, and send TRAPs through the trap vector table.
+    exceptions are enabled so that the routines can return with RTI. a
+    console set on the machine afterwards is not seen by the devices
+    */
+    pub fn boot(&self, vm: &mut Vm) -> Result<(), OsError> {
+        if vm.isa() != Isa::Lc3 {
+            return Err(OsError::UnsupportedIsa(vm.isa()));
+        }
+        let console = vm.share_console();
+        bus::attach_console_devices(&mut vm.memory.bus, console)?;
+
+        vm.load_image(&self.image);
+        for (table, default) in [
+            (TRAP_VECTOR_TABLE, "BAD_TRAP"),
+            (INTERRUPT_VECTOR_TABLE, "BAD_INTERRUPT"),
+        ] {
+            let Some(routine) = self.symbols.get(default) else {
+                continue;
+            };
+            for vector in 0..0x100 {
+                if vm.memory.locations[(table + vector) as usize] == 0 {
+                    vm.write_memory(table + vector, *routine);
+                }
+            }
+        }
+
+        vm.set_trap_table(true);
+        vm.set_exceptions(true);
+        info!("booted OS of {} words", self.image.words.len());
+        Ok(())
+    }
+}
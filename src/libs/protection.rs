@@ -0,0 +1,180 @@
+use std::{fmt, ops::BitOr};
+
+use crate::libs::types::{Memory, Opcodes, RegisterStorage};
+
+/* the processor's privilege mode, PSR[15] */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Privilege {
+    Supervisor,
+    /* programs loaded at x3000 run in user mode, as under the LC-3 OS */
+    #[default]
+    User,
+}
+
+/* a set of permitted access kinds */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access(u8);
+
+impl Access {
+    pub const NONE: Access = Access(0);
+    pub const READ: Access = Access(0b001);
+    pub const WRITE: Access = Access(0b010);
+    pub const EXECUTE: Access = Access(0b100);
+    pub const ALL: Access = Access(0b111);
+
+    pub fn allows(self, kind: AccessKind) -> bool {
+        let bit = match kind {
+            AccessKind::Read => Self::READ,
+            AccessKind::Write => Self::WRITE,
+            AccessKind::Execute => Self::EXECUTE,
+        };
+        self.0 & bit.0 != 0
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /* the set of the access kinds in `bits`, as `bits` returns them */
+    pub fn from_bits(bits: u8) -> Self {
+        Access(bits & Self::ALL.0)
+    }
+}
+
+impl BitOr for Access {
+    type Output = Access;
+
+    fn bitor(self, rhs: Access) -> Access {
+        Access(self.0 | rhs.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+            AccessKind::Execute => "execute",
+        })
+    }
+}
+
+/* the addresses `start..=end` and what each privilege mode may do there */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u16,
+    pub end: u16,
+    pub user: Access,
+    pub supervisor: Access,
+}
+
+impl Region {
+    /* accessible in supervisor mode only */
+    pub fn system(start: u16, end: u16) -> Self {
+        Self {
+            start,
+            end,
+            user: Access::NONE,
+            supervisor: Access::ALL,
+        }
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+/* an access the running program was not allowed to make */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    pub address: u16,
+    pub kind: AccessKind,
+}
+
+/*
+access rules for memory. the first region containing an address decides;
+addresses outside every region are unrestricted.
+
+accesses are checked before an instruction executes, from the effective
+addresses it is about to use, so a violating instruction has no effect. the
+machine itself (loading images, exception stack frames) is not restricted
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Protection {
+    pub regions: Vec<Region>,
+}
+
+impl Protection {
+    /* system space `0x0000–0x2FFF` and the device registers `0xFE00–0xFFFF` are privileged */
+    pub fn lc3() -> Self {
+        Self {
+            regions: vec![
+                Region::system(0x0000, 0x2FFF),
+                Region::system(0xFE00, 0xFFFF),
+            ],
+        }
+    }
+
+    pub fn allows(&self, address: u16, kind: AccessKind, privilege: Privilege) -> bool {
+        match self.regions.iter().find(|region| region.contains(address)) {
+            Some(region) => match privilege {
+                Privilege::User => region.user.allows(kind),
+                Privilege::Supervisor => region.supervisor.allows(kind),
+            },
+            None => true,
+        }
+    }
+
+    /* the first access of `instr`, fetched from `pc`, that `privilege` does not permit */
+    pub fn check(
+        &self,
+        privilege: Privilege,
+        pc: u16,
+        instr: u16,
+        register_storage: &RegisterStorage,
+        memory: &Memory,
+    ) -> Result<(), Violation> {
+        let check = |address, kind| {
+            if self.allows(address, kind, privilege) {
+                Ok(())
+            } else {
+                Err(Violation { address, kind })
+            }
+        };
+
+        check(pc, AccessKind::Execute)?;
+
+        let next_pc = pc.wrapping_add(1);
+        let pc_offset9 = next_pc.wrapping_add(sign_extend(instr, 9));
+        let base_offset6 = register_storage.locations[((instr >> 6) & 0x7) as usize]
+            .wrapping_add(sign_extend(instr, 6));
+
+        match Opcodes::from_u16(instr >> 12) {
+            Some(Opcodes::LD) => check(pc_offset9, AccessKind::Read),
+            Some(Opcodes::ST) => check(pc_offset9, AccessKind::Write),
+            Some(Opcodes::LDR) => check(base_offset6, AccessKind::Read),
+            Some(Opcodes::STR) => check(base_offset6, AccessKind::Write),
+            Some(Opcodes::LDI) => {
+                check(pc_offset9, AccessKind::Read)?;
+                check(memory.peek(pc_offset9), AccessKind::Read)
+            }
+            Some(Opcodes::STI) => {
+                check(pc_offset9, AccessKind::Read)?;
+                check(memory.peek(pc_offset9), AccessKind::Write)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn sign_extend(instr: u16, bit_count: u32) -> u16 {
+    let shift = 16 - bit_count;
+    (((instr << shift) as i16) >> shift) as u16
+}
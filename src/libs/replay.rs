@@ -0,0 +1,286 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use thiserror::Error;
+use tracing::warn;
+
+use crate::libs::console::{Console, ConsoleState};
+
+const RECORDING_HEADER: &str = "# lc3 input recording v1";
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("Replay Error: missing header `{RECORDING_HEADER}`")]
+    MissingHeader,
+    #[error("Replay Error: malformed event on line {0}")]
+    MalformedEvent(usize),
+    #[error("Replay Error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/*
+one read from the console: the number of instructions completed before the
+instruction that consumed it, and the byte (`None` when no input was available)
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub instruction: u64,
+    pub byte: Option<u8>,
+}
+
+/*
+every input event of a session, in order. stored as text, one event per line:
+`<instruction> <byte as hex>` or `<instruction> eof`
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputRecording {
+    pub events: Vec<InputEvent>,
+}
+
+impl InputRecording {
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{RECORDING_HEADER}\n");
+        for event in &self.events {
+            let _ = writeln!(out, "{}", format_event(event));
+        }
+        out
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, ReplayError> {
+        let mut lines = text.lines().enumerate();
+
+        match lines.next() {
+            Some((_, header)) if header.trim() == RECORDING_HEADER => {}
+            _ => return Err(ReplayError::MissingHeader),
+        }
+
+        let events = lines
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(index, line)| parse_event(line).ok_or(ReplayError::MalformedEvent(index + 1)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { events })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+}
+
+fn format_event(event: &InputEvent) -> String {
+    match event.byte {
+        Some(byte) => format!("{} {byte:#04x}", event.instruction),
+        None => format!("{} eof", event.instruction),
+    }
+}
+
+fn parse_event(line: &str) -> Option<InputEvent> {
+    let (instruction, byte) = line.trim().split_once(' ')?;
+    let instruction = instruction.parse().ok()?;
+    let byte = match byte.trim() {
+        "eof" => None,
+        hex => Some(u8::from_str_radix(hex.strip_prefix("0x")?, 16).ok()?),
+    };
+    Some(InputEvent { instruction, byte })
+}
+
+/*
+wraps a console and records every input event it delivers. with a sink the
+events are also appended to a file as they happen, so the recording survives
+the process ending abruptly
+*/
+pub struct RecordingConsole<C: Console> {
+    inner: C,
+    now: u64,
+    recording: Rc<RefCell<InputRecording>>,
+    sink: Option<File>,
+}
+
+impl<C: Console> RecordingConsole<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            now: 0,
+            recording: Rc::default(),
+            sink: None,
+        }
+    }
+
+    pub fn with_sink(inner: C, path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let mut sink = File::create(path)?;
+        writeln!(sink, "{RECORDING_HEADER}")?;
+
+        Ok(Self {
+            sink: Some(sink),
+            ..Self::new(inner)
+        })
+    }
+
+    /* shared handle to the events recorded so far */
+    pub fn recording(&self) -> Rc<RefCell<InputRecording>> {
+        Rc::clone(&self.recording)
+    }
+
+    fn record(&mut self, byte: Option<u8>) {
+        let event = InputEvent {
+            instruction: self.now,
+            byte,
+        };
+
+        if let Some(sink) = &mut self.sink
+            && let Err(err) = writeln!(sink, "{}", format_event(&event))
+        {
+            warn!("failed to write input recording: {err}");
+        }
+        self.recording.borrow_mut().events.push(event);
+    }
+}
+
+impl<C: Console> Console for RecordingConsole<C> {
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.inner.read_byte();
+        self.record(byte);
+        byte
+    }
+
+    fn poll_byte(&mut self) -> Option<u8> {
+        let byte = self.inner.poll_byte();
+        if byte.is_some() {
+            self.record(byte);
+        }
+        byte
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.inner.write_byte(byte);
+    }
+
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+
+    fn set_instruction_count(&mut self, count: u64) {
+        self.now = count;
+        self.inner.set_instruction_count(count);
+    }
+
+    fn state(&self) -> Option<ConsoleState> {
+        self.inner.state()
+    }
+
+    fn restore(&mut self, state: ConsoleState) {
+        self.inner.restore(state);
+    }
+}
+
+/* where a replayed session stopped matching its recording */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    pub event: usize,
+    pub expected_instruction: u64,
+    pub actual_instruction: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayStatus {
+    pub consumed: usize,
+    pub divergence: Option<Divergence>,
+}
+
+/*
+feeds a recording back to the machine in order, ignoring the host's input.
+output still goes to the wrapped console. a read at a different instruction
+than recorded means the program took a different path; the first such read
+is reported through the status handle
+*/
+pub struct ReplayConsole<C: Console> {
+    output: C,
+    now: u64,
+    events: VecDeque<InputEvent>,
+    status: Rc<RefCell<ReplayStatus>>,
+}
+
+impl<C: Console> ReplayConsole<C> {
+    pub fn new(recording: InputRecording, output: C) -> Self {
+        Self {
+            output,
+            now: 0,
+            events: recording.events.into(),
+            status: Rc::default(),
+        }
+    }
+
+    pub fn status(&self) -> Rc<RefCell<ReplayStatus>> {
+        Rc::clone(&self.status)
+    }
+}
+
+impl<C: Console> Console for ReplayConsole<C> {
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut status = self.status.borrow_mut();
+
+        /* past the end of the recording the input stream is simply exhausted */
+        let event = self.events.pop_front()?;
+
+        if event.instruction != self.now && status.divergence.is_none() {
+            warn!(
+                "replay diverged at event {}: recorded at instruction {}, read at {}",
+                status.consumed, event.instruction, self.now
+            );
+            status.divergence = Some(Divergence {
+                event: status.consumed,
+                expected_instruction: event.instruction,
+                actual_instruction: self.now,
+            });
+        }
+        status.consumed += 1;
+
+        event.byte
+    }
+
+    /*
+    a poll gets the next recorded byte once the machine has reached the
+    instruction it arrived at. the recording has no empty polls to consume
+    */
+    fn poll_byte(&mut self) -> Option<u8> {
+        match self.events.front() {
+            Some(event) if event.byte.is_some() && event.instruction <= self.now => {
+                self.read_byte()
+            }
+            _ => None,
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.output.write_byte(byte);
+    }
+
+    fn flush(&mut self) {
+        self.output.flush();
+    }
+
+    fn set_instruction_count(&mut self, count: u64) {
+        self.now = count;
+        self.output.set_instruction_count(count);
+    }
+
+    fn state(&self) -> Option<ConsoleState> {
+        self.output.state()
+    }
+
+    fn restore(&mut self, state: ConsoleState) {
+        self.output.restore(state);
+    }
+}
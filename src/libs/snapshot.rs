@@ -0,0 +1,409 @@
+use std::{fs, io, path::Path};
+
+use thiserror::Error;
+use tracing::info;
+
+use crate::libs::{
+    console::ConsoleState,
+    constants::MEMORY_MAX,
+    instructions::Revision,
+    protection::{Access, Protection, Region},
+    types::Registers,
+    vm::{Engine, Isa, Vm},
+};
+
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"LC3SNAP\0";
+pub const SNAPSHOT_VERSION: u16 = 3;
+
+/* section tags; readers skip sections they do not know */
+const REGISTERS: &[u8; 4] = b"REGS";
+const MEMORY: &[u8; 4] = b"MEMY";
+const COUNTERS: &[u8; 4] = b"CNTR";
+const CONSOLE: &[u8; 4] = b"CONS";
+const STATUS: &[u8; 4] = b"PSR ";
+const DEVICES: &[u8; 4] = b"DEVS";
+const CONFIG: &[u8; 4] = b"CONF";
+
+/* `Config::flags` bits */
+const EXCEPTIONS: u16 = 1 << 0;
+const TRAP_TABLE: u16 = 1 << 1;
+const ARITHMETIC_EXTENSION: u16 = 1 << 2;
+const PROTECTION: u16 = 1 << 3;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Snapshot Error: not a snapshot file")]
+    BadMagic,
+    #[error("Snapshot Error: unsupported version {0} (only {SNAPSHOT_VERSION} is supported)")]
+    UnsupportedVersion(u16),
+    #[error("Snapshot Error: truncated data")]
+    Truncated,
+    #[error("Snapshot Error: section {0} has the wrong size")]
+    BadSection(String),
+    #[error("Snapshot Error: missing section {0}")]
+    MissingSection(String),
+    #[error("Snapshot Error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/* how the machine executes, as selected by its setters */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub isa: Isa,
+    pub engine: Engine,
+    pub revision: Revision,
+    pub exceptions: bool,
+    pub trap_table: bool,
+    pub arithmetic_extension: bool,
+    pub protection: Option<Protection>,
+}
+
+impl Config {
+    pub fn capture(vm: &Vm) -> Self {
+        Self {
+            isa: vm.isa(),
+            engine: vm.engine(),
+            revision: vm.revision(),
+            exceptions: vm.exceptions(),
+            trap_table: vm.trap_table(),
+            arithmetic_extension: vm.arithmetic_extension(),
+            protection: vm.protection().cloned(),
+        }
+    }
+
+    pub fn apply(&self, vm: &mut Vm) {
+        vm.set_isa(self.isa);
+        vm.set_engine(self.engine);
+        vm.set_revision(self.revision);
+        vm.set_exceptions(self.exceptions);
+        vm.set_trap_table(self.trap_table);
+        vm.set_arithmetic_extension(self.arithmetic_extension);
+        vm.set_protection(self.protection.clone());
+    }
+
+    /* the selections, the flags and the protection regions, as words */
+    fn to_words(&self) -> Vec<u16> {
+        let mut flags = 0;
+        for (set, flag) in [
+            (self.exceptions, EXCEPTIONS),
+            (self.trap_table, TRAP_TABLE),
+            (self.arithmetic_extension, ARITHMETIC_EXTENSION),
+            (self.protection.is_some(), PROTECTION),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+
+        let mut words = vec![
+            self.isa as u16,
+            self.engine as u16,
+            self.revision as u16,
+            flags,
+        ];
+        if let Some(protection) = &self.protection {
+            words.push(protection.regions.len() as u16);
+            for region in &protection.regions {
+                words.extend([
+                    region.start,
+                    region.end,
+                    u16::from(region.user.bits()),
+                    u16::from(region.supervisor.bits()),
+                ]);
+            }
+        }
+        words
+    }
+
+    /* read back what `to_words` wrote */
+    fn read(section: &mut Reader) -> Result<Self, SnapshotError> {
+        let bad_section = || SnapshotError::BadSection(tag_name(CONFIG));
+
+        let isa = match section.u16()? {
+            0 => Isa::Lc3,
+            1 => Isa::Lc3b,
+            _ => return Err(bad_section()),
+        };
+        let engine = match section.u16()? {
+            0 => Engine::Interpreter,
+            1 => Engine::Threaded,
+            _ => return Err(bad_section()),
+        };
+        let revision = match section.u16()? {
+            0 => Revision::Legacy,
+            1 => Revision::Strict,
+            _ => return Err(bad_section()),
+        };
+        let flags = section.u16()?;
+
+        let protection = if flags & PROTECTION != 0 {
+            let mut regions = Vec::new();
+            for _ in 0..section.u16()? {
+                let [start, end, user, supervisor] = section.words(4)?[..] else {
+                    return Err(bad_section());
+                };
+                regions.push(Region {
+                    start,
+                    end,
+                    user: Access::from_bits(user as u8),
+                    supervisor: Access::from_bits(supervisor as u8),
+                });
+            }
+            Some(Protection { regions })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            isa,
+            engine,
+            revision,
+            exceptions: flags & EXCEPTIONS != 0,
+            trap_table: flags & TRAP_TABLE != 0,
+            arithmetic_extension: flags & ARITHMETIC_EXTENSION != 0,
+            protection,
+        })
+    }
+}
+
+/*
+the complete state of a machine at an instruction boundary, along with the
+configuration it runs under.
+
+on disk a snapshot is the magic, a big-endian version word and a list of
+sections, each a 4-byte tag, a big-endian u32 length and the payload
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub registers: [u16; Registers::COUNT as usize],
+    pub memory: Vec<u16>,
+    pub instruction_count: u64,
+    pub console: Option<ConsoleState>,
+    /* processor status and the stack pointer saved for the other privilege mode */
+    pub psr: u16,
+    pub saved_ssp: u16,
+    pub saved_usp: u16,
+    /* device state by device name, including their pending interrupts; see `Bus::save` */
+    pub devices: Vec<(String, Vec<u16>)>,
+    pub config: Config,
+}
+
+impl Snapshot {
+    pub fn capture(vm: &Vm) -> Self {
+        Self {
+            registers: vm.register_storage.locations,
+            memory: vm.memory.locations.to_vec(),
+            instruction_count: vm.instruction_count,
+            console: vm.console.state(),
+            psr: vm.psr(),
+            saved_ssp: vm.saved_ssp,
+            saved_usp: vm.saved_usp,
+            devices: vm.memory.bus.save(),
+            config: Config::capture(vm),
+        }
+    }
+
+    /*
+    restore onto `vm`, whose devices should be attached already. the
+    snapshot's configuration replaces the machine's
+    */
+    pub fn restore(&self, vm: &mut Vm) {
+        info!(
+            "restoring snapshot taken after {} instructions",
+            self.instruction_count
+        );
+
+        self.config.apply(vm);
+        vm.register_storage.locations = self.registers;
+        vm.memory.locations.copy_from_slice(&self.memory);
+        vm.instruction_count = self.instruction_count;
+        vm.set_psr(self.psr);
+        vm.saved_ssp = self.saved_ssp;
+        vm.saved_usp = self.saved_usp;
+        vm.memory.bus.restore(&self.devices);
+        if let Some(console) = &self.console {
+            vm.console.restore(console.clone());
+        }
+        vm.flush_block_cache();
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = SNAPSHOT_MAGIC.to_vec();
+        out.extend(SNAPSHOT_VERSION.to_be_bytes());
+
+        let registers: Vec<u8> = self
+            .registers
+            .iter()
+            .flat_map(|r| r.to_be_bytes())
+            .collect();
+        write_section(&mut out, REGISTERS, &registers);
+
+        let memory: Vec<u8> = self.memory.iter().flat_map(|w| w.to_be_bytes()).collect();
+        write_section(&mut out, MEMORY, &memory);
+
+        write_section(&mut out, COUNTERS, &self.instruction_count.to_be_bytes());
+
+        if let Some(console) = &self.console {
+            let mut payload = Vec::new();
+            for buffer in [&console.input, &console.output] {
+                payload.extend((buffer.len() as u32).to_be_bytes());
+                payload.extend(buffer);
+            }
+            write_section(&mut out, CONSOLE, &payload);
+        }
+
+        let status: Vec<u8> = [self.psr, self.saved_ssp, self.saved_usp]
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect();
+        write_section(&mut out, STATUS, &status);
+
+        if !self.devices.is_empty() {
+            let mut payload = (self.devices.len() as u32).to_be_bytes().to_vec();
+            for (name, state) in &self.devices {
+                payload.extend((name.len() as u32).to_be_bytes());
+                payload.extend(name.as_bytes());
+                payload.extend((state.len() as u32).to_be_bytes());
+                payload.extend(state.iter().flat_map(|w| w.to_be_bytes()));
+            }
+            write_section(&mut out, DEVICES, &payload);
+        }
+
+        let config: Vec<u8> = self
+            .config
+            .to_words()
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect();
+        write_section(&mut out, CONFIG, &config);
+
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader(bytes);
+
+        if reader.take(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut registers = None;
+        let mut memory = None;
+        let mut instruction_count = None;
+        let mut console = None;
+        let mut status = None;
+        let mut devices = Vec::new();
+        let mut config = None;
+
+        while !reader.0.is_empty() {
+            let tag: [u8; 4] = reader.take(4)?.try_into().unwrap();
+            let length = reader.u32()? as usize;
+            let mut section = Reader(reader.take(length)?);
+            let bad_section = || SnapshotError::BadSection(tag_name(&tag));
+
+            match &tag {
+                REGISTERS => {
+                    let words = section.words(Registers::COUNT as usize)?;
+                    registers = Some(words.try_into().map_err(|_| bad_section())?);
+                }
+                MEMORY => memory = Some(section.words(MEMORY_MAX)?),
+                COUNTERS => instruction_count = Some(section.u64()?),
+                CONSOLE => {
+                    let input = section.bytes()?;
+                    let output = section.bytes()?;
+                    console = Some(ConsoleState { input, output });
+                }
+                STATUS => status = Some(section.words(3)?),
+                DEVICES => {
+                    for _ in 0..section.u32()? {
+                        let name =
+                            String::from_utf8(section.bytes()?).map_err(|_| bad_section())?;
+                        let length = section.u32()? as usize;
+                        devices.push((name, section.words(length)?));
+                    }
+                }
+                CONFIG => config = Some(Config::read(&mut section)?),
+                _ => continue,
+            }
+
+            if !section.0.is_empty() {
+                return Err(bad_section());
+            }
+        }
+
+        let missing = |tag: &[u8; 4]| SnapshotError::MissingSection(tag_name(tag));
+        let registers: [u16; Registers::COUNT as usize] =
+            registers.ok_or_else(|| missing(REGISTERS))?;
+
+        let status = status.ok_or_else(|| missing(STATUS))?;
+
+        Ok(Self {
+            registers,
+            memory: memory.ok_or_else(|| missing(MEMORY))?,
+            instruction_count: instruction_count.ok_or_else(|| missing(COUNTERS))?,
+            console,
+            psr: status[0],
+            saved_ssp: status[1],
+            saved_usp: status[2],
+            devices,
+            config: config.ok_or_else(|| missing(CONFIG))?,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+fn write_section(out: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    out.extend(tag);
+    out.extend((payload.len() as u32).to_be_bytes());
+    out.extend(payload);
+}
+
+fn tag_name(tag: &[u8; 4]) -> String {
+    String::from_utf8_lossy(tag).into_owned()
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.0.len() < count {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, tail) = self.0.split_at(count);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn words(&mut self, count: usize) -> Result<Vec<u16>, SnapshotError> {
+        (0..count).map(|_| self.u16()).collect()
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, SnapshotError> {
+        let length = self.u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+}
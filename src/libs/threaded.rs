@@ -0,0 +1,450 @@
+use std::rc::Rc;
+
+use tracing::debug;
+
+use crate::libs::{
+    console::Console,
+    constants::{CLOCK_ENABLE, MCR, MEMORY_MAX},
+    error::VmError,
+    instructions::{InstructionSet, InstructionSetError, Instructions, Revision},
+    protection::{Privilege, Protection},
+    types::{ConditionalFlags, MemomryTrait, Memory, Opcodes, RegisterStorage, Registers},
+};
+
+/* code is tracked in pages of 256 words for invalidation */
+const PAGE_SHIFT: u16 = 8;
+const PAGE_COUNT: usize = MEMORY_MAX >> PAGE_SHIFT;
+
+/* upper bound on the number of instructions compiled into a single block */
+pub const MAX_BLOCK_LEN: usize = 64;
+
+type Handler =
+    fn(&mut RegisterStorage, &mut Memory, &mut dyn Console, u16) -> Result<(), InstructionSetError>;
+
+/*
+pre-decoded form of one instruction. the hot ALU, load/store and branch
+instructions have their operands (and PC-relative addresses) resolved at
+compile time; everything else is bound directly to its `Instructions` handler
+*/
+#[derive(Clone, Copy)]
+enum MicroOp {
+    AddReg {
+        dr: usize,
+        sr1: usize,
+        sr2: usize,
+    },
+    AddImm {
+        dr: usize,
+        sr1: usize,
+        imm: u16,
+    },
+    AndReg {
+        dr: usize,
+        sr1: usize,
+        sr2: usize,
+    },
+    AndImm {
+        dr: usize,
+        sr1: usize,
+        imm: u16,
+    },
+    Not {
+        dr: usize,
+        sr: usize,
+    },
+    Lea {
+        dr: usize,
+        address: u16,
+        flags: bool,
+    },
+    Ld {
+        dr: usize,
+        address: u16,
+    },
+    St {
+        sr: usize,
+        address: u16,
+    },
+    Br {
+        nzp: u16,
+        target: u16,
+    },
+    /* left to the caller, which runs the trap routines */
+    Trap,
+    Bound {
+        handler: Handler,
+        instr: u16,
+    },
+}
+
+pub struct Block {
+    pub start: u16,
+    ops: Vec<MicroOp>,
+}
+
+impl Block {
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        address.wrapping_sub(self.start) < self.ops.len() as u16
+    }
+
+    fn pages(&self) -> impl Iterator<Item = usize> {
+        let first = self.start >> PAGE_SHIFT;
+        let last = self.start.wrapping_add(self.ops.len() as u16 - 1) >> PAGE_SHIFT;
+        (first..=last).map(usize::from)
+    }
+}
+
+/*
+cache of compiled basic blocks, indexed by their start address. a block is
+invalidated as soon as anything writes to one of the words it was compiled from
+*/
+pub struct BlockCache {
+    blocks: Vec<Option<Rc<Block>>>,
+    pages: Vec<Vec<u16>>,
+    arithmetic_extension: bool,
+    revision: Revision,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockCache {
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![None; MEMORY_MAX],
+            pages: vec![Vec::new(); PAGE_COUNT],
+            arithmetic_extension: false,
+            revision: Revision::default(),
+        }
+    }
+
+    /* compile the reserved opcode as the arithmetic extension; drops every block */
+    pub fn set_arithmetic_extension(&mut self, enabled: bool) {
+        self.arithmetic_extension = enabled;
+        self.clear();
+    }
+
+    /* compile for the ISA revision `revision`; drops every block */
+    pub fn set_revision(&mut self, revision: Revision) {
+        self.revision = revision;
+        self.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.iter().all(Vec::is_empty)
+    }
+
+    pub fn get(&self, start: u16) -> Option<Rc<Block>> {
+        self.blocks[start as usize].clone()
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.iter_mut().for_each(|block| *block = None);
+        self.pages.iter_mut().for_each(Vec::clear);
+    }
+
+    /* drop every block compiled from `address`; returns whether any were dropped */
+    pub fn invalidate(&mut self, address: u16) -> bool {
+        let page = (address >> PAGE_SHIFT) as usize;
+
+        /* the page index narrows the search to blocks that could contain the address */
+        let blocks = &self.blocks;
+        let stale: Vec<u16> = self.pages[page]
+            .iter()
+            .copied()
+            .filter(|start| {
+                blocks[*start as usize]
+                    .as_ref()
+                    .is_some_and(|block| block.contains(address))
+            })
+            .collect();
+
+        for start in &stale {
+            if let Some(block) = self.blocks[*start as usize].take() {
+                debug!("invalidating block at {start:#06x}");
+                for page in block.pages() {
+                    self.pages[page].retain(|s| s != start);
+                }
+            }
+        }
+        !stale.is_empty()
+    }
+
+    pub fn lookup_or_compile(&mut self, start: u16, memory: &Memory) -> Rc<Block> {
+        if let Some(block) = &self.blocks[start as usize] {
+            return Rc::clone(block);
+        }
+
+        let block = Rc::new(self.compile(start, memory));
+        debug!("compiled block at {start:#06x} ({} ops)", block.len());
+
+        for page in block.pages() {
+            self.pages[page].push(start);
+        }
+        self.blocks[start as usize] = Some(Rc::clone(&block));
+        block
+    }
+
+    /* discover the basic block starting at `start`, ending at the first BR/JMP/JSR/TRAP (or illegal opcode) */
+    pub fn compile(&self, start: u16, memory: &Memory) -> Block {
+        let mut ops = Vec::new();
+        let mut address = start;
+
+        loop {
+            /* decoding ahead is not an access: devices in the way see no reads */
+            let instr = memory.peek(address);
+            let (op, terminator) = self.decode(address, instr);
+            ops.push(op);
+
+            if terminator || ops.len() == MAX_BLOCK_LEN || address == u16::MAX {
+                break;
+            }
+            address += 1;
+        }
+
+        Block { start, ops }
+    }
+
+    fn decode(&self, address: u16, instr: u16) -> (MicroOp, bool) {
+        let next_pc = address.wrapping_add(1);
+        let dr = ((instr >> 9) & 0x7) as usize;
+        let sr1 = ((instr >> 6) & 0x7) as usize;
+        let imm_mode = (instr >> 5) & 0x1 == 1;
+        let imm5 = sign_extend(instr, 5);
+        let pc_offset9 = next_pc.wrapping_add(sign_extend(instr, 9));
+
+        let bound = |handler: Handler| MicroOp::Bound { handler, instr };
+
+        match Opcodes::from_u16(instr >> 12) {
+            Some(Opcodes::ADD) if imm_mode => (MicroOp::AddImm { dr, sr1, imm: imm5 }, false),
+            Some(Opcodes::ADD) => {
+                let sr2 = (instr & 0x7) as usize;
+                (MicroOp::AddReg { dr, sr1, sr2 }, false)
+            }
+            Some(Opcodes::AND) if imm_mode => (MicroOp::AndImm { dr, sr1, imm: imm5 }, false),
+            Some(Opcodes::AND) => {
+                let sr2 = (instr & 0x7) as usize;
+                (MicroOp::AndReg { dr, sr1, sr2 }, false)
+            }
+            Some(Opcodes::NOT) => (MicroOp::Not { dr, sr: sr1 }, false),
+            Some(Opcodes::LEA) => (
+                MicroOp::Lea {
+                    dr,
+                    address: pc_offset9,
+                    flags: self.revision.lea_sets_flags(),
+                },
+                false,
+            ),
+            Some(Opcodes::LD) => (
+                MicroOp::Ld {
+                    dr,
+                    address: pc_offset9,
+                },
+                false,
+            ),
+            Some(Opcodes::ST) => (
+                MicroOp::St {
+                    sr: dr,
+                    address: pc_offset9,
+                },
+                false,
+            ),
+            Some(Opcodes::LDI) => (bound(|rs, mem, _, i| Instructions::ldi(rs, mem, i)), false),
+            Some(Opcodes::LDR) => (
+                bound(|rs, mem, _, i| Instructions::load_register(rs, mem, i)),
+                false,
+            ),
+            Some(Opcodes::STI) => (
+                bound(|rs, mem, _, i| Instructions::store_indirect(rs, mem, i)),
+                false,
+            ),
+            Some(Opcodes::STR) => (
+                bound(|rs, mem, _, i| Instructions::store_register(rs, mem, i)),
+                false,
+            ),
+            Some(Opcodes::BR) => (
+                MicroOp::Br {
+                    nzp: (instr >> 9) & 0x7,
+                    target: pc_offset9,
+                },
+                true,
+            ),
+            Some(Opcodes::JMP) => (bound(|rs, _, _, i| Instructions::jump(rs, i)), true),
+            Some(Opcodes::JSR) => (
+                bound(|rs, _, _, i| Instructions::jump_register(rs, i)),
+                true,
+            ),
+            Some(Opcodes::TRAP) => (MicroOp::Trap, true),
+            Some(Opcodes::RES) if self.arithmetic_extension => {
+                (bound(|rs, _, _, i| Instructions::arithmetic(rs, i)), false)
+            }
+            Some(Opcodes::RES) | Some(Opcodes::RTI) | None => (
+                bound(|_, _, _, i| Err(InstructionSetError::BadOpcode(i))),
+                true,
+            ),
+        }
+    }
+
+    /*
+    run compiled blocks until `budget` instructions have executed, an
+    instruction fails or a TRAP is reached. `executed` is advanced for every
+    completed instruction; a TRAP is not executed but its address returned,
+    with PC past it
+    */
+    pub fn run(
+        &mut self,
+        register_storage: &mut RegisterStorage,
+        memory: &mut Memory,
+        console: &mut dyn Console,
+        budget: u64,
+        executed: &mut u64,
+        guard: Option<(&Protection, Privilege)>,
+    ) -> Result<Option<u16>, VmError> {
+        let limit = executed.saturating_add(budget);
+
+        while *executed < limit {
+            let pc = register_storage.locations[Registers::PC as usize];
+            let block = self.lookup_or_compile(pc, memory);
+
+            for (offset, op) in block.ops.iter().enumerate() {
+                if *executed == limit {
+                    break;
+                }
+
+                let address = block.start.wrapping_add(offset as u16);
+                register_storage.locations[Registers::PC as usize] = address.wrapping_add(1);
+
+                if let Some((protection, privilege)) = guard {
+                    let instr = memory.locations[address as usize];
+                    protection
+                        .check(privilege, address, instr, register_storage, memory)
+                        .map_err(|violation| VmError::new(address, instr, violation))?;
+                }
+
+                if let MicroOp::Trap = op {
+                    return Ok(Some(address));
+                }
+
+                let written =
+                    Self::execute(op, register_storage, memory, console).map_err(|err| {
+                        VmError::new(address, memory.locations[address as usize], err)
+                    })?;
+                *executed += 1;
+
+                /* HALT, or a store to the MCR, stopped the clock */
+                if memory.locations[MCR as usize] & CLOCK_ENABLE == 0 {
+                    return Ok(None);
+                }
+
+                /* self-modifying code: leave the (possibly stale) block and recompile from PC */
+                if let Some(written) = written
+                    && self.invalidate(written)
+                {
+                    break;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /* execute a single micro-op, returning the memory address it wrote to, if any */
+    fn execute(
+        op: &MicroOp,
+        register_storage: &mut RegisterStorage,
+        memory: &mut Memory,
+        console: &mut dyn Console,
+    ) -> Result<Option<u16>, InstructionSetError> {
+        let regs = register_storage;
+
+        match *op {
+            MicroOp::AddReg { dr, sr1, sr2 } => {
+                let value = regs.get(sr1).wrapping_add(regs.get(sr2));
+                set_result(regs, dr, value);
+            }
+            MicroOp::AddImm { dr, sr1, imm } => {
+                let value = regs.get(sr1).wrapping_add(imm);
+                set_result(regs, dr, value);
+            }
+            MicroOp::AndReg { dr, sr1, sr2 } => {
+                let value = regs.get(sr1) & regs.get(sr2);
+                set_result(regs, dr, value);
+            }
+            MicroOp::AndImm { dr, sr1, imm } => {
+                let value = regs.get(sr1) & imm;
+                set_result(regs, dr, value);
+            }
+            MicroOp::Not { dr, sr } => {
+                let value = !regs.get(sr);
+                set_result(regs, dr, value);
+            }
+            MicroOp::Lea { dr, address, flags } if flags => set_result(regs, dr, address),
+            MicroOp::Lea { dr, address, .. } => regs.set(dr, address),
+            MicroOp::Ld { dr, address } => set_result(regs, dr, memory.read(address)),
+            MicroOp::St { sr, address } => {
+                memory.write(address, regs.get(sr));
+                return Ok(Some(address));
+            }
+            MicroOp::Br { nzp, target } => {
+                if nzp & regs.get(Registers::COND as usize) != 0 {
+                    regs.set(Registers::PC as usize, target);
+                }
+            }
+            MicroOp::Trap => {}
+            MicroOp::Bound { handler, instr } => {
+                let written = store_target(regs, memory, instr);
+                handler(regs, memory, console, instr)?;
+                return Ok(written);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/* address a STI/STR is about to write to, resolved before it executes */
+fn store_target(register_storage: &RegisterStorage, memory: &Memory, instr: u16) -> Option<u16> {
+    let pc = register_storage.locations[Registers::PC as usize];
+
+    match Opcodes::from_u16(instr >> 12) {
+        Some(Opcodes::STI) => Some(memory.peek(pc.wrapping_add(sign_extend(instr, 9)))),
+        Some(Opcodes::STR) => {
+            let base = register_storage.locations[((instr >> 6) & 0x7) as usize];
+            Some(base.wrapping_add(sign_extend(instr, 6)))
+        }
+        _ => None,
+    }
+}
+
+fn sign_extend(instr: u16, bit_count: u32) -> u16 {
+    let bits = instr & ((1 << bit_count) - 1);
+    Instructions::sign_extend(bits, bit_count).unwrap_or(bits)
+}
+
+/* write an ALU/load result and set the condition codes from it */
+fn set_result(register_storage: &mut RegisterStorage, dr: usize, value: u16) {
+    register_storage.set(dr, value);
+    let flag = if value == 0 {
+        ConditionalFlags::ZRO
+    } else if value >> 15 == 1 {
+        ConditionalFlags::NEG
+    } else {
+        ConditionalFlags::POS
+    };
+    register_storage.set(Registers::COND as usize, flag as u16);
+}
@@ -0,0 +1,144 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::libs::{
+    bus::{Bus, BusError, Device, Interrupt},
+    constants::{TMCNT, TMCR},
+};
+
+/* TMCR bit 15: the counter reached zero; cleared by any write to TMCR */
+pub const EXPIRED: u16 = 1 << 15;
+/* TMCR bit 14: interrupt while expired */
+pub const INTERRUPT_ENABLE: u16 = 1 << 14;
+/* TMCR bits 10-8: priority level of the interrupt */
+pub const PRIORITY_SHIFT: u16 = 8;
+/* TMCR bit 2: count cycles instead of instructions */
+pub const COUNT_CYCLES: u16 = 1 << 2;
+/* TMCR bit 1: reload the counter on expiry instead of stopping */
+pub const PERIODIC: u16 = 1 << 1;
+/* TMCR bit 0: the timer is counting */
+pub const ENABLE: u16 = 1;
+
+pub const TIMER_INTERRUPT_VECTOR: u16 = 0x81;
+
+const WRITABLE: u16 = INTERRUPT_ENABLE | 0x7 << PRIORITY_SHIFT | COUNT_CYCLES | PERIODIC | ENABLE;
+
+/*
+a programmable interval timer with control (TMCR), reload (TMRR) and
+counter (TMCNT) registers. while enabled the counter counts down by one per
+instruction, or by the cycles each instruction takes; when it reaches zero
+the timer expires, raising an interrupt at its priority if enabled to, and
+either reloads or stops.
+
+writing TMRR also loads the counter. an interrupt at priority 0 is never
+taken, since it does not preempt anything
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timer {
+    control: u16,
+    reload: u16,
+    counter: u16,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn control(&self) -> u16 {
+        self.control
+    }
+
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    pub fn priority(&self) -> u16 {
+        (self.control >> PRIORITY_SHIFT) & 0x7
+    }
+
+    fn expire(&mut self) {
+        self.control |= EXPIRED;
+        if self.control & PERIODIC != 0 {
+            self.counter = self.reload;
+        } else {
+            self.counter = 0;
+            self.control &= !ENABLE;
+        }
+    }
+}
+
+impl Device for Timer {
+    fn name(&self) -> &str {
+        "timer"
+    }
+
+    /* TMCR at offset 0, TMRR at offset 2, TMCNT at offset 4 */
+    fn peek(&self, offset: u16) -> u16 {
+        match offset {
+            0 => self.control,
+            2 => self.reload,
+            4 => self.counter,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        match offset {
+            0 => {
+                let starting = self.control & ENABLE == 0 && value & ENABLE != 0;
+                self.control = value & WRITABLE;
+                if starting && self.counter == 0 {
+                    self.counter = self.reload;
+                }
+            }
+            2 => {
+                self.reload = value;
+                self.counter = value;
+            }
+            4 => self.counter = value,
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if self.control & ENABLE == 0 {
+            return;
+        }
+
+        let elapsed = if self.control & COUNT_CYCLES != 0 {
+            cycles
+        } else {
+            1
+        };
+        if u64::from(self.counter) <= elapsed {
+            self.expire();
+        } else {
+            self.counter -= elapsed as u16;
+        }
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        let asserted = EXPIRED | INTERRUPT_ENABLE;
+        (self.control & asserted == asserted).then(|| Interrupt {
+            vector: TIMER_INTERRUPT_VECTOR,
+            priority: self.priority(),
+        })
+    }
+
+    fn save(&self) -> Option<Vec<u16>> {
+        Some(vec![self.control, self.reload, self.counter])
+    }
+
+    fn restore(&mut self, state: &[u16]) {
+        if let [control, reload, counter] = *state {
+            self.control = control;
+            self.reload = reload;
+            self.counter = counter;
+        }
+    }
+}
+
+/* attach a timer at its standard registers */
+pub fn attach_timer(bus: &mut Bus) -> Result<Rc<RefCell<Timer>>, BusError> {
+    bus.attach(TMCR, TMCNT + 1, Timer::new())
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::libs::{
+    constants::{CLOCK_ENABLE, MCR},
+    instructions::{InstructionSet, Instructions, Revision},
+    loader::Image,
+    trap::Trap,
+    types::{Opcodes, Registers},
+};
+
+const PC: usize = Registers::PC as usize;
+const COND: usize = Registers::COND as usize;
+
+/* how a block hands control back to the dispatcher */
+enum Exit {
+    /* continue at a statically known address */
+    Direct(Vec<u16>),
+    /* continue at a register value (JMP/JSRR/RET), possibly after a return site */
+    Indirect(Option<u16>),
+    /* the block cannot continue (HALT, illegal opcode) */
+    Stop,
+}
+
+/* the statements implementing one instruction, and what they need from their block */
+struct Lifted {
+    lines: Vec<String>,
+    memory: bool,
+    console: bool,
+}
+
+/*
+statically lifts an LC-3 image into a Rust module: every basic block reachable
+from the origin becomes a function returning the next PC, and a dispatcher
+loop maps PCs to blocks. a PC that does not start a discovered block (say the
+target of an indirect jump outside the image) falls back to the `Instructions`
+interpreter. blocks are lifted from the image as loaded, so code the program
+overwrites at run time keeps running as it was translated. instructions are
+lifted as `revision` defines them and, with `arithmetic_extension`, the
+reserved opcode as `Arithmetic`, matching a machine set up the same way
+*/
+pub struct Translator<'a> {
+    image: &'a Image,
+    revision: Revision,
+    arithmetic_extension: bool,
+    blocks: BTreeMap<u16, Vec<u16>>,
+}
+
+impl<'a> Translator<'a> {
+    pub fn new(image: &'a Image, revision: Revision, arithmetic_extension: bool) -> Self {
+        let mut translator = Self {
+            image,
+            revision,
+            arithmetic_extension,
+            blocks: BTreeMap::new(),
+        };
+        translator.discover();
+        translator
+    }
+
+    /* start addresses of the discovered basic blocks */
+    pub fn blocks(&self) -> impl Iterator<Item = u16> + '_ {
+        self.blocks.keys().copied()
+    }
+
+    fn word(&self, address: u16) -> Option<u16> {
+        let offset = address.checked_sub(self.image.origin)? as usize;
+        self.image.words.get(offset).copied()
+    }
+
+    fn discover(&mut self) {
+        let mut pending = vec![self.image.origin];
+        let mut seen = BTreeSet::new();
+
+        while let Some(start) = pending.pop() {
+            if !seen.insert(start) || self.word(start).is_none() {
+                continue;
+            }
+
+            let mut address = start;
+            let mut instructions = Vec::new();
+
+            while let Some(instr) = self.word(address) {
+                instructions.push(instr);
+
+                match self.exit(address, instr) {
+                    Some(Exit::Direct(targets)) => pending.extend(targets),
+                    Some(Exit::Indirect(return_site)) => pending.extend(return_site),
+                    Some(Exit::Stop) => {}
+                    None => {
+                        if let Some(next) = address.checked_add(1) {
+                            address = next;
+                            continue;
+                        }
+                    }
+                }
+                break;
+            }
+
+            self.blocks.insert(start, instructions);
+        }
+    }
+
+    /* successors of a block-ending instruction, `None` when the instruction does not end a block */
+    fn exit(&self, address: u16, instr: u16) -> Option<Exit> {
+        let next = address.wrapping_add(1);
+
+        match Opcodes::from_u16(instr >> 12)? {
+            Opcodes::BR => {
+                let target = next.wrapping_add(sign_extend(instr & 0x1FF, 9));
+                match (instr >> 9) & 0x7 {
+                    0b000 => Some(Exit::Direct(vec![next])),
+                    0b111 => Some(Exit::Direct(vec![target])),
+                    _ => Some(Exit::Direct(vec![target, next])),
+                }
+            }
+            Opcodes::JMP => Some(Exit::Indirect(None)),
+            Opcodes::JSR if (instr >> 11) & 1 == 1 => {
+                let target = next.wrapping_add(sign_extend(instr & 0x7FF, 11));
+                Some(Exit::Direct(vec![target, next]))
+            }
+            Opcodes::JSR => Some(Exit::Indirect(Some(next))),
+            Opcodes::TRAP => match Trap::from_u16(instr & 0xFF) {
+                Some(Trap::HALT) => Some(Exit::Stop),
+                _ => Some(Exit::Direct(vec![next])),
+            },
+            Opcodes::RES if self.arithmetic_extension => None,
+            Opcodes::RES | Opcodes::RTI => Some(Exit::Stop),
+            _ => None,
+        }
+    }
+
+    /* render the discovered blocks and dispatcher as a Rust module */
+    pub fn emit(&self, with_main: bool) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "// translated from an LC-3 image: origin {:#06x}, {} words, {} blocks",
+            self.image.origin,
+            self.image.words.len(),
+            self.blocks.len()
+        );
+        out.push_str(
+            "#![allow(unreachable_code, unused_imports, clippy::all)]\n\n\
+             use virtual_machine::libs::{\n    \
+             instructions::{InstructionSet, InstructionSetError, Instructions},\n    \
+             console::Console,\n    \
+             trap::Trap,\n    \
+             types::{MemomryTrait, Memory, RegisterStorage, RegisterStorageTrait},\n\
+             };\n\n",
+        );
+
+        let _ = writeln!(out, "pub const ORIGIN: u16 = {:#06x};\n", self.image.origin);
+        let _ = writeln!(
+            out,
+            "pub const IMAGE: [u16; {}] = [",
+            self.image.words.len()
+        );
+        for chunk in self.image.words.chunks(8) {
+            let words: Vec<String> = chunk.iter().map(|word| format!("{word:#06x}")).collect();
+            let _ = writeln!(out, "    {},", words.join(", "));
+        }
+        out.push_str("];\n\n");
+
+        let _ = write!(
+            out,
+            "pub fn load(memory: &mut Memory) {{\n    \
+             for (offset, word) in IMAGE.iter().enumerate() {{\n        \
+             memory.write(ORIGIN.wrapping_add(offset as u16), *word);\n    \
+             }}\n    \
+             /* start the clock */\n    \
+             memory.locations[{MCR:#06x}] |= {CLOCK_ENABLE:#06x};\n\
+             }}\n\n",
+        );
+
+        self.emit_dispatcher(&mut out);
+
+        for (start, instructions) in &self.blocks {
+            self.emit_block(&mut out, *start, instructions);
+        }
+
+        if with_main {
+            let _ = write!(
+                out,
+                "\nfn main() {{\n    \
+                 let mut register_storage = RegisterStorage::new();\n    \
+                 let mut memory = Memory::new();\n    \
+                 load(&mut memory);\n    \
+                 register_storage.locations[{COND}] = 1 << 1;\n    \
+                 register_storage.locations[{PC}] = ORIGIN;\n\n    \
+                 let mut console = virtual_machine::libs::console::StdConsole;\n    \
+                 if let Err(err) = run(&mut register_storage, &mut memory, &mut console) {{\n        \
+                 eprintln!(\"{{err}}\");\n        \
+                 std::process::exit(1);\n    \
+                 }}\n\
+                 }}\n"
+            );
+        }
+
+        out
+    }
+
+    fn emit_dispatcher(&self, out: &mut String) {
+        /* opcodes the machine's configuration executes differently from `execute` */
+        let mut arms = Vec::new();
+        if self.arithmetic_extension {
+            arms.push((Opcodes::RES, "Instructions::arithmetic(rs, instr)?"));
+        }
+        if !self.revision.lea_sets_flags() {
+            arms.push((Opcodes::LEA, "Instructions::effective_address(rs, instr)?"));
+        }
+        let execute = if arms.is_empty() {
+            "Instructions::execute(rs, mem, console, instr)?;".to_string()
+        } else {
+            let mut execute = "match instr >> 12 {\n".to_string();
+            for (opcode, call) in arms {
+                let _ = writeln!(
+                    execute,
+                    "                    {:#x} => {call},",
+                    opcode as u16
+                );
+            }
+            execute.push_str(
+                "                    _ => Instructions::execute(rs, mem, console, instr)?,\n                \
+                 }",
+            );
+            execute
+        };
+
+        let _ = write!(
+            out,
+            "/* runs from the current PC until the program stops the clock */\n\
+             pub fn run(rs: &mut RegisterStorage, mem: &mut Memory, console: &mut dyn Console) -> Result<(), InstructionSetError> {{\n    \
+             loop {{\n        \
+             let pc = rs.locations[{PC}];\n        \
+             let next = match pc {{\n"
+        );
+        for start in self.blocks.keys() {
+            let _ = writeln!(
+                out,
+                "            {start:#06x} => block_{start:04x}(rs, mem, console)?,"
+            );
+        }
+        let _ = write!(
+            out,
+            "            _ => {{\n                \
+             /* not discovered statically: interpret a single instruction */\n                \
+             let instr = mem.read(pc);\n                \
+             rs.locations[{PC}] = pc.wrapping_add(1);\n                \
+             {execute}\n                \
+             rs.locations[{PC}]\n            \
+             }}\n        \
+             }};\n        \
+             rs.locations[{PC}] = next;\n        \
+             if mem.locations[{MCR:#06x}] & {CLOCK_ENABLE:#06x} == 0 {{\n            \
+             return Ok(());\n        \
+             }}\n    \
+             }}\n\
+             }}\n"
+        );
+    }
+
+    fn emit_block(&self, out: &mut String, start: u16, instructions: &[u16]) {
+        let mut body = String::new();
+        let (mut uses_memory, mut uses_console) = (false, false);
+        let mut address = start;
+        for instr in instructions {
+            let _ = writeln!(body, "    // {address:#06x}: {instr:#06x}");
+            let lifted = self.lift(address, *instr);
+            for line in lifted.lines {
+                let _ = writeln!(body, "    {line}");
+            }
+            uses_memory |= lifted.memory;
+            uses_console |= lifted.console;
+            address = address.wrapping_add(1);
+        }
+
+        /* blocks that touch neither memory nor the console take them unused */
+        let mem = if uses_memory { "mem" } else { "_mem" };
+        let console = if uses_console { "console" } else { "_console" };
+        let _ = write!(
+            out,
+            "\nfn block_{start:04x}(rs: &mut RegisterStorage, {mem}: &mut Memory, {console}: &mut dyn Console) -> Result<u16, InstructionSetError> {{\n{body}"
+        );
+
+        /* block ran off the end of the image without a terminator */
+        if self
+            .exit(
+                address.wrapping_sub(1),
+                instructions[instructions.len() - 1],
+            )
+            .is_none()
+        {
+            let _ = writeln!(out, "    Ok({address:#06x})");
+        }
+        out.push_str("}\n");
+    }
+
+    /* Rust statements implementing a single instruction */
+    fn lift(&self, address: u16, instr: u16) -> Lifted {
+        let next = address.wrapping_add(1);
+        let dr = (instr >> 9) & 0x7;
+        let sr1 = (instr >> 6) & 0x7;
+        let sr2 = instr & 0x7;
+        let imm5 = sign_extend(instr & 0x1F, 5);
+        let pc_offset9 = next.wrapping_add(sign_extend(instr & 0x1FF, 9));
+        let imm_mode = (instr >> 5) & 0x1 == 1;
+        let flags = format!("rs.update_flags({dr})?;");
+
+        /* handlers that read PC themselves need it set first */
+        let delegate = |handler: &str, args: &str| {
+            vec![
+                format!("rs.locations[{PC}] = {next:#06x};"),
+                format!("Instructions::{handler}({args}, {instr:#06x})?;"),
+            ]
+        };
+
+        let Some(opcode) = Opcodes::from_u16(instr >> 12) else {
+            return Lifted {
+                lines: vec![format!(
+                    "return Err(InstructionSetError::BadOpcode({instr:#06x}));"
+                )],
+                memory: false,
+                console: false,
+            };
+        };
+
+        let lines = match opcode {
+            Opcodes::ADD if imm_mode => vec![
+                format!("rs.locations[{dr}] = rs.locations[{sr1}].wrapping_add({imm5:#06x});"),
+                flags,
+            ],
+            Opcodes::ADD => vec![
+                format!(
+                    "rs.locations[{dr}] = rs.locations[{sr1}].wrapping_add(rs.locations[{sr2}]);"
+                ),
+                flags,
+            ],
+            Opcodes::AND if imm_mode => vec![
+                format!("rs.locations[{dr}] = rs.locations[{sr1}] & {imm5:#06x};"),
+                flags,
+            ],
+            Opcodes::AND => vec![
+                format!("rs.locations[{dr}] = rs.locations[{sr1}] & rs.locations[{sr2}];"),
+                flags,
+            ],
+            Opcodes::NOT => vec![format!("rs.locations[{dr}] = !rs.locations[{sr1}];"), flags],
+            Opcodes::LEA if self.revision.lea_sets_flags() => {
+                vec![format!("rs.locations[{dr}] = {pc_offset9:#06x};"), flags]
+            }
+            Opcodes::LEA => vec![format!("rs.locations[{dr}] = {pc_offset9:#06x};")],
+            Opcodes::LD => vec![
+                format!("rs.locations[{dr}] = mem.read({pc_offset9:#06x});"),
+                flags,
+            ],
+            Opcodes::ST => vec![format!("mem.write({pc_offset9:#06x}, rs.locations[{dr}]);")],
+            Opcodes::LDI => delegate("ldi", "rs, mem"),
+            Opcodes::LDR => delegate("load_register", "rs, mem"),
+            Opcodes::STI => delegate("store_indirect", "rs, mem"),
+            Opcodes::STR => delegate("store_register", "rs, mem"),
+            Opcodes::BR => {
+                let nzp = (instr >> 9) & 0x7;
+                match nzp {
+                    0b000 => vec![format!("return Ok({next:#06x});")],
+                    0b111 => vec![format!("return Ok({pc_offset9:#06x});")],
+                    _ => vec![
+                        format!("if rs.locations[{COND}] & {nzp:#05b} != 0 {{"),
+                        format!("    return Ok({pc_offset9:#06x});"),
+                        "}".to_string(),
+                        format!("return Ok({next:#06x});"),
+                    ],
+                }
+            }
+            Opcodes::JMP => vec![format!("return Ok(rs.locations[{sr1}]);")],
+            Opcodes::JSR if (instr >> 11) & 1 == 1 => {
+                let target = next.wrapping_add(sign_extend(instr & 0x7FF, 11));
+                vec![
+                    format!("rs.locations[7] = {next:#06x};"),
+                    format!("return Ok({target:#06x});"),
+                ]
+            }
+            Opcodes::JSR => vec![
+                format!("let target = rs.locations[{sr1}];"),
+                format!("rs.locations[7] = {next:#06x};"),
+                "return Ok(target);".to_string(),
+            ],
+            Opcodes::TRAP => {
+                let vector = instr & 0xFF;
+                let name = Trap::from_u16(vector)
+                    .map(|trap| format!(" /* {trap:?} */"))
+                    .unwrap_or_default();
+                vec![
+                    format!("rs.locations[7] = {next:#06x};"),
+                    format!(
+                        "Trap::execute_trap_instruction(rs, mem, console, {vector:#04x}{name})?;"
+                    ),
+                    format!("return Ok({next:#06x});"),
+                ]
+            }
+            Opcodes::RES if self.arithmetic_extension => {
+                vec![format!("Instructions::arithmetic(rs, {instr:#06x})?;")]
+            }
+            Opcodes::RES | Opcodes::RTI => vec![format!(
+                "return Err(InstructionSetError::BadOpcode({instr:#06x}));"
+            )],
+        };
+
+        Lifted {
+            lines,
+            /* loads, stores and traps go through memory; traps also do I/O */
+            memory: matches!(
+                opcode,
+                Opcodes::LD
+                    | Opcodes::ST
+                    | Opcodes::LDI
+                    | Opcodes::LDR
+                    | Opcodes::STI
+                    | Opcodes::STR
+                    | Opcodes::TRAP
+            ),
+            console: opcode == Opcodes::TRAP,
+        }
+    }
+}
+
+fn sign_extend(bits: u16, bit_count: u32) -> u16 {
+    Instructions::sign_extend(bits, bit_count).unwrap_or(bits)
+}
@@ -1,7 +1,12 @@
-use std::io::{self, Read, Write};
-
-use crate::libs::types::{
-    MemomryTrait, RegisterError, RegisterStorage, RegisterStorageTrait, Registers,
+use crate::libs::{
+    console::Console,
+    constants::{CLOCK_ENABLE, MCR, MEMORY_MAX},
+    error::Fault,
+    instructions::InstructionSetError,
+    protection::{AccessKind, Privilege, Protection, Violation},
+    types::{
+        MemomryTrait, Memory, RegisterError, RegisterStorage, RegisterStorageTrait, Registers,
+    },
 };
 
 #[derive(Debug)]
@@ -15,30 +20,40 @@ pub enum Trap {
 }
 
 pub trait TrapTrait {
-    fn getc(register_storage: &mut RegisterStorage) -> Result<(), RegisterError>;
-    fn out(register_storage: &mut RegisterStorage) -> Result<(), RegisterError>;
+    fn getc(
+        register_storage: &mut RegisterStorage,
+        console: &mut dyn Console,
+    ) -> Result<(), RegisterError>;
+    fn out(
+        register_storage: &mut RegisterStorage,
+        console: &mut dyn Console,
+    ) -> Result<(), RegisterError>;
     fn puts(
         register_storage: &mut RegisterStorage,
         memory: &impl MemomryTrait,
+        console: &mut dyn Console,
+    ) -> Result<(), RegisterError>;
+    fn trap_in(
+        register_storage: &mut RegisterStorage,
+        console: &mut dyn Console,
     ) -> Result<(), RegisterError>;
-    fn trap_in(register_storage: &mut RegisterStorage) -> Result<(), RegisterError>;
     fn putsp(
         register_storage: &mut RegisterStorage,
         memory: &impl MemomryTrait,
+        console: &mut dyn Console,
     ) -> Result<(), RegisterError>;
-    fn halt() -> Result<(), RegisterError>;
+    fn halt(memory: &mut impl MemomryTrait, console: &mut dyn Console)
+    -> Result<(), RegisterError>;
 }
 
 impl TrapTrait for Trap {
-    fn getc(register_storage: &mut RegisterStorage) -> Result<(), RegisterError> {
-        // Read a single character from stdin (non-blocking)
-        let mut buffer = [0u8; 1];
-        let char_code = if io::stdin().read_exact(&mut buffer).is_ok() {
-            buffer[0] as u16
-        } else {
-            // No input available, return 0 or handle appropriately
-            0
-        };
+    fn getc(
+        register_storage: &mut RegisterStorage,
+        console: &mut dyn Console,
+    ) -> Result<(), RegisterError> {
+        // Read a single character from the console
+        // No input available, return 0
+        let char_code = console.read_byte().map_or(0, u16::from);
 
         // Store in R0 and clear high 8 bits
         register_storage.store(char_code & 0x00FF, Registers::R0 as u16)?;
@@ -48,14 +63,15 @@ impl TrapTrait for Trap {
         Ok(())
     }
 
-    fn out(register_storage: &mut RegisterStorage) -> Result<(), RegisterError> {
+    fn out(
+        register_storage: &mut RegisterStorage,
+        console: &mut dyn Console,
+    ) -> Result<(), RegisterError> {
         let char_code = register_storage.load(Registers::R0 as u16)? & 0x00FF;
 
-        // Output character to stdout
-        if let Some(c) = char::from_u32(char_code as u32) {
-            print!("{}", c);
-            io::stdout().flush().unwrap();
-        }
+        // Output character to the console
+        console.write_byte(char_code as u8);
+        console.flush();
 
         Ok(())
     }
@@ -63,11 +79,13 @@ impl TrapTrait for Trap {
     fn puts(
         register_storage: &mut RegisterStorage,
         memory: &impl MemomryTrait,
+        console: &mut dyn Console,
     ) -> Result<(), RegisterError> {
         let mut address = register_storage.load(Registers::R0 as u16)?;
 
-        // Read and output characters until null terminator 0x0000
-        loop {
+        // Read and output characters until null terminator 0x0000,
+        // giving up after one pass over memory if there is none
+        for _ in 0..MEMORY_MAX {
             let memory_value = memory.read(address);
 
             if memory_value == 0 {
@@ -75,34 +93,30 @@ impl TrapTrait for Trap {
             }
 
             let char_code = memory_value & 0x00FF;
-            if let Some(c) = char::from_u32(char_code as u32) {
-                print!("{}", c);
-            }
+            console.write_byte(char_code as u8);
 
-            address += 1;
+            address = address.wrapping_add(1);
         }
 
-        io::stdout().flush().unwrap();
+        console.flush();
         Ok(())
     }
 
-    fn trap_in(register_storage: &mut RegisterStorage) -> Result<(), RegisterError> {
-        print!("Enter a character: ");
-        io::stdout().flush().unwrap();
+    fn trap_in(
+        register_storage: &mut RegisterStorage,
+        console: &mut dyn Console,
+    ) -> Result<(), RegisterError> {
+        console.write_str("Enter a character: ");
+        console.flush();
 
         // Read character
-        let mut buffer = [0u8; 1];
-        let char_code = if io::stdin().read_exact(&mut buffer).is_ok() {
-            buffer[0] as u16
-        } else {
-            0
-        };
+        let char_code = console.read_byte().map_or(0, u16::from);
 
         // Store in R0 and echo
         register_storage.store(char_code & 0x00FF, Registers::R0 as u16)?;
-        if let Some(c) = char::from_u32(char_code as u32) {
-            println!("{}", c);
-        }
+        console.write_byte(char_code as u8);
+        console.write_byte(b'\n');
+        console.flush();
 
         register_storage.update_flags(Registers::R0 as u16)?;
         Ok(())
@@ -111,11 +125,13 @@ impl TrapTrait for Trap {
     fn putsp(
         register_storage: &mut RegisterStorage,
         memory: &impl MemomryTrait,
+        console: &mut dyn Console,
     ) -> Result<(), RegisterError> {
         let mut address = register_storage.load(Registers::R0 as u16)?;
 
-        // Read and output packed characters until null terminator 0x000
-        loop {
+        // Read and output packed characters until null terminator 0x000,
+        // giving up after one pass over memory if there is none
+        for _ in 0..MEMORY_MAX {
             let memory_value = memory.read(address);
             if memory_value == 0 {
                 break;
@@ -125,43 +141,48 @@ impl TrapTrait for Trap {
             let char1 = (memory_value & 0x00FF) as u8;
             let char2 = ((memory_value >> 8) & 0x00FF) as u8;
 
-            if let Some(c) = char::from_u32(char1 as u32) {
-                print!("{}", c);
-            }
+            console.write_byte(char1);
             if char2 != 0 {
-                if let Some(c) = char::from_u32(char2 as u32) {
-                    print!("{}", c);
-                }
+                console.write_byte(char2);
             }
 
-            address += 1;
+            address = address.wrapping_add(1);
         }
 
-        io::stdout().flush().unwrap();
+        console.flush();
         Ok(())
     }
 
-    fn halt() -> Result<(), RegisterError> {
-        println!("Program halted");
-        std::process::exit(0);
+    /* stop the clock, as the LC-3 OS does, ending the machine's run loop */
+    fn halt(
+        memory: &mut impl MemomryTrait,
+        console: &mut dyn Console,
+    ) -> Result<(), RegisterError> {
+        console.write_str("Program halted\n");
+        console.flush();
+        memory.write(MCR, memory.read(MCR) & !CLOCK_ENABLE);
+        Ok(())
     }
 }
 
 impl Trap {
     pub fn execute_trap_instruction(
         register_storage: &mut RegisterStorage,
-        memory: &impl MemomryTrait,
+        memory: &mut impl MemomryTrait,
+        console: &mut dyn Console,
         trap_vector: u16,
-    ) -> Result<(), RegisterError> {
-        match Self::from_u16(trap_vector & 0xFF) {
-            Some(Trap::GETC) => Self::getc(register_storage),
-            Some(Trap::OUT) => Self::out(register_storage),
-            Some(Trap::PUTS) => Self::puts(register_storage, memory),
-            Some(Trap::IN) => Self::trap_in(register_storage),
-            Some(Trap::PUTSP) => Self::putsp(register_storage, memory),
-            Some(Trap::HALT) => Self::halt(),
-            _ => todo!(),
+    ) -> Result<(), InstructionSetError> {
+        let vector = trap_vector & 0xFF;
+        match Self::from_u16(vector) {
+            Some(Trap::GETC) => Self::getc(register_storage, console)?,
+            Some(Trap::OUT) => Self::out(register_storage, console)?,
+            Some(Trap::PUTS) => Self::puts(register_storage, memory, console)?,
+            Some(Trap::IN) => Self::trap_in(register_storage, console)?,
+            Some(Trap::PUTSP) => Self::putsp(register_storage, memory, console)?,
+            Some(Trap::HALT) => Self::halt(memory, console)?,
+            None => return Err(InstructionSetError::UnknownTrap(vector)),
         }
+        Ok(())
     }
 
     pub fn from_u16(instr: u16) -> Option<Trap> {
@@ -176,3 +197,91 @@ impl Trap {
         }
     }
 }
+
+/*
+the machine as seen by a registered trap handler. memory goes through
+`read`/`write` so that the machine can invalidate code the handler overwrote,
+and so that the accesses are checked against the machine's protection on
+behalf of the program that trapped
+*/
+pub struct TrapContext<'a> {
+    pub register_storage: &'a mut RegisterStorage,
+    pub console: &'a mut dyn Console,
+    memory: &'a mut Memory,
+    protection: Option<(&'a Protection, Privilege)>,
+    written: Vec<u16>,
+}
+
+impl<'a> TrapContext<'a> {
+    pub fn new(
+        register_storage: &'a mut RegisterStorage,
+        memory: &'a mut Memory,
+        console: &'a mut dyn Console,
+        protection: Option<(&'a Protection, Privilege)>,
+    ) -> Self {
+        Self {
+            register_storage,
+            console,
+            memory,
+            protection,
+            written: Vec::new(),
+        }
+    }
+
+    pub fn register(&self, register: Registers) -> u16 {
+        self.register_storage.get(register as usize)
+    }
+
+    pub fn set_register(&mut self, register: Registers, value: u16) {
+        self.register_storage.set(register as usize, value);
+    }
+
+    /* set the condition codes from `register`, as loads and ALU instructions do */
+    pub fn update_flags(&mut self, register: Registers) {
+        let _ = self.register_storage.update_flags(register as u16);
+    }
+
+    /* whether the program that trapped may access `address` itself */
+    pub fn check(&self, address: u16, kind: AccessKind) -> Result<(), Violation> {
+        match self.protection {
+            Some((protection, privilege)) if !protection.allows(address, kind, privilege) => {
+                Err(Violation { address, kind })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn read(&mut self, address: u16) -> Result<u16, Violation> {
+        self.check(address, AccessKind::Read)?;
+        Ok(self.memory.read(address))
+    }
+
+    pub fn write(&mut self, address: u16, value: u16) -> Result<(), Violation> {
+        self.check(address, AccessKind::Write)?;
+        self.memory.write(address, value);
+        self.written.push(address);
+        Ok(())
+    }
+
+    /* addresses written through `write`, in order */
+    pub fn written(&self) -> &[u16] {
+        &self.written
+    }
+}
+
+/*
+a host-side service routine for a trap vector, registered on the machine
+with `Vm::register_trap`. closures taking a `&mut TrapContext` are handlers
+*/
+pub trait TrapHandler {
+    fn call(&mut self, context: &mut TrapContext) -> Result<(), Fault>;
+}
+
+impl<F> TrapHandler for F
+where
+    F: FnMut(&mut TrapContext) -> Result<(), Fault>,
+{
+    fn call(&mut self, context: &mut TrapContext) -> Result<(), Fault> {
+        self(context)
+    }
+}
@@ -1,7 +1,11 @@
+use std::cell::Cell;
+
 use thiserror::Error;
 use tracing::info;
 
-use crate::libs::constants::MEMORY_MAX;
+#[cfg(feature = "instrument")]
+use crate::libs::observer::ObserverSlot;
+use crate::libs::{bus::Bus, constants::MEMORY_MAX, error::Fault};
 
 #[derive(Debug, Error)]
 pub enum RegisterError {
@@ -9,7 +13,7 @@ pub enum RegisterError {
     InvalidRegister(u16),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Registers {
     R0 = 0,
     R1,
@@ -24,7 +28,7 @@ pub enum Registers {
     COUNT,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcodes {
     BR = 0, /* branch */
     ADD,    /* add  */
@@ -84,6 +88,8 @@ pub trait RegisterStorageTrait {
 #[derive(Debug)]
 pub struct RegisterStorage {
     pub locations: [u16; Registers::COUNT as usize],
+    #[cfg(feature = "instrument")]
+    pub observer: ObserverSlot,
 }
 
 impl RegisterStorageTrait for RegisterStorage {
@@ -92,36 +98,31 @@ impl RegisterStorageTrait for RegisterStorage {
 
         Self {
             locations: [0u16; Registers::COUNT as usize],
+            #[cfg(feature = "instrument")]
+            observer: ObserverSlot::default(),
         }
     }
 
     fn load(&self, reg_location: u16) -> Result<u16, RegisterError> {
         let register = RegisterStorage::get_register(reg_location)?;
-
-        info!("loading instruction from register: {register:?}");
-        Ok(self.locations[register as usize])
+        Ok(self.get(register as usize))
     }
 
     fn store(&mut self, instr: u16, reg_location: u16) -> Result<(), RegisterError> {
         let register = RegisterStorage::get_register(reg_location)?;
-
-        info!("storing instruction to register: {register:?}");
-        self.locations[register as usize] = instr;
+        self.set(register as usize, instr);
         Ok(())
     }
 
     fn update_flags(&mut self, destination_register: u16) -> Result<(), RegisterError> {
         let result = self.load(destination_register)?;
         if result == 0 {
-            let _set_zero_conditional_flag =
-                self.store(ConditionalFlags::ZRO as u16, Registers::COND as u16)?;
+            self.store(ConditionalFlags::ZRO as u16, Registers::COND as u16)?;
         } else if result >> 15 == 1 {
             /* a 1 in the left-most bit indicates negative */
-            let _set_negative_condition =
-                self.store(ConditionalFlags::NEG as u16, Registers::COND as u16)?;
+            self.store(ConditionalFlags::NEG as u16, Registers::COND as u16)?;
         } else {
-            let _set_positive_condition =
-                self.store(ConditionalFlags::POS as u16, Registers::COND as u16)?;
+            self.store(ConditionalFlags::POS as u16, Registers::COND as u16)?;
         }
         Ok(())
     }
@@ -143,6 +144,26 @@ impl RegisterStorage {
             _ => Err(RegisterError::InvalidRegister(register)),
         }
     }
+
+    /* unchecked access by index, reported to the observer (if any) */
+    #[inline]
+    pub fn get(&self, index: usize) -> u16 {
+        let value = self.locations[index];
+        #[cfg(feature = "instrument")]
+        if let Some(observer) = self.observer.get() {
+            observer.register_load(index as u16, value);
+        }
+        value
+    }
+
+    #[inline]
+    pub fn set(&mut self, index: usize, value: u16) {
+        #[cfg(feature = "instrument")]
+        if let Some(observer) = self.observer.get() {
+            observer.register_store(index as u16, value);
+        }
+        self.locations[index] = value;
+    }
 }
 
 pub trait MemomryTrait {
@@ -151,9 +172,15 @@ pub trait MemomryTrait {
     fn write(&mut self, address: u16, value: u16);
 }
 
+/* RAM, with the devices on `bus` taking over the addresses they are mapped at */
 #[derive(Debug)]
 pub struct Memory {
     pub locations: [u16; MEMORY_MAX],
+    pub bus: Bus,
+    /* the last device access that faulted, for the machine to fail the instruction with */
+    pub bus_fault: Cell<Option<Fault>>,
+    #[cfg(feature = "instrument")]
+    pub observer: ObserverSlot,
 }
 
 impl MemomryTrait for Memory {
@@ -162,16 +189,55 @@ impl MemomryTrait for Memory {
 
         Self {
             locations: [0u16; MEMORY_MAX],
+            bus: Bus::new(),
+            bus_fault: Cell::new(None),
+            #[cfg(feature = "instrument")]
+            observer: ObserverSlot::default(),
         }
     }
 
+    #[inline]
     fn read(&self, memory_address: u16) -> u16 {
-        info!("reading from memory address: {memory_address:?}");
-        self.locations[memory_address as usize]
+        let value = self.resolve(memory_address, self.bus.read(memory_address));
+        #[cfg(feature = "instrument")]
+        if let Some(observer) = self.observer.get() {
+            observer.memory_read(memory_address, value);
+        }
+        value
     }
 
+    #[inline]
     fn write(&mut self, memory_address: u16, value: u16) {
-        info!("storing to {value} memory address: {memory_address:?}");
-        self.locations[memory_address as usize] = value
+        #[cfg(feature = "instrument")]
+        if let Some(observer) = self.observer.get() {
+            observer.memory_write(memory_address, value);
+        }
+        match self.bus.write(memory_address, value) {
+            None => self.locations[memory_address as usize] = value,
+            Some(Ok(())) => {}
+            Some(Err(fault)) => self.bus_fault.set(Some(fault)),
+        }
+    }
+}
+
+impl Memory {
+    /*
+    the word `read` would return, without device side effects or observer
+    notifications; for checks made before the access itself
+    */
+    pub fn peek(&self, address: u16) -> u16 {
+        self.resolve(address, self.bus.peek(address))
+    }
+
+    /* the word a device answered with, RAM if none is mapped at `address` */
+    fn resolve(&self, address: u16, answer: Option<Result<u16, Fault>>) -> u16 {
+        match answer {
+            None => self.locations[address as usize],
+            Some(Ok(value)) => value,
+            Some(Err(fault)) => {
+                self.bus_fault.set(Some(fault));
+                0
+            }
+        }
     }
 }
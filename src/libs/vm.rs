@@ -0,0 +1,691 @@
+use std::{cell::Cell, collections::HashMap, rc::Rc};
+
+#[cfg(feature = "instrument")]
+use crate::libs::observer::{Observer, ObserverSlot};
+use crate::libs::{
+    bus,
+    console::{BufferConsole, Console, CountingConsole, SharedConsole, StdConsole},
+    constants::{
+        CLOCK_ENABLE, INTERRUPT_VECTOR_TABLE, LC3B_INTERRUPT_VECTOR_TABLE, MCR, PC_START,
+        SUPERVISOR_STACK_START, TRAP_VECTOR_TABLE,
+    },
+    error::{Fault, VmError},
+    instructions::{InstructionSet, Instructions, Revision},
+    lc3b,
+    limits::{LimitTracker, Limits, StopReason},
+    loader::Image,
+    protection::{Privilege, Protection},
+    threaded::BlockCache,
+    trap::{TrapContext, TrapHandler},
+    types::{
+        ConditionalFlags, MemomryTrait, Memory, Opcodes, RegisterStorage, RegisterStorageTrait,
+        Registers,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /* fetch, decode and execute one instruction at a time */
+    #[default]
+    Interpreter,
+    /* execute cached basic blocks of pre-decoded micro-ops */
+    Threaded,
+}
+
+impl Engine {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "interpreter" => Some(Engine::Interpreter),
+            "threaded" => Some(Engine::Threaded),
+            _ => None,
+        }
+    }
+}
+
+/* the instruction set the machine executes */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Isa {
+    #[default]
+    Lc3,
+    /* byte-addressed memory and the LC-3b instructions, see `lc3b` */
+    Lc3b,
+}
+
+impl Isa {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lc3" => Some(Isa::Lc3),
+            "lc3b" => Some(Isa::Lc3b),
+            _ => None,
+        }
+    }
+
+    /* the address distance between consecutive words */
+    pub fn word_step(self) -> u16 {
+        match self {
+            Isa::Lc3 => 1,
+            Isa::Lc3b => 2,
+        }
+    }
+}
+
+/* exceptions the machine raises itself, by vector */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    PrivilegeViolation = 0x00,
+    IllegalOpcode = 0x01,
+    AccessViolation = 0x02,
+    DivideByZero = 0x03,
+}
+
+/*
+the complete machine: registers, memory and the engine driving them.
+memory should be modified through `write_memory`/`load_image` so that
+compiled blocks are invalidated
+*/
+pub struct Vm {
+    pub register_storage: RegisterStorage,
+    pub memory: Memory,
+    pub console: Box<dyn Console>,
+    pub instruction_count: u64,
+    /* the stack pointer of the mode not currently running, swapped into R6 on mode changes */
+    pub saved_ssp: u16,
+    pub saved_usp: u16,
+    privilege: Privilege,
+    priority: u16,
+    engine: Engine,
+    isa: Isa,
+    revision: Revision,
+    block_cache: BlockCache,
+    limits: Limits,
+    output_bytes: Rc<Cell<u64>>,
+    protection: Option<Protection>,
+    exceptions: bool,
+    trap_table: bool,
+    trap_handlers: HashMap<u8, Box<dyn TrapHandler>>,
+    arithmetic_extension: bool,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_engine(Engine::default())
+    }
+
+    pub fn with_engine(engine: Engine) -> Self {
+        let mut register_storage = RegisterStorage::new();
+
+        /* since exactly one condition flag should be set at any given time, set the Z flag */
+        register_storage.locations[Registers::COND as usize] = ConditionalFlags::ZRO as u16;
+        /* set the PC to starting position, 0x3000 is the default */
+        register_storage.locations[Registers::PC as usize] = PC_START;
+
+        /* start the clock */
+        let mut memory = Memory::new();
+        memory.locations[MCR as usize] = CLOCK_ENABLE;
+
+        let output_bytes = Rc::new(Cell::new(0));
+
+        Self {
+            register_storage,
+            memory,
+            console: Box::new(CountingConsole::new(StdConsole, Rc::clone(&output_bytes))),
+            instruction_count: 0,
+            saved_ssp: SUPERVISOR_STACK_START,
+            saved_usp: 0,
+            privilege: Privilege::default(),
+            priority: 0,
+            engine,
+            isa: Isa::default(),
+            revision: Revision::default(),
+            block_cache: BlockCache::new(),
+            limits: Limits::default(),
+            output_bytes,
+            protection: None,
+            exceptions: false,
+            trap_table: false,
+            trap_handlers: HashMap::new(),
+            arithmetic_extension: false,
+        }
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+        self.block_cache.clear();
+    }
+
+    pub fn isa(&self) -> Isa {
+        self.isa
+    }
+
+    /*
+    switch the instruction set, before loading a program with `load_image`.
+    the LC-3b is always interpreted, whatever the engine; on it the trap
+    vector table holds a word per vector at `TRAP_VECTOR_TABLE + 2 * vector`
+    and exceptions and interrupts vector through `LC3B_INTERRUPT_VECTOR_TABLE`
+    */
+    pub fn set_isa(&mut self, isa: Isa) {
+        self.isa = isa;
+        self.block_cache.clear();
+    }
+
+    /* report register and memory accesses of this machine to `observer` */
+    #[cfg(feature = "instrument")]
+    pub fn set_observer(&mut self, observer: Rc<dyn Observer>) {
+        self.register_storage.observer = ObserverSlot::new(Some(Rc::clone(&observer)));
+        self.memory.observer = ObserverSlot::new(Some(observer));
+    }
+
+    pub fn set_console(&mut self, console: impl Console + 'static) {
+        self.console = Box::new(CountingConsole::new(console, Rc::clone(&self.output_bytes)));
+    }
+
+    /*
+    the console as a handle that devices can share with the machine, which
+    keeps writing through it as well
+    */
+    pub fn share_console(&mut self) -> SharedConsole {
+        let console = std::mem::replace(&mut self.console, Box::new(BufferConsole::new()));
+        let shared = SharedConsole::new(console);
+        self.console = Box::new(shared.clone());
+        shared
+    }
+
+    /* memory protection, checked before every instruction; `None` allows everything */
+    pub fn set_protection(&mut self, protection: Option<Protection>) {
+        self.protection = protection;
+    }
+
+    pub fn protection(&self) -> Option<&Protection> {
+        self.protection.as_ref()
+    }
+
+    /*
+    with exceptions enabled RTI is executed and illegal opcodes, access and
+    privilege violations vector through the interrupt vector table instead
+    of being returned as errors. device interrupts are only taken with
+    exceptions enabled
+    */
+    pub fn set_exceptions(&mut self, enabled: bool) {
+        self.exceptions = enabled;
+    }
+
+    pub fn exceptions(&self) -> bool {
+        self.exceptions
+    }
+
+    /*
+    with the trap table enabled TRAP enters the routine of the trap vector
+    table like an exception, in supervisor mode and returning with RTI,
+    instead of running the native routines. R7 is set to the return address
+    either way
+    */
+    pub fn set_trap_table(&mut self, enabled: bool) {
+        self.trap_table = enabled;
+    }
+
+    pub fn trap_table(&self) -> bool {
+        self.trap_table
+    }
+
+    /*
+    run `handler` on the host for TRAPs to `vector`, in place of the native
+    routine or the trap vector table. R7 is set to the return address before
+    it is called; it returns the handler it replaced, if any
+    */
+    pub fn register_trap(
+        &mut self,
+        vector: u8,
+        handler: impl TrapHandler + 'static,
+    ) -> Option<Box<dyn TrapHandler>> {
+        self.trap_handlers.insert(vector, Box::new(handler))
+    }
+
+    pub fn unregister_trap(&mut self, vector: u8) -> Option<Box<dyn TrapHandler>> {
+        self.trap_handlers.remove(&vector)
+    }
+
+    /*
+    with the arithmetic extension enabled the reserved opcode executes
+    MUL, DIV, MOD and shifts (see `Arithmetic`) instead of being illegal
+    */
+    pub fn set_arithmetic_extension(&mut self, enabled: bool) {
+        self.arithmetic_extension = enabled;
+        self.block_cache.set_arithmetic_extension(enabled);
+    }
+
+    pub fn arithmetic_extension(&self) -> bool {
+        self.arithmetic_extension
+    }
+
+    /* the LC-3 edition whose semantics apply where editions differ; see `Revision` */
+    pub fn set_revision(&mut self, revision: Revision) {
+        self.revision = revision;
+        self.block_cache.set_revision(revision);
+    }
+
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    pub fn privilege(&self) -> Privilege {
+        self.privilege
+    }
+
+    /* switch modes without touching the stacks, e.g. to start a program in supervisor mode */
+    pub fn set_privilege(&mut self, privilege: Privilege) {
+        self.privilege = privilege;
+    }
+
+    /* processor status register: privilege in bit 15, priority in bits 10-8, NZP in bits 2-0 */
+    pub fn psr(&self) -> u16 {
+        let user = (self.privilege == Privilege::User) as u16;
+        let cond = self.register_storage.locations[Registers::COND as usize] & 0x7;
+        user << 15 | (self.priority & 0x7) << 8 | cond
+    }
+
+    pub fn set_psr(&mut self, psr: u16) {
+        self.privilege = if psr >> 15 == 1 {
+            Privilege::User
+        } else {
+            Privilege::Supervisor
+        };
+        self.priority = (psr >> 8) & 0x7;
+        self.register_storage.locations[Registers::COND as usize] = psr & 0x7;
+    }
+
+    /*
+    enter the service routine for `vector` of the interrupt vector table:
+    switch to the supervisor stack if coming from user mode, push PSR and PC,
+    and continue at the routine. interrupts also raise the priority level
+    */
+    pub fn enter_service_routine(&mut self, vector: u16, priority: Option<u16>) {
+        let table = match self.isa {
+            Isa::Lc3 => INTERRUPT_VECTOR_TABLE,
+            Isa::Lc3b => LC3B_INTERRUPT_VECTOR_TABLE,
+        };
+        self.enter_routine(table, vector, priority);
+    }
+
+    fn enter_routine(&mut self, table: u16, vector: u16, priority: Option<u16>) {
+        let psr = self.psr();
+        let r6 = Registers::R6 as usize;
+
+        if self.privilege == Privilege::User {
+            self.saved_usp = self.register_storage.locations[r6];
+            self.register_storage.locations[r6] = self.saved_ssp;
+            self.privilege = Privilege::Supervisor;
+        }
+        if let Some(priority) = priority {
+            self.priority = priority & 0x7;
+        }
+
+        self.push(psr);
+        self.push(self.pc());
+        let entry = (vector & 0xFF) * self.isa.word_step();
+        let routine = self.memory.read(table.wrapping_add(entry));
+        self.set_pc(routine);
+    }
+
+    /* RTI: pop PC and PSR, going back to the user stack if returning to user mode */
+    fn return_from_interrupt(&mut self) {
+        let pc = self.pop();
+        let psr = self.pop();
+        self.set_pc(pc);
+        self.set_psr(psr);
+
+        if self.privilege == Privilege::User {
+            let r6 = Registers::R6 as usize;
+            self.saved_ssp = self.register_storage.locations[r6];
+            self.register_storage.locations[r6] = self.saved_usp;
+        }
+    }
+
+    fn push(&mut self, value: u16) {
+        let r6 = Registers::R6 as usize;
+        let sp = self.register_storage.locations[r6].wrapping_sub(self.isa.word_step());
+        self.register_storage.locations[r6] = sp;
+        self.write_memory(sp, value);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let r6 = Registers::R6 as usize;
+        let sp = self.register_storage.locations[r6];
+        self.register_storage.locations[r6] = sp.wrapping_add(self.isa.word_step());
+        self.memory.read(sp)
+    }
+
+    /* bytes written to the console so far */
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes.get()
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /* limits applied by `run_with_limits` */
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn block_cache(&self) -> &BlockCache {
+        &self.block_cache
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.register_storage.locations[Registers::PC as usize]
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.register_storage.locations[Registers::PC as usize] = pc;
+    }
+
+    pub fn write_memory(&mut self, address: u16, value: u16) {
+        self.memory.write(address, value);
+        self.block_cache.invalidate(address);
+    }
+
+    /* drop all compiled blocks, after memory was changed behind the machine's back */
+    pub fn flush_block_cache(&mut self) {
+        self.block_cache.clear();
+    }
+
+    pub fn load_image(&mut self, image: &Image) {
+        match self.isa {
+            Isa::Lc3 => image.load_into(&mut self.memory),
+            Isa::Lc3b => image.load_into_byte_addressed(&mut self.memory),
+        }
+        self.block_cache.clear();
+    }
+
+    /* whether the clock-enable bit of the MCR is clear, as HALT leaves it */
+    pub fn halted(&self) -> bool {
+        self.memory.locations[MCR as usize] & CLOCK_ENABLE == 0
+    }
+
+    /* execute a single instruction */
+    pub fn step(&mut self) -> Result<(), VmError> {
+        self.run(1)
+    }
+
+    /*
+    execute up to `max_instructions` instructions, stopping early once the
+    clock is stopped. with exceptions enabled, faults that have an exception
+    vector transfer control to their service routine instead of ending the run
+    */
+    pub fn run(&mut self, max_instructions: u64) -> Result<(), VmError> {
+        self.run_until(max_instructions, None)
+    }
+
+    /* `run`, also stopping once more than `max_output` bytes have been written */
+    fn run_until(&mut self, max_instructions: u64, max_output: Option<u64>) -> Result<(), VmError> {
+        let limit = self.instruction_count.saturating_add(max_instructions);
+        let devices = !self.memory.bus.is_empty();
+
+        while self.instruction_count < limit
+            && !self.halted()
+            && max_output.is_none_or(|max| self.output_bytes() <= max)
+        {
+            /* attached devices are ticked, and may interrupt, after every instruction */
+            let budget = if devices {
+                1
+            } else {
+                limit - self.instruction_count
+            };
+            let pc = self.pc();
+            let instr = self.memory.locations[pc as usize];
+            if devices {
+                /* devices reading the console timestamp input like traps do */
+                self.console.set_instruction_count(self.instruction_count);
+            }
+            let result = match (self.isa, self.engine) {
+                (Isa::Lc3b, _) => self.interpret_lc3b(budget),
+                (Isa::Lc3, Engine::Interpreter) => self.interpret(budget),
+                (Isa::Lc3, Engine::Threaded) => self.block_cache.run(
+                    &mut self.register_storage,
+                    &mut self.memory,
+                    self.console.as_mut(),
+                    budget,
+                    &mut self.instruction_count,
+                    self.protection.as_ref().map(|p| (p, self.privilege)),
+                ),
+            };
+
+            /* the engines leave TRAPs to the machine */
+            let result = match result {
+                Ok(Some(address)) => self.trap(address),
+                Ok(None) => Ok(()),
+                Err(err) => Err(err),
+            };
+            /* a device access that faulted fails the instruction that made it */
+            let result = match self.memory.bus_fault.take() {
+                Some(fault) if result.is_ok() => Err(VmError::new(pc, instr, fault)),
+                _ => result,
+            };
+
+            match result {
+                Ok(()) => {}
+                Err(err) if self.exceptions => self.take_exception(err)?,
+                Err(err) => return Err(err),
+            }
+            if devices {
+                self.console.set_instruction_count(self.instruction_count);
+                self.service_devices(bus::cycles(instr));
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    tick every device, let them access memory and, with exceptions enabled,
+    take the most urgent interrupt
+    */
+    fn service_devices(&mut self, cycles: u64) {
+        self.memory.bus.tick(cycles);
+        if self.memory.bus.dma(&mut self.memory.locations) {
+            self.block_cache.clear();
+        }
+
+        if self.exceptions
+            && let Some(interrupt) = self.memory.bus.pending_interrupt(self.priority)
+        {
+            self.enter_service_routine(interrupt.vector, Some(interrupt.priority));
+        }
+    }
+
+    /* interpret up to `budget` instructions, stopping at a TRAP and returning its address */
+    fn interpret(&mut self, budget: u64) -> Result<Option<u16>, VmError> {
+        for _ in 0..budget {
+            /* FETCH: get the instruction from memory at the address pointed to by the PC */
+            let pc = self.pc();
+            let instr = self.memory.read(pc);
+
+            /* INCREMENT PC */
+            self.set_pc(pc.wrapping_add(1));
+
+            if let Some(protection) = &self.protection {
+                protection
+                    .check(
+                        self.privilege,
+                        pc,
+                        instr,
+                        &self.register_storage,
+                        &self.memory,
+                    )
+                    .map_err(|violation| VmError::new(pc, instr, violation))?;
+            }
+
+            if instr >> 12 == Opcodes::TRAP as u16 {
+                return Ok(Some(pc));
+            }
+
+            let result = match Opcodes::from_u16(instr >> 12) {
+                Some(Opcodes::RES) if self.arithmetic_extension => {
+                    Instructions::arithmetic(&mut self.register_storage, instr)
+                }
+                Some(Opcodes::LEA) if !self.revision.lea_sets_flags() => {
+                    Instructions::effective_address(&mut self.register_storage, instr)
+                }
+                _ => Instructions::execute(
+                    &mut self.register_storage,
+                    &mut self.memory,
+                    self.console.as_mut(),
+                    instr,
+                ),
+            };
+            result.map_err(|err| VmError::new(pc, instr, err))?;
+            self.instruction_count += 1;
+
+            if self.halted() {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    /* `interpret` for the LC-3b */
+    fn interpret_lc3b(&mut self, budget: u64) -> Result<Option<u16>, VmError> {
+        for _ in 0..budget {
+            let pc = self.pc() & !1;
+            let instr = lc3b::read_word(&self.memory, pc);
+            self.set_pc(pc.wrapping_add(2));
+
+            if let Some(protection) = &self.protection {
+                lc3b::check(
+                    protection,
+                    self.privilege,
+                    pc,
+                    instr,
+                    &self.register_storage,
+                )
+                .map_err(|violation| VmError::new(pc, instr, violation))?;
+            }
+
+            if instr >> 12 == lc3b::Opcode::TRAP as u16 {
+                return Ok(Some(pc));
+            }
+
+            lc3b::execute(&mut self.register_storage, &mut self.memory, instr)
+                .map_err(|err| VmError::new(pc, instr, err))?;
+            self.instruction_count += 1;
+
+            if self.halted() {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    /*
+    execute the TRAP fetched from `address`, whose PC has been incremented:
+    by its registered handler, through the trap vector table or natively
+    */
+    fn trap(&mut self, address: u16) -> Result<(), VmError> {
+        let instr = self.memory.locations[address as usize];
+        let pc = self.pc();
+
+        if let Some(handler) = self.trap_handlers.get_mut(&(instr as u8)) {
+            self.register_storage.locations[Registers::R7 as usize] = pc;
+            self.console.set_instruction_count(self.instruction_count);
+
+            let mut context = TrapContext::new(
+                &mut self.register_storage,
+                &mut self.memory,
+                self.console.as_mut(),
+                self.protection.as_ref().map(|p| (p, self.privilege)),
+            );
+            let result = handler.call(&mut context);
+            for written in context.written() {
+                self.block_cache.invalidate(*written);
+            }
+            result.map_err(|fault| VmError::new(address, instr, fault))?;
+        } else if self.trap_table {
+            self.register_storage.locations[Registers::R7 as usize] = pc;
+            self.enter_routine(TRAP_VECTOR_TABLE, instr & 0xFF, None);
+        } else {
+            /* traps may consume console input, which is timestamped */
+            self.console.set_instruction_count(self.instruction_count);
+            let native = match self.isa {
+                Isa::Lc3 => Instructions::trap,
+                Isa::Lc3b => lc3b::trap,
+            };
+            native(
+                &mut self.register_storage,
+                &mut self.memory,
+                self.console.as_mut(),
+                instr,
+            )
+            .map_err(|err| VmError::new(address, instr, err))?;
+        }
+        self.instruction_count += 1;
+        Ok(())
+    }
+
+    /* turn a fault into the exception it stands for, or give it back */
+    fn take_exception(&mut self, err: VmError) -> Result<(), VmError> {
+        let exception = match (err.opcode, err.cause) {
+            (Some(Opcodes::RTI), Fault::IllegalOpcode)
+                if self.privilege == Privilege::Supervisor =>
+            {
+                self.instruction_count += 1;
+                self.return_from_interrupt();
+                return Ok(());
+            }
+            (Some(Opcodes::RTI), Fault::IllegalOpcode) => Exception::PrivilegeViolation,
+            (_, Fault::IllegalOpcode) => Exception::IllegalOpcode,
+            (_, Fault::AccessViolation { .. }) => Exception::AccessViolation,
+            (_, Fault::DivideByZero) => Exception::DivideByZero,
+            _ => return Err(err),
+        };
+
+        /* the faulting instruction retires; its service routine returns past it */
+        self.set_pc(err.pc.wrapping_add(self.isa.word_step()));
+        self.instruction_count += 1;
+        self.enter_service_routine(exception as u16, None);
+        Ok(())
+    }
+
+    /*
+    run until the program stops the clock, failing with `Fault::StepLimit` if
+    it has not after `max_instructions`
+    */
+    pub fn run_to_halt(&mut self, max_instructions: u64) -> Result<(), VmError> {
+        let limit = self.instruction_count.saturating_add(max_instructions);
+
+        while !self.halted() {
+            if self.instruction_count >= limit {
+                let pc = self.pc();
+                return Err(VmError::new(
+                    pc,
+                    self.memory.locations[pc as usize],
+                    Fault::StepLimit(max_instructions),
+                ));
+            }
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /* run until the program halts or one of the machine's limits stops it */
+    pub fn run_with_limits(&mut self) -> Result<StopReason, VmError> {
+        let mut tracker = LimitTracker::new(self.limits, self);
+
+        loop {
+            if let Some(reason) = tracker.check(self) {
+                return Ok(reason);
+            }
+            /* batches let the threaded engine run whole blocks */
+            self.run_until(tracker.budget(self), tracker.output_ceiling())?;
+        }
+    }
+}
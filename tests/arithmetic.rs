@@ -0,0 +1,191 @@
+mod common;
+
+use common::{ENGINES, machine_with};
+use virtual_machine::libs::{
+    asm,
+    constants::INTERRUPT_VECTOR_TABLE,
+    error::{Fault, VmError},
+    golden::{self, Spec},
+    instructions::Revision,
+    loader::Image,
+    os::Os,
+    translate::Translator,
+    types::{ConditionalFlags, Opcodes, Registers},
+    vm::{Engine, Exception, Vm},
+};
+
+/* R2 = R0 <op> R1 */
+fn operation(op: u16) -> u16 {
+    0xD000 | 2 << 9 | op << 3 | 1
+}
+
+/* machine setup enabling the extension */
+fn extension(vm: &mut Vm) {
+    vm.set_arithmetic_extension(true);
+}
+
+#[test]
+fn operations_compute_and_set_the_condition_codes() {
+    let cases: [(u16, u16, u16, u16); 12] = [
+        (0, 7, 6, 42),
+        (0, -3i16 as u16, 5, -15i16 as u16),
+        (0, 0x4000, 4, 0),
+        (1, 7, 2, 3),
+        (1, -7i16 as u16, 2, -3i16 as u16),
+        (1, 0x8000, 0xFFFF, 0x8000),
+        (2, -7i16 as u16, 2, -1i16 as u16),
+        (2, 7, -2i16 as u16, 1),
+        (3, 0x0101, 4, 0x1010),
+        (3, 1, 16, 0),
+        (4, 0x8000, 15, 1),
+        (5, 0x8000, 20, 0xFFFF),
+    ];
+
+    for engine in ENGINES {
+        for (op, a, b, expected) in cases {
+            let (mut vm, _) = machine_with(engine, &[operation(op)], extension);
+            vm.register_storage.locations[Registers::R0 as usize] = a;
+            vm.register_storage.locations[Registers::R1 as usize] = b;
+            vm.step().unwrap();
+
+            let flag = match expected {
+                0 => ConditionalFlags::ZRO,
+                value if value >> 15 == 1 => ConditionalFlags::NEG,
+                _ => ConditionalFlags::POS,
+            };
+            let regs = &vm.register_storage.locations;
+            assert_eq!(
+                regs[Registers::R2 as usize],
+                expected,
+                "op {op} {a:#x} {b:#x}"
+            );
+            assert_eq!(regs[Registers::COND as usize], flag as u16);
+        }
+    }
+}
+
+#[test]
+fn the_reserved_opcode_stays_illegal_unless_enabled() {
+    for engine in ENGINES {
+        let (mut vm, _) = machine_with(
+            engine,
+            &[operation(0), operation(6), operation(7)],
+            extension,
+        );
+        vm.set_arithmetic_extension(false);
+        let err = vm.step().unwrap_err();
+        assert_eq!(
+            (err.opcode, err.cause),
+            (Some(Opcodes::RES), Fault::IllegalOpcode)
+        );
+
+        /* operations 110 and 111 are illegal either way */
+        vm.set_arithmetic_extension(true);
+        vm.set_pc(0x3000);
+        vm.step().unwrap();
+        for op in [6, 7] {
+            let err = vm.step().unwrap_err();
+            assert_eq!(
+                (err.instr, err.cause),
+                (operation(op), Fault::IllegalOpcode)
+            );
+        }
+    }
+}
+
+#[test]
+fn division_by_zero_faults() {
+    for engine in ENGINES {
+        for op in [1, 2] {
+            let (mut vm, _) = machine_with(engine, &[0x1021, operation(op)], extension);
+            let err = vm.run(10).unwrap_err();
+            assert_eq!((err.pc, err.cause), (0x3001, Fault::DivideByZero));
+        }
+    }
+}
+
+#[test]
+fn division_by_zero_raises_an_exception() {
+    for engine in ENGINES {
+        let (mut vm, _) = machine_with(engine, &[operation(1), 0x1261], extension);
+        vm.set_exceptions(true);
+        vm.write_memory(
+            INTERRUPT_VECTOR_TABLE + Exception::DivideByZero as u16,
+            0x0400,
+        );
+        vm.write_memory(0x0400, 0x8000); /* RTI */
+        vm.run(3).unwrap();
+
+        /* the handler returned past the DIV */
+        assert_eq!(vm.pc(), 0x3002);
+        assert_eq!(vm.register_storage.locations[Registers::R1 as usize], 1);
+
+        /* the OS reports it and halts */
+        let (mut vm, console) = machine_with(engine, &[operation(2)], extension);
+        Os::bundled().boot(&mut vm).unwrap();
+        vm.run(1_000).unwrap();
+        assert!(vm.halted());
+        assert_eq!(console.output(), b"Division by zero\nProgram halted\n");
+    }
+}
+
+#[test]
+fn the_assembler_encodes_the_extension() {
+    let program = asm::assemble(
+        ".ORIG x3000\nMUL R2, R0, R1\nDIV R2, R0, R1\nMOD R2, R0, R1\nSHL R2, R0, R1\nSHR R2, R0, R1\nSRA R2, R0, R1\n.END",
+    )
+    .unwrap();
+    let expected: Vec<u16> = (0..6).map(operation).collect();
+    assert_eq!(program.image.words, expected);
+}
+
+#[test]
+fn golden_specs_enable_the_extension_and_trace_its_operations() {
+    /* MUL R2, R0, R1; SRA R2, R0, R1; BRnzp #-1 */
+    let image = Image {
+        origin: 0x3000,
+        words: vec![operation(0), operation(5), 0x0FFF],
+    };
+    let spec = Spec::parse(
+        "arithmetic-extension\nset-register R0 #6\nset-register R1 #1\nmax-instructions 4",
+    )
+    .unwrap();
+    assert!(spec.arithmetic_extension);
+
+    for engine in ENGINES {
+        let result = golden::run_case("spin", &image, &spec, engine);
+        let trace: Vec<String> = result.trace.iter().map(ToString::to_string).collect();
+        assert_eq!(trace[0], "0x3000: 0xd401 MUL");
+        assert_eq!(trace[1], "0x3001: 0xd429 SRA");
+    }
+
+    /* without the directive the reserved opcode faults */
+    let spec = Spec::parse("max-instructions 10").unwrap();
+    let result = golden::run_case("spin", &image, &spec, Engine::Interpreter);
+    assert_eq!(result.trace[0].to_string(), "0x3000: 0xd401 RES");
+    assert!(matches!(
+        result.failures[..],
+        [golden::Failure::Fault(VmError {
+            cause: Fault::IllegalOpcode,
+            ..
+        })]
+    ));
+}
+
+#[test]
+fn the_translator_lifts_the_extension() {
+    let image = Image {
+        origin: 0x3000,
+        words: vec![operation(0), 0xF025],
+    };
+
+    let source = Translator::new(&image, Revision::Legacy, true).emit(false);
+    assert!(source.contains("Instructions::arithmetic(rs, 0xd401)?;"));
+    assert!(source.contains("Trap::execute_trap_instruction(rs, mem, console, 0x25 /* HALT */)?;"));
+
+    /* left out, the reserved opcode ends the block as an illegal instruction */
+    let translator = Translator::new(&image, Revision::Legacy, false);
+    let source = translator.emit(false);
+    assert!(source.contains("return Err(InstructionSetError::BadOpcode(0xd401));"));
+    assert!(!source.contains("HALT"));
+}
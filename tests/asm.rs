@@ -0,0 +1,108 @@
+use virtual_machine::libs::asm::{self, AsmError};
+
+#[test]
+fn assembles_instructions_and_directives() {
+    let program = asm::assemble(
+        "
+        .ORIG x3000
+START   ADD R1, R1, #-1     ; decrement
+        AND R2, R2, R3
+        NOT R4, R5
+        BRnp START
+        LD R0, VALUE
+        LDR R0, R1, #-2
+        LEA R0, TEXT
+        JSR START
+        JSRR R2
+        RET
+        TRAP x23
+        HALT
+VALUE   .FILL xBEEF
+        .BLKW 2
+TEXT    .STRINGZ \"a;b\\n\"
+        .END
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(program.image.origin, 0x3000);
+    assert_eq!(
+        program.image.words,
+        [
+            0x127F, 0x5483, 0x997F, 0x0BFC, 0x2007, 0x607E, 0xE008, 0x4FF8, 0x4080, 0xC1C0, 0xF023,
+            0xF025, 0xBEEF, 0, 0, 0x61, 0x3B, 0x62, 0x0A, 0,
+        ]
+    );
+    assert_eq!(program.symbols["TEXT"], 0x300F);
+}
+
+#[test]
+fn numbers_in_every_base() {
+    let program = asm::assemble(".ORIG #12288\n.FILL b101\n.FILL #-1\n.FILL 10\n.END").unwrap();
+    assert_eq!(program.image.origin, 0x3000);
+    assert_eq!(program.image.words, [5, 0xFFFF, 10]);
+}
+
+#[test]
+fn reports_the_line_of_errors() {
+    let cases = [
+        (
+            ".ORIG x3000\nBR NOWHERE",
+            AsmError::UndefinedLabel {
+                line: 2,
+                label: "NOWHERE".to_string(),
+            },
+        ),
+        (
+            ".ORIG x3000\nADD R0, R0, #16",
+            AsmError::OutOfRange {
+                line: 2,
+                value: 16,
+                bits: 5,
+            },
+        ),
+        (
+            ".ORIG x3000\nA ADD R0, R0, R0\nA NOT R0, R0",
+            AsmError::DuplicateLabel {
+                line: 3,
+                label: "A".to_string(),
+            },
+        ),
+        (
+            "ADD R0, R0, R0",
+            AsmError::MissingOrig {
+                line: 1,
+                found: "ADD".to_string(),
+            },
+        ),
+        (
+            ".ORIG x3000\nNOT R0, R9",
+            AsmError::InvalidOperand {
+                line: 2,
+                operand: "R9".to_string(),
+            },
+        ),
+    ];
+
+    for (source, expected) in cases {
+        assert_eq!(asm::assemble(source).err(), Some(expected), "{source}");
+    }
+}
+
+#[test]
+fn branches_reach_the_ends_of_their_range() {
+    let mut source = String::from(".ORIG x3000\nBRz FAR\n");
+    source.push_str(&".FILL 0\n".repeat(255));
+    source.push_str("FAR BR BACK\n");
+    source.push_str(&".FILL 0\n".repeat(255));
+    source.push_str("BACK .FILL 0\n");
+    let program = asm::assemble(&source).unwrap();
+    assert_eq!(program.image.words[0], 0x04FF);
+    assert_eq!(program.image.words[256], 0x0EFF);
+
+    source.insert_str(source.find("FAR BR").unwrap(), ".FILL 0\n");
+    assert!(matches!(
+        asm::assemble(&source),
+        Err(AsmError::OutOfRange { line: 2, .. })
+    ));
+}
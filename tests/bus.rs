@@ -0,0 +1,186 @@
+mod common;
+
+use common::{ENGINES, machine};
+use virtual_machine::libs::{
+    bus::{self, BusError, Device, Keyboard},
+    console::{BufferConsole, Console},
+    constants::{KBDR, KBSR},
+    loader::Image,
+    protection::Privilege,
+    types::{MemomryTrait, Memory, Registers},
+    vm::Engine,
+};
+
+/* counts instructions and remembers the last value written to it */
+#[derive(Default)]
+struct Counter {
+    ticks: u16,
+    written: Option<(u16, u16)>,
+}
+
+impl Device for Counter {
+    fn name(&self) -> &str {
+        "counter"
+    }
+
+    fn peek(&self, offset: u16) -> u16 {
+        self.ticks + offset
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        self.written = Some((offset, value));
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        self.ticks += 1;
+    }
+}
+
+#[test]
+fn devices_take_over_their_addresses() {
+    let mut memory = Memory::new();
+    memory.locations[0x4001] = 0x1111;
+    let counter = memory
+        .bus
+        .attach(0x4000, 0x4003, Counter::default())
+        .unwrap();
+
+    assert_eq!(memory.read(0x4001), 1);
+    memory.write(0x4002, 0xBEEF);
+    assert_eq!(counter.borrow().written, Some((2, 0xBEEF)));
+    assert_eq!(memory.locations[0x4002], 0);
+
+    /* RAM still backs everything else */
+    memory.write(0x4004, 7);
+    assert_eq!(memory.read(0x4004), 7);
+    assert!(memory.bus.is_mapped(0x4003) && !memory.bus.is_mapped(0x4004));
+
+    assert!(matches!(
+        memory.bus.attach(0x3FF0, 0x4000, Counter::default()),
+        Err(BusError::Overlap { .. })
+    ));
+    assert!(matches!(
+        memory.bus.attach(0x5000, 0x4FFF, Counter::default()),
+        Err(BusError::EmptyRange { .. })
+    ));
+}
+
+#[test]
+fn devices_tick_once_per_instruction() {
+    for engine in ENGINES {
+        /* loop: ADD R3, R3, #1; BRnzp loop */
+        let (mut vm, _) = machine(engine, &[0x16E1, 0x0FFE]);
+        let counter = vm
+            .memory
+            .bus
+            .attach(0x4000, 0x4000, Counter::default())
+            .unwrap();
+
+        vm.run(10).unwrap();
+        assert_eq!(counter.borrow().ticks, 10);
+        assert_eq!(vm.register_storage.locations[Registers::R3 as usize], 5);
+    }
+}
+
+#[test]
+fn programs_echo_through_the_console_registers() {
+    /*
+    poll: LDI R0, KBSR; BRzp poll; LDI R0, KBDR; STI R0, DDR; BRnzp poll
+    */
+    let program = [
+        0xA004, 0x07FE, 0xA003, 0xB003, 0x0FFB, 0xFE00, 0xFE02, 0xFE06,
+    ];
+
+    for engine in ENGINES {
+        let (mut vm, _) = machine(engine, &program);
+        let console = BufferConsole::with_input(b"hi");
+        bus::attach_console_devices(&mut vm.memory.bus, console.clone()).unwrap();
+
+        vm.run(40).unwrap();
+        assert_eq!(console.output(), b"hi");
+        /* still polling the empty keyboard */
+        assert!(vm.pc() <= 0x3001);
+    }
+}
+
+#[test]
+fn keyboard_interrupts_run_the_service_routine() {
+    /* STI R1, KBSR; loop: ADD R3, R3, #1; BRnzp loop */
+    let program = [0xB202, 0x16E1, 0x0FFE, 0xFE00];
+    /* LDI R2, KBDR; RTI */
+    let handler = [0xA401, 0x8000, KBDR];
+
+    for engine in ENGINES {
+        let (mut vm, _) = machine(engine, &program);
+        vm.set_exceptions(true);
+        vm.register_storage.locations[Registers::R1 as usize] = 0x4000;
+        vm.register_storage.locations[Registers::R6 as usize] = 0x5000;
+        vm.write_memory(0x0180, 0x1000);
+        vm.load_image(&Image {
+            origin: 0x1000,
+            words: handler.to_vec(),
+        });
+        let keyboard = vm
+            .memory
+            .bus
+            .attach(
+                KBSR,
+                KBDR + 1,
+                Keyboard::new(BufferConsole::with_input(b"x")),
+            )
+            .unwrap();
+
+        vm.step().unwrap();
+        assert_eq!(vm.pc(), 0x1000);
+        assert_eq!(vm.privilege(), Privilege::Supervisor);
+        assert_eq!(vm.psr() >> 8 & 0x7, 4);
+
+        vm.run(2).unwrap();
+        let registers = &vm.register_storage.locations;
+        assert_eq!(registers[Registers::R2 as usize], u16::from(b'x'));
+        assert_eq!(registers[Registers::R6 as usize], 0x5000);
+        assert_eq!((vm.pc(), vm.privilege()), (0x3001, Privilege::User));
+        assert_eq!(keyboard.borrow_mut().read(0), 0x4000);
+
+        vm.run(4).unwrap();
+        assert_eq!(vm.register_storage.locations[Registers::R3 as usize], 2);
+    }
+}
+
+#[test]
+fn interrupts_need_exceptions_enabled() {
+    let (mut vm, _) = machine(Engine::Interpreter, &[0xB202, 0x16E1, 0x0FFE, 0xFE00]);
+    vm.register_storage.locations[Registers::R1 as usize] = 0x4000;
+    bus::attach_console_devices(&mut vm.memory.bus, BufferConsole::with_input(b"x")).unwrap();
+
+    vm.run(5).unwrap();
+    assert_eq!(vm.register_storage.locations[Registers::R3 as usize], 2);
+    assert_eq!(vm.privilege(), Privilege::User);
+}
+
+/* a terminal nobody types on: reading waits forever, polling finds nothing */
+#[derive(Clone)]
+struct IdleConsole;
+
+impl Console for IdleConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        panic!("blocked waiting for input");
+    }
+
+    fn poll_byte(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn write_byte(&mut self, _byte: u8) {}
+}
+
+#[test]
+fn keyboard_polls_without_blocking() {
+    /* STI R1, KBSR; ADD R3, R3, #1; BRnzp #-2 */
+    let (mut vm, _) = machine(Engine::Interpreter, &[0xB202, 0x16E1, 0x0FFE, 0xFE00]);
+    vm.register_storage.locations[Registers::R1 as usize] = 0x4000;
+    bus::attach_console_devices(&mut vm.memory.bus, IdleConsole).unwrap();
+
+    vm.run(100).unwrap();
+    assert_eq!(vm.memory.read(KBSR), 0x4000);
+}
@@ -0,0 +1,74 @@
+/*
+helpers shared by the integration tests. every test crate compiles its own
+copy and uses only some of them
+*/
+#![allow(dead_code)]
+
+use virtual_machine::libs::{
+    console::BufferConsole,
+    loader::Image,
+    types::Registers,
+    vm::{Engine, Vm},
+};
+
+/* tests that hold for either engine run on both */
+pub const ENGINES: [Engine; 2] = [Engine::Interpreter, Engine::Threaded];
+
+/* a user program at x3000 */
+pub fn image(words: &[u16]) -> Image {
+    Image {
+        origin: 0x3000,
+        words: words.to_vec(),
+    }
+}
+
+/* a machine with `words` loaded at x3000, writing to the returned console */
+pub fn machine(engine: Engine, words: &[u16]) -> (Vm, BufferConsole) {
+    machine_with(engine, words, |_| {})
+}
+
+/*
+`machine`, configured by `setup` before the program is loaded (the ISA
+decides how an image is laid out in memory)
+*/
+pub fn machine_with(
+    engine: Engine,
+    words: &[u16],
+    setup: impl FnOnce(&mut Vm),
+) -> (Vm, BufferConsole) {
+    let console = BufferConsole::new();
+    let mut vm = Vm::with_engine(engine);
+    vm.set_console(console.clone());
+    setup(&mut vm);
+    vm.load_image(&image(words));
+    (vm, console)
+}
+
+/* seed the general purpose registers of `vm` */
+pub fn set_registers(vm: &mut Vm, registers: &[(Registers, u16)]) {
+    for (register, value) in registers {
+        vm.register_storage.locations[*register as usize] = *value;
+    }
+}
+
+/* xorshift64, so random inputs are reproducible from their seed */
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn word(&mut self) -> u16 {
+        (self.next_u64() >> 16) as u16
+    }
+
+    /* up to `max_len` random bytes */
+    pub fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+        let len = self.next_u64() as usize % (max_len + 1);
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+}
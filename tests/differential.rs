@@ -0,0 +1,437 @@
+mod common;
+mod reference;
+
+use std::fmt::Write;
+
+use common::Rng;
+use reference::{Outcome, Reference};
+use virtual_machine::libs::{
+    types::Registers,
+    vm::{Engine, Vm},
+};
+
+const ORIGIN: u16 = 0x3000;
+
+/* a program at ORIGIN, the initial R0-R7, and optionally random background memory */
+#[derive(Debug, Clone)]
+struct Case {
+    registers: [u16; 8],
+    program: Vec<u16>,
+    memory_seed: Option<u64>,
+    steps: usize,
+}
+
+impl Case {
+    fn memory(&self) -> Vec<u16> {
+        let mut memory = vec![0u16; 1 << 16];
+        if let Some(seed) = self.memory_seed {
+            let mut rng = Rng(seed | 1);
+            memory.iter_mut().for_each(|word| *word = rng.word());
+        }
+        let origin = ORIGIN as usize;
+        memory[origin..origin + self.program.len()].copy_from_slice(&self.program);
+        /* the clock runs until a store to the MCR stops it */
+        memory[reference::MCR] |= 0x8000;
+        memory
+    }
+
+    fn listing(&self) -> String {
+        let mut out = String::new();
+        for (offset, word) in self.program.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "  {:#06x}: {word:#06x} {:016b} {}",
+                ORIGIN as usize + offset,
+                word,
+                Reference::mnemonic(*word)
+            );
+        }
+        let _ = write!(
+            out,
+            "  registers: {:04x?}\n  background memory: {:?}",
+            self.registers, self.memory_seed
+        );
+        out
+    }
+}
+
+/* a machine under test, driven one instruction at a time */
+trait Machine {
+    fn boot(case: &Case) -> Self;
+    /* `false` when the instruction faulted */
+    fn step(&mut self) -> bool;
+    fn registers(&self) -> [u16; 8];
+    fn pc(&self) -> u16;
+    fn cc(&self) -> u16;
+    fn read(&self, address: u16) -> u16;
+}
+
+struct VmUnderTest<const THREADED: bool>(Vm);
+
+impl<const THREADED: bool> Machine for VmUnderTest<THREADED> {
+    fn boot(case: &Case) -> Self {
+        let engine = if THREADED {
+            Engine::Threaded
+        } else {
+            Engine::Interpreter
+        };
+        let mut vm = Vm::with_engine(engine);
+        vm.memory.locations.copy_from_slice(&case.memory());
+        vm.flush_block_cache();
+        vm.register_storage.locations[..8].copy_from_slice(&case.registers);
+        vm.set_pc(ORIGIN);
+        Self(vm)
+    }
+
+    fn step(&mut self) -> bool {
+        self.0.step().is_ok()
+    }
+
+    fn registers(&self) -> [u16; 8] {
+        self.0.register_storage.locations[..8].try_into().unwrap()
+    }
+
+    fn pc(&self) -> u16 {
+        self.0.pc()
+    }
+
+    fn cc(&self) -> u16 {
+        self.0.register_storage.locations[Registers::COND as usize]
+    }
+
+    fn read(&self, address: u16) -> u16 {
+        self.0.memory.locations[address as usize]
+    }
+}
+
+impl Machine for Reference {
+    fn boot(case: &Case) -> Self {
+        Reference::new(case.registers, ORIGIN, case.memory())
+    }
+
+    fn step(&mut self) -> bool {
+        Reference::step(self) != Outcome::Illegal
+    }
+
+    fn registers(&self) -> [u16; 8] {
+        self.regs
+    }
+
+    fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    fn cc(&self) -> u16 {
+        self.cc
+    }
+
+    fn read(&self, address: u16) -> u16 {
+        self.memory[address as usize]
+    }
+}
+
+#[derive(Debug)]
+struct Divergence {
+    step: usize,
+    pc: u16,
+    word: u16,
+    detail: String,
+}
+
+/* run `case` on the oracle and on `M`, comparing the architectural state after every step */
+fn first_divergence<M: Machine>(case: &Case) -> Option<Divergence> {
+    let mut oracle = <Reference as Machine>::boot(case);
+    let mut machine = M::boot(case);
+
+    for step in 0..case.steps {
+        let pc = oracle.pc;
+        let word = oracle.memory[pc as usize];
+        let diverged = |detail: String| {
+            Some(Divergence {
+                step,
+                pc,
+                word,
+                detail,
+            })
+        };
+
+        let expected = oracle.step();
+        if expected == Outcome::Unsupported {
+            return None;
+        }
+        let retired = machine.step();
+        if expected == Outcome::Halted {
+            /* a stopped machine must not move */
+            if machine.pc() != oracle.pc {
+                return diverged(format!("pc {:#06x} with the clock stopped", machine.pc()));
+            }
+            return None;
+        }
+
+        match expected {
+            Outcome::Illegal if retired => return diverged("retired an illegal opcode".into()),
+            Outcome::Illegal => return None,
+            _ if !retired => return diverged("faulted on a legal instruction".into()),
+            _ => {}
+        }
+
+        if machine.registers() != oracle.regs {
+            return diverged(format!(
+                "registers {:04x?}, expected {:04x?}",
+                machine.registers(),
+                oracle.regs
+            ));
+        }
+        if machine.pc() != oracle.pc {
+            return diverged(format!(
+                "pc {:#06x}, expected {:#06x}",
+                machine.pc(),
+                oracle.pc
+            ));
+        }
+        if machine.cc() != oracle.cc {
+            return diverged(format!(
+                "nzp {:03b}, expected {:03b}",
+                machine.cc(),
+                oracle.cc
+            ));
+        }
+        if let Outcome::Retired(Some((address, value))) = expected
+            && machine.read(address) != value
+        {
+            return diverged(format!(
+                "memory[{address:#06x}] = {:#06x}, expected {value:#06x}",
+                machine.read(address)
+            ));
+        }
+    }
+
+    (0..=u16::MAX)
+        .find(|address| machine.read(*address) != oracle.memory[*address as usize])
+        .map(|address| Divergence {
+            step: case.steps,
+            pc: oracle.pc,
+            word: 0,
+            detail: format!("memory[{address:#06x}] differs at the end of the run"),
+        })
+}
+
+/* simpler variants of a case, most aggressive first */
+fn candidates(case: &Case) -> Vec<Case> {
+    let mut out = Vec::new();
+    let with = |change: &dyn Fn(&mut Case)| {
+        let mut candidate = case.clone();
+        change(&mut candidate);
+        candidate
+    };
+
+    if case.memory_seed.is_some() {
+        out.push(with(&|c| c.memory_seed = None));
+    }
+    for length in [case.program.len() / 2, case.program.len().saturating_sub(1)] {
+        if length < case.program.len() {
+            out.push(with(&|c| c.program.truncate(length)));
+        }
+    }
+    for index in 0..case.program.len() {
+        if case.program[index] != 0 {
+            /* 0x0000 is BR with no condition bits: a no-op */
+            out.push(with(&|c| c.program[index] = 0));
+        }
+    }
+    for index in 0..8 {
+        if case.registers[index] != 0 {
+            out.push(with(&|c| c.registers[index] = 0));
+        }
+    }
+    for index in 0..case.program.len() {
+        for bit in (0..16).filter(|bit| case.program[index] & (1 << bit) != 0) {
+            out.push(with(&|c| c.program[index] &= !(1 << bit)));
+        }
+    }
+    for index in 0..8 {
+        for bit in (0..16).filter(|bit| case.registers[index] & (1 << bit) != 0) {
+            out.push(with(&|c| c.registers[index] &= !(1 << bit)));
+        }
+    }
+    out
+}
+
+/* greedily simplify a diverging case until no candidate still diverges */
+fn shrink<M: Machine>(mut case: Case) -> Case {
+    while let Some(smaller) = candidates(&case)
+        .into_iter()
+        .find(|candidate| first_divergence::<M>(candidate).is_some())
+    {
+        case = smaller;
+    }
+    case
+}
+
+fn check<M: Machine>(case: Case, name: &str) {
+    if first_divergence::<M>(&case).is_none() {
+        return;
+    }
+
+    let minimal = shrink::<M>(case);
+    let divergence = first_divergence::<M>(&minimal).unwrap();
+    panic!(
+        "{name} diverged from the reference at step {} (pc {:#06x}, {:#06x} {}): {}\nminimal case:\n{}",
+        divergence.step,
+        divergence.pc,
+        divergence.word,
+        Reference::mnemonic(divergence.word),
+        divergence.detail,
+        minimal.listing()
+    );
+}
+
+fn random_case(rng: &mut Rng, seed: u64) -> Case {
+    let program = (0..24)
+        .map(|_| {
+            loop {
+                /* TRAP does host I/O and is outside the model */
+                let word = rng.word();
+                if word >> 12 != 0xF {
+                    break word;
+                }
+            }
+        })
+        .collect();
+
+    /* half the registers point into the program so JMP/JSRR/LDR/STR land somewhere interesting */
+    let registers = std::array::from_fn(|index| {
+        let value = rng.word();
+        if index % 2 == 0 {
+            ORIGIN + (value & 0x1F)
+        } else {
+            value
+        }
+    });
+
+    Case {
+        registers,
+        program,
+        memory_seed: seed.is_multiple_of(2).then_some(seed),
+        steps: 48,
+    }
+}
+
+fn corpus() -> Vec<(&'static str, Case)> {
+    let case = |program: &[u16], steps| Case {
+        registers: [0; 8],
+        program: program.to_vec(),
+        memory_seed: None,
+        steps,
+    };
+
+    vec![
+        /* AND R0,R0,#0; ADD R1,R0,#10; ADD R0,R0,#3; ST R0,#3; ADD R1,R1,#-1; BRp #-4; BRnzp #-1 */
+        (
+            "counting loop",
+            case(
+                &[0x5020, 0x122A, 0x1023, 0x3003, 0x127F, 0x03FC, 0x0FFF],
+                80,
+            ),
+        ),
+        /* recursive SUM(12) with a stack in R6 */
+        (
+            "recursive calls",
+            case(
+                &[
+                    0x2C12, 0x5020, 0x102C, 0x4801, 0x0FFB, 0x1DBF, 0x7F80, 0x1DBF, 0x7380, 0x1220,
+                    0x0403, 0x103F, 0x4FF8, 0x1001, 0x6380, 0x1DA1, 0x6F80, 0x1DA1, 0xC1C0, 0x6000,
+                ],
+                400,
+            ),
+        ),
+        /* JSRR through R7 itself, then RET */
+        ("jsrr r7", case(&[0xEE02, 0x41C0, 0x0FFF, 0xC1C0], 4)),
+        /* LDI/STI through a pointer, LEA and NOT flags */
+        (
+            "indirect access",
+            case(&[0xA204, 0x1261, 0xB202, 0xE3FC, 0x967F, 0x3005], 5),
+        ),
+        /* LEA R1,#2; LDR R0,R1,#0 loads into R0, not the base register */
+        (
+            "ldr destination",
+            case(&[0xE202, 0x6040, 0x0FFF, 0x1234], 3),
+        ),
+    ]
+}
+
+#[test]
+fn corpus_matches_reference() {
+    for (name, case) in corpus() {
+        check::<VmUnderTest<false>>(case.clone(), &format!("interpreter on {name}"));
+        check::<VmUnderTest<true>>(case, &format!("threaded engine on {name}"));
+    }
+}
+
+#[test]
+fn random_streams_match_reference() {
+    let mut rng = Rng(0xD1FF_0000_0000_0001);
+
+    for seed in 0..300 {
+        let case = random_case(&mut rng, seed);
+        check::<VmUnderTest<false>>(case.clone(), &format!("interpreter, seed {seed}"));
+        check::<VmUnderTest<true>>(case, &format!("threaded engine, seed {seed}"));
+    }
+}
+
+/* a reference with a deliberately broken NOT, to check the harness itself */
+struct FaultyNot(Reference);
+
+impl Machine for FaultyNot {
+    fn boot(case: &Case) -> Self {
+        Self(<Reference as Machine>::boot(case))
+    }
+
+    fn step(&mut self) -> bool {
+        let word = self.0.memory[self.0.pc as usize];
+        let retired = Machine::step(&mut self.0);
+        if word >> 12 == 0b1001 {
+            self.0.cc = reference::Z;
+        }
+        retired
+    }
+
+    fn registers(&self) -> [u16; 8] {
+        self.0.regs
+    }
+
+    fn pc(&self) -> u16 {
+        self.0.pc
+    }
+
+    fn cc(&self) -> u16 {
+        self.0.cc
+    }
+
+    fn read(&self, address: u16) -> u16 {
+        self.0.read(address)
+    }
+}
+
+#[test]
+fn shrinks_divergence_to_a_single_instruction() {
+    let mut rng = Rng(0x0BAD_0000_0000_0001);
+    let case = (0..)
+        .map(|seed| random_case(&mut rng, seed))
+        .find(|case| first_divergence::<FaultyNot>(case).is_some())
+        .unwrap();
+
+    let minimal = shrink::<FaultyNot>(case);
+
+    /* one NOT, with as few bits set as still produce a non-zero result */
+    let live: Vec<u16> = minimal
+        .program
+        .iter()
+        .copied()
+        .filter(|w| *w != 0)
+        .collect();
+    assert_eq!(live.len(), 1, "{}", minimal.listing());
+    assert_eq!(Reference::mnemonic(live[0]), "NOT");
+    assert_eq!(minimal.memory_seed, None);
+    assert_eq!(minimal.registers, [0; 8]);
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use common::ENGINES;
+use virtual_machine::libs::{
+    bus::Device,
+    console::BufferConsole,
+    disk::{self, DMA, DONE, Disk, ERROR, READ, READY, SECTOR_WORDS, WRITE},
+    loader::Image,
+    types::Registers,
+    vm::Vm,
+};
+
+/* DKCR, DKSR, DKSEC, DKADR and DKDATA offsets */
+const CONTROL: u16 = 0;
+const STATUS: u16 = 2;
+const SECTOR: u16 = 4;
+const ADDRESS: u16 = 6;
+const DATA: u16 = 8;
+
+/* a disk image in its own directory, removed when dropped */
+struct ImageFile(PathBuf);
+
+impl ImageFile {
+    fn new(name: &str, words: &[u16]) -> Self {
+        let dir = env::temp_dir().join(format!("lc3-disk-{name}-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("disk.img");
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        fs::write(&path, bytes).unwrap();
+        Self(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ImageFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(self.0.parent().unwrap());
+    }
+}
+
+/* sector `n` holds the words `n << 8 | i` */
+fn sectors(count: u16) -> Vec<u16> {
+    (0..count)
+        .flat_map(|sector| (0..SECTOR_WORDS as u16).map(move |i| sector << 8 | i))
+        .collect()
+}
+
+#[test]
+fn reads_sectors_through_the_buffer() {
+    let image = ImageFile::new("buffer-read", &sectors(2));
+    let mut disk = Disk::open(image.path()).unwrap();
+    let mut ram = vec![0; 0x10000];
+
+    disk.write(SECTOR, 1);
+    disk.write(CONTROL, READ);
+    assert_eq!(disk.read(STATUS), 0);
+
+    disk.tick(1);
+    assert!(!disk.dma(&mut ram));
+    assert_eq!(disk.read(STATUS), READY | DONE);
+    assert_eq!(disk.read(STATUS), READY);
+
+    let words: Vec<u16> = (0..SECTOR_WORDS).map(|_| disk.read(DATA)).collect();
+    assert_eq!(words, sectors(2)[SECTOR_WORDS..]);
+
+    /* past the end of the image */
+    disk.write(SECTOR, 9);
+    disk.write(CONTROL, READ);
+    disk.tick(1);
+    disk.dma(&mut ram);
+    assert_eq!(disk.read(STATUS), READY | DONE);
+    assert!((0..SECTOR_WORDS).all(|_| disk.read(DATA) == 0));
+}
+
+#[test]
+fn writes_grow_the_image() {
+    let image = ImageFile::new("buffer-write", &sectors(1));
+    let mut disk = Disk::open(image.path()).unwrap();
+
+    for i in 0..SECTOR_WORDS as u16 {
+        disk.write(DATA, 0xA000 | i);
+    }
+    disk.write(SECTOR, 2);
+    disk.write(CONTROL, WRITE);
+    disk.tick(1);
+    disk.dma(&mut []);
+    assert_eq!(disk.status() & ERROR, 0);
+
+    let bytes = fs::read(image.path()).unwrap();
+    assert_eq!(bytes.len(), 3 * 512);
+    assert_eq!(&bytes[2 * 512..2 * 512 + 4], &[0xA0, 0x00, 0xA0, 0x01]);
+    assert!(bytes[512..1024].iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn commands_complete_after_their_latency() {
+    let image = ImageFile::new("latency", &sectors(1));
+    let mut disk = Disk::open(image.path()).unwrap();
+    disk.set_latency(3);
+
+    let mut ram = vec![0; 0x10000];
+    disk.write(ADDRESS, 0x4000);
+    disk.write(CONTROL, READ | DMA);
+    /* the issuing instruction, then three more */
+    for _ in 0..3 {
+        disk.tick(1);
+        assert!(!disk.dma(&mut ram));
+        assert_eq!(disk.status() & READY, 0);
+    }
+    disk.tick(1);
+    assert!(disk.dma(&mut ram));
+    assert_eq!(ram[0x4000..0x4100], sectors(1)[..]);
+
+    /* a DMA write stores memory */
+    ram[0x4000] = 0xBEEF;
+    disk.write(CONTROL, WRITE | DMA);
+    for _ in 0..4 {
+        disk.tick(1);
+        disk.dma(&mut ram);
+    }
+    assert_eq!(&fs::read(image.path()).unwrap()[..2], &[0xBE, 0xEF]);
+}
+
+#[test]
+fn boots_code_loaded_by_dma() {
+    /* ADD R5, R5, #7; BRnzp #-1 */
+    let mut boot = vec![0x1B67, 0x0FFF];
+    boot.resize(SECTOR_WORDS, 0);
+    let image = ImageFile::new("boot", &boot);
+
+    /*
+    DMA sector 0 to x4000, wait for READY and jump there:
+    LD R0, addr; STI R0, DKADR; LD R0, cmd; STI R0, DKCR;
+    poll: LDI R1, DKSR; BRzp poll; LD R2, addr; JMP R2
+    */
+    let program = vec![
+        0x2007, 0xB007, 0x2007, 0xB007, 0xA207, 0x07FE, 0x2401, 0xC080, 0x4000, 0xFE16, 0x0005,
+        0xFE10, 0xFE12,
+    ];
+
+    for engine in ENGINES {
+        let mut vm = Vm::with_engine(engine);
+        vm.set_console(BufferConsole::new());
+        vm.load_image(&Image {
+            origin: 0x3000,
+            words: program.clone(),
+        });
+        disk::attach_disk(&mut vm.memory.bus, Disk::open(image.path()).unwrap()).unwrap();
+
+        vm.run(20).unwrap();
+        assert_eq!(vm.register_storage.locations[Registers::R5 as usize], 7);
+        assert_eq!(vm.pc(), 0x4001);
+    }
+}
+
+#[test]
+fn completion_raises_an_interrupt() {
+    let image = ImageFile::new("interrupt", &sectors(1));
+
+    /* start a DMA read with interrupts at priority 3, then count in R3 */
+    let program = vec![
+        0x2007, 0xB007, 0x2007, 0xB007, 0x16E1, 0x0FFE, 0x0000, 0x0000, 0x4000, 0xFE16, 0x4305,
+        0xFE10,
+    ];
+    /* LDI R1, DKSR; ADD R4, R4, #1; RTI */
+    let handler = vec![0xA202, 0x1921, 0x8000, 0xFE12];
+
+    for engine in ENGINES {
+        let mut vm = Vm::with_engine(engine);
+        vm.set_console(BufferConsole::new());
+        vm.set_exceptions(true);
+        vm.register_storage.locations[Registers::R6 as usize] = 0x5000;
+        vm.load_image(&Image {
+            origin: 0x3000,
+            words: program.clone(),
+        });
+        vm.load_image(&Image {
+            origin: 0x1000,
+            words: handler.clone(),
+        });
+        vm.write_memory(0x0182, 0x1000);
+        disk::attach_disk(&mut vm.memory.bus, Disk::open(image.path()).unwrap()).unwrap();
+
+        vm.run(4).unwrap();
+        assert_eq!(vm.pc(), 0x1000);
+        assert_eq!(vm.psr() >> 8 & 0x7, 3);
+        assert_eq!(vm.memory.locations[0x4000..0x4100], sectors(1)[..]);
+
+        vm.run(13).unwrap();
+        assert_eq!(vm.register_storage.locations[Registers::R4 as usize], 1);
+        assert_eq!(vm.register_storage.locations[Registers::R3 as usize], 5);
+    }
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::{ENGINES, machine};
+use virtual_machine::libs::{
+    bus::Keyboard,
+    console::BufferConsole,
+    constants::KBSR,
+    error::{Fault, VmError},
+    instructions::{InstructionSet, InstructionSetError, Instructions},
+    types::Opcodes,
+    vm::Engine,
+};
+
+#[test]
+fn faults_carry_the_faulting_instruction() {
+    for engine in ENGINES {
+        /* ADD R0, R0, #1; ADD R0, R0, #1; RES */
+        let (mut vm, _) = machine(engine, &[0x1021, 0x1021, 0xD123]);
+        let err = vm.run(10).unwrap_err();
+
+        assert_eq!(
+            err,
+            VmError {
+                pc: 0x3002,
+                instr: 0xD123,
+                opcode: Some(Opcodes::RES),
+                cause: Fault::IllegalOpcode,
+            }
+        );
+        assert_eq!(vm.instruction_count, 2);
+        assert_eq!(
+            err.to_string(),
+            "VM Error: illegal opcode at pc 0x3002 (instruction 0xd123, RES)"
+        );
+
+        /* TRAP x7F */
+        let (mut vm, _) = machine(engine, &[0xF07F]);
+        assert_eq!(vm.step().unwrap_err().cause, Fault::UnknownTrap(0x7F));
+    }
+}
+
+#[test]
+fn run_to_halt_stops_at_the_step_limit() {
+    /* BRnzp #-1 */
+    let (mut vm, _) = machine(Engine::Interpreter, &[0x0FFF]);
+    let err = vm.run_to_halt(100).unwrap_err();
+    assert_eq!(err.cause, Fault::StepLimit(100));
+    assert_eq!((err.pc, err.opcode), (0x3000, Some(Opcodes::BR)));
+    assert_eq!(vm.instruction_count, 100);
+
+    /* ADD R0, R0, #1; HALT */
+    let (mut vm, _) = machine(Engine::Threaded, &[0x1021, 0xF025]);
+    vm.run_to_halt(100).unwrap();
+    assert!(vm.halted());
+    assert_eq!((vm.pc(), vm.instruction_count), (0x3002, 2));
+}
+
+#[test]
+fn instruction_errors_map_to_faults() {
+    let err = Instructions::sign_extend(0x1F, 17).unwrap_err();
+    assert!(matches!(err, InstructionSetError::InvalidBitCount(17)));
+    assert_eq!(Fault::from(err), Fault::InvalidBitCount(17));
+}
+
+#[test]
+fn busy_devices_fault_the_access() {
+    for engine in ENGINES {
+        /* LDI R0, #0; .FILL KBSR */
+        let (mut vm, _) = machine(engine, &[0xA000, KBSR]);
+        let keyboard = vm
+            .memory
+            .bus
+            .attach(KBSR, KBSR + 3, Keyboard::new(BufferConsole::new()))
+            .unwrap();
+
+        let busy = keyboard.borrow_mut();
+        let err = vm.step().unwrap_err();
+        assert_eq!((err.pc, err.cause), (0x3000, Fault::Mmio(KBSR)));
+        assert_eq!(
+            err.to_string(),
+            "VM Error: memory-mapped I/O fault at 0xfe00 at pc 0x3000 (instruction 0xa000, LDI)"
+        );
+        drop(busy);
+
+        vm.set_pc(0x3000);
+        vm.step().unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+mod common;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use common::ENGINES;
+use virtual_machine::libs::{
+    asm,
+    console::BufferConsole,
+    files::{FileError, HostFiles, MAX_OPEN_FILES},
+    protection::Protection,
+    types::{ConditionalFlags, Registers},
+    vm::{Engine, Vm},
+};
+
+/* a sandbox directory, removed when dropped */
+struct Sandbox(PathBuf);
+
+impl Sandbox {
+    fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("lc3-files-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("root")).unwrap();
+        Self(dir)
+    }
+
+    fn root(&self) -> PathBuf {
+        self.0.join("root")
+    }
+
+    fn outside(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn run(engine: Engine, sandbox: &Sandbox, source: &str) -> Vm {
+    let mut vm = Vm::with_engine(engine);
+    vm.set_console(BufferConsole::new());
+    HostFiles::new(sandbox.root()).unwrap().install(&mut vm);
+
+    let program = asm::assemble(source).unwrap();
+    vm.load_image(&program.image);
+    vm.set_pc(program.image.origin);
+    vm.run(1_000).unwrap();
+    assert!(vm.halted());
+    vm
+}
+
+fn register(vm: &Vm, register: Registers) -> u16 {
+    vm.register_storage.locations[register as usize]
+}
+
+/* open PATH in mode R1, leaving the handle in R0 and R3 */
+const OPEN_PATH: &str = "
+        LEA R0, PATH
+        TRAP x30
+        ADD R3, R0, #0
+";
+
+#[test]
+fn programs_write_and_read_files() {
+    let sandbox = Sandbox::new("round-trip");
+    let source = format!(
+        r#"
+        .ORIG x3000
+        AND R1, R1, #0
+        ADD R1, R1, #1
+        {OPEN_PATH}
+        LEA R1, TEXT
+        AND R2, R2, #0
+        ADD R2, R2, #2
+        TRAP x33            ; WRITEB "hi"
+        ADD R0, R3, #0
+        LEA R1, WORDS
+        AND R2, R2, #0
+        ADD R2, R2, #1
+        TRAP x35            ; WRITEW x1234
+        ADD R0, R3, #0
+        TRAP x31
+        AND R1, R1, #0
+        {OPEN_PATH}
+        LEA R1, BUFFER
+        AND R2, R2, #0
+        ADD R2, R2, #5
+        TRAP x32            ; READB up to 5
+        ADD R4, R0, #0
+        ADD R0, R3, #0
+        AND R1, R1, #0
+        AND R2, R2, #0
+        TRAP x36            ; SEEK to the start
+        ADD R0, R3, #0
+        LEA R1, BUFFER
+        ADD R1, R1, #5
+        AND R2, R2, #0
+        ADD R2, R2, #3
+        TRAP x34            ; READW up to 3
+        ADD R5, R0, #0
+        HALT
+PATH    .STRINGZ "out/data.bin"
+TEXT    .STRINGZ "hi"
+WORDS   .FILL x1234
+BUFFER  .BLKW 8
+        .END
+    "#
+    );
+
+    for engine in ENGINES {
+        fs::create_dir_all(sandbox.root().join("out")).unwrap();
+        let vm = run(engine, &sandbox, &source);
+
+        assert_eq!(
+            fs::read(sandbox.root().join("out/data.bin")).unwrap(),
+            b"hi\x12\x34"
+        );
+        assert_eq!(
+            (register(&vm, Registers::R4), register(&vm, Registers::R5)),
+            (4, 2)
+        );
+
+        let buffer = 0x3000 + source_offset(&source, "BUFFER");
+        let words = &vm.memory.locations[buffer..buffer + 7];
+        assert_eq!(words, [0x68, 0x69, 0x12, 0x34, 0, 0x6869, 0x1234]);
+    }
+}
+
+fn source_offset(source: &str, label: &str) -> usize {
+    asm::assemble(source).unwrap().symbols[label] as usize - 0x3000
+}
+
+#[test]
+fn paths_outside_the_root_are_denied() {
+    let sandbox = Sandbox::new("escape");
+    fs::write(sandbox.outside().join("secret"), "secret").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(
+        sandbox.outside().join("secret"),
+        sandbox.root().join("link"),
+    )
+    .unwrap();
+
+    let files = HostFiles::new(sandbox.root()).unwrap();
+    for path in ["../secret", "/etc/passwd", "a/../../secret"] {
+        assert_eq!(files.resolve(path), Err(FileError::Denied), "{path}");
+    }
+    #[cfg(unix)]
+    assert_eq!(files.resolve("link"), Err(FileError::Denied));
+    assert_eq!(files.resolve("new.txt"), Ok(files.root().join("new.txt")));
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_symlinks_out_of_the_root_are_not_followed() {
+    let sandbox = Sandbox::new("dangling");
+    let escaped = sandbox.outside().join("escaped");
+    std::os::unix::fs::symlink(&escaped, sandbox.root().join("evil")).unwrap();
+    let source = format!(
+        r#"
+        .ORIG x3000
+        AND R1, R1, #0
+        ADD R1, R1, #1
+        {OPEN_PATH}
+        LEA R1, TEXT
+        AND R2, R2, #0
+        ADD R2, R2, #2
+        TRAP x33
+        HALT
+PATH    .STRINGZ "evil"
+TEXT    .STRINGZ "AB"
+        .END
+    "#
+    );
+
+    for engine in ENGINES {
+        let vm = run(engine, &sandbox, &source);
+        assert_eq!(register(&vm, Registers::R3), FileError::Denied.code());
+        assert!(!escaped.exists());
+    }
+}
+
+#[test]
+fn failures_are_negative_codes_with_the_n_flag() {
+    let sandbox = Sandbox::new("errors");
+    let cases = [
+        (
+            "LEA R0, MISSING\nAND R1, R1, #0\nTRAP x30",
+            FileError::NotFound,
+        ),
+        (
+            "LEA R0, ESCAPE\nAND R1, R1, #0\nTRAP x30",
+            FileError::Denied,
+        ),
+        (
+            "LEA R0, MISSING\nAND R1, R1, #0\nADD R1, R1, #7\nTRAP x30",
+            FileError::Invalid,
+        ),
+        (
+            "AND R0, R0, #0\nADD R0, R0, #9\nTRAP x31",
+            FileError::BadHandle,
+        ),
+        ("AND R0, R0, #0\nTRAP x32", FileError::BadHandle),
+        /* counts of x8000 and up would come back negative */
+        ("LD R2, HUGE\nTRAP x32", FileError::Invalid),
+        ("LD R2, HUGE\nTRAP x35", FileError::Invalid),
+    ];
+
+    for engine in ENGINES {
+        for (code, error) in cases {
+            let source = format!(
+                ".ORIG x3000\n{code}\nHALT\nMISSING .STRINGZ \"missing\"\nESCAPE .STRINGZ \"../x\"\nHUGE .FILL x8000\n.END"
+            );
+            let vm = run(engine, &sandbox, &source);
+
+            assert_eq!(register(&vm, Registers::R0), error.code(), "{code}");
+            assert_eq!(
+                register(&vm, Registers::COND),
+                ConditionalFlags::NEG as u16,
+                "{code}"
+            );
+        }
+    }
+}
+
+#[test]
+fn open_files_are_limited() {
+    let sandbox = Sandbox::new("limit");
+    fs::write(sandbox.root().join("file"), "").unwrap();
+    let source = format!(
+        r#"
+        .ORIG x3000
+        AND R1, R1, #0
+        LD R2, COUNT
+LOOP    {OPEN_PATH}
+        BRn DONE
+        ADD R2, R2, #-1
+        BRp LOOP
+DONE    HALT
+COUNT   .FILL {}
+PATH    .STRINGZ "file"
+        .END
+    "#,
+        MAX_OPEN_FILES + 1
+    );
+
+    for engine in ENGINES {
+        let vm = run(engine, &sandbox, &source);
+        assert_eq!(register(&vm, Registers::R0), FileError::TooManyFiles.code());
+        assert_eq!(register(&vm, Registers::R2), 1);
+    }
+}
+
+#[test]
+fn buffers_are_checked_against_memory_protection() {
+    let sandbox = Sandbox::new("protection");
+    fs::write(sandbox.root().join("file"), [0xAA, 0xBB]).unwrap();
+    let source = format!(
+        r#"
+        .ORIG x3000
+        AND R1, R1, #0
+        {OPEN_PATH}
+        LD R1, VECTOR
+        AND R2, R2, #0
+        ADD R2, R2, #1
+        TRAP x34            ; READW into the HALT vector
+        ADD R4, R0, #0
+        ADD R0, R3, #0
+        LD R1, VECTOR
+        TRAP x33            ; WRITEB from system space
+        ADD R5, R0, #0
+        HALT
+VECTOR  .FILL x0025
+PATH    .STRINGZ "file"
+        .END
+    "#
+    );
+
+    for engine in ENGINES {
+        let mut vm = Vm::with_engine(engine);
+        vm.set_console(BufferConsole::new());
+        vm.set_protection(Some(Protection::lc3()));
+        HostFiles::new(sandbox.root()).unwrap().install(&mut vm);
+        let program = asm::assemble(&source).unwrap();
+        vm.load_image(&program.image);
+        vm.set_pc(program.image.origin);
+        vm.run(1_000).unwrap();
+
+        assert_eq!(register(&vm, Registers::R3), 0);
+        assert_eq!(register(&vm, Registers::R4), FileError::Denied.code());
+        assert_eq!(register(&vm, Registers::R5), FileError::Denied.code());
+        assert_eq!(vm.memory.locations[0x25], 0);
+    }
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use std::{env, fs, process};
+
+use common::ENGINES;
+use virtual_machine::libs::{
+    console::BufferConsole,
+    framebuffer::{self, Framebuffer, FramebufferError},
+    loader::Image,
+    vm::{Engine, Vm},
+};
+
+const RED: u16 = 0x1F << 10;
+const TEAL: u16 = 0x10 << 5 | 0x1F;
+
+/* plot red at (0, 0) and (1, 1) */
+fn plot(engine: Engine) -> (Vm, Framebuffer) {
+    let mut vm = Vm::with_engine(engine);
+    vm.set_console(BufferConsole::new());
+    let framebuffer = framebuffer::attach_framebuffer(&mut vm.memory.bus).unwrap();
+
+    /* LD R1, color; LD R2, x(0,0); STR R1, R2, #0; LD R3, x(1,1); STR R1, R3, #0 */
+    vm.load_image(&Image {
+        origin: 0x3000,
+        words: vec![0x2204, 0x2404, 0x7280, 0x2603, 0x72C0, RED, 0xC000, 0xC081],
+    });
+    vm.run(5).unwrap();
+
+    let pixels = framebuffer.borrow().clone();
+    (vm, pixels)
+}
+
+#[test]
+fn programs_draw_into_video_memory() {
+    for engine in ENGINES {
+        let (vm, framebuffer) = plot(engine);
+
+        assert_eq!(framebuffer.pixel(0, 0), RED);
+        assert_eq!(framebuffer.pixel(1, 1), RED);
+        assert_eq!(framebuffer.pixel(1, 0), 0);
+        assert_eq!(vm.memory.locations[0xC000], 0);
+    }
+}
+
+#[test]
+fn exports_ppm() {
+    let mut framebuffer = Framebuffer::new(2, 1);
+    framebuffer.set_pixel(0, 0, RED);
+    framebuffer.set_pixel(1, 0, TEAL);
+
+    assert_eq!(
+        framebuffer.to_ppm(),
+        b"P6\n2 1\n255\n\xff\x00\x00\x00\x84\xff".to_vec()
+    );
+}
+
+#[test]
+fn exports_png() {
+    let (_, framebuffer) = plot(Engine::Interpreter);
+    let png = framebuffer.to_png();
+
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    /* IHDR: 128x124, 8-bit RGB */
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(&png[16..29], &[0, 0, 0, 128, 0, 0, 0, 124, 8, 2, 0, 0, 0]);
+    /* IEND with its well-known CRC */
+    assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+
+    /* IDAT holds a zlib header and a single stored block of filtered rows */
+    let idat = 8 + 25;
+    assert_eq!(&png[idat + 4..idat + 8], b"IDAT");
+    let zlib = &png[idat + 8..];
+    let row = 1 + 128 * 3;
+    assert_eq!(zlib[2], 1);
+    assert_eq!(u16::from_le_bytes([zlib[3], zlib[4]]) as usize, row * 124);
+
+    let raw = &zlib[7..];
+    assert_eq!(&raw[..4], &[0, 255, 0, 0]);
+    assert_eq!(&raw[row + 1 + 3..row + 1 + 6], &[255, 0, 0]);
+}
+
+#[test]
+fn renders_to_the_terminal() {
+    let (_, framebuffer) = plot(Engine::Interpreter);
+    let text = framebuffer.to_terminal();
+
+    assert_eq!(text.lines().count(), 62);
+    assert!(text.starts_with("\x1b[38;2;255;0;0m\x1b[48;2;0;0;0m▀"));
+    assert_eq!(text.lines().next().unwrap().matches('▀').count(), 128);
+}
+
+#[test]
+fn saves_by_extension() {
+    let (_, framebuffer) = plot(Engine::Interpreter);
+    let dir = env::temp_dir().join(format!("lc3-framebuffer-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    framebuffer.save(dir.join("frame.ppm")).unwrap();
+    framebuffer.save(dir.join("frame.PNG")).unwrap();
+    assert_eq!(
+        fs::read(dir.join("frame.ppm")).unwrap(),
+        framebuffer.to_ppm()
+    );
+    assert_eq!(
+        fs::read(dir.join("frame.PNG")).unwrap(),
+        framebuffer.to_png()
+    );
+    assert!(matches!(
+        framebuffer.save(dir.join("frame.bmp")),
+        Err(FramebufferError::UnknownFormat(_))
+    ));
+
+    fs::remove_dir_all(dir).unwrap();
+}
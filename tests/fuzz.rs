@@ -0,0 +1,51 @@
+mod common;
+
+use std::env;
+
+use common::Rng;
+use virtual_machine::libs::fuzz;
+
+/*
+built-in random driver for the fuzz targets, for machines without cargo-fuzz.
+`LC3_FUZZ_ITERATIONS` raises the number of inputs per target
+*/
+fn iterations() -> u64 {
+    env::var("LC3_FUZZ_ITERATIONS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(300)
+}
+
+fn drive(seed: u64, max_len: usize, target: fn(&[u8])) {
+    let mut rng = Rng(seed);
+    for _ in 0..iterations() {
+        target(&rng.bytes(max_len));
+    }
+}
+
+#[test]
+fn run_image_survives_random_images() {
+    drive(0x1111_2222_3333_4445, 512, fuzz::run_image);
+}
+
+#[test]
+fn load_object_survives_random_bytes() {
+    drive(0x5555_6666_7777_8889, 64, fuzz::load_object);
+}
+
+#[test]
+fn execute_words_survives_random_words() {
+    drive(0x9999_AAAA_BBBB_CCCD, 256, fuzz::execute_words);
+}
+
+/* inputs that used to panic or hang */
+#[test]
+fn regressions() {
+    /* TRAP x26 hit `todo!()` */
+    fuzz::run_image(&[0x00, 0x30, 0x00, 0xF0, 0x26]);
+
+    /* PUTS with no terminator anywhere in memory never returned */
+    let mut image = vec![0x00, 0x00, 0x00, 0xF0, 0x22];
+    image.extend([0x01; 2 * 0xFFFF]);
+    fuzz::run_image(&image);
+}
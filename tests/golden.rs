@@ -0,0 +1,185 @@
+mod common;
+
+use std::{env, fs, path::PathBuf, process, time::Duration};
+
+use common::ENGINES;
+use virtual_machine::libs::{
+    error::{Fault, VmError},
+    golden::{self, Failure, GoldenError, Spec},
+    limits::StopReason,
+    loader::Image,
+    types::{Opcodes, Registers},
+    vm::Engine,
+};
+
+/*
+     LEA R0, MSG
+     PUTS
+     GETC
+     ADD R1, R0, #0
+     HALT
+MSG  .STRINGZ "Hi\n"
+*/
+fn greeter() -> Image {
+    Image {
+        origin: 0x3000,
+        words: vec![
+            0xE004, 0xF022, 0xF020, 0x1220, 0xF025, 0x48, 0x69, 0x0A, 0x00,
+        ],
+    }
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("lc3-golden-{name}-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn parses_every_directive() {
+    let spec = Spec::parse(
+        "# greeter\n\
+         input \"y\"\n\
+         output \"Hi\\n\"\n\
+         output \"\\x41\\\"\"\n\
+         max-instructions 50\n\
+         register r1 x79\n\
+         register COND 0x0001\n\
+         memory 0x3005 #72\n\
+         memory x4000 #-1\n\
+         set-register R6 xFE00\n\
+         set-memory x4000 12\n\
+         timeout-ms 250\n\
+         max-output 64\n\
+         detect-loops\n",
+    )
+    .unwrap();
+
+    assert_eq!(spec.input, b"y");
+    assert_eq!(spec.output.as_deref(), Some(&b"Hi\nA\""[..]));
+    assert_eq!(spec.max_instructions, 50);
+    assert_eq!(
+        spec.registers,
+        [(Registers::R1, 0x79), (Registers::COND, 0x0001)]
+    );
+    assert_eq!(spec.memory, [(0x3005, 72), (0x4000, 0xFFFF)]);
+    assert_eq!(spec.setup_registers, [(Registers::R6, 0xFE00)]);
+    assert_eq!(spec.setup_memory, [(0x4000, 12)]);
+    assert_eq!(spec.timeout, Some(Duration::from_millis(250)));
+    assert_eq!(spec.max_output_bytes, Some(64));
+    assert!(spec.detect_loops);
+
+    assert!(matches!(
+        Spec::parse("output \"ok\"\nregister R8 1"),
+        Err(GoldenError::Parse { line: 2, .. })
+    ));
+    assert!(matches!(
+        Spec::parse("expect 1"),
+        Err(GoldenError::Parse { line: 1, .. })
+    ));
+}
+
+#[test]
+fn checks_output_registers_and_memory() {
+    let spec = Spec::parse(
+        "input \"y\"\noutput \"Hi\\nProgram halted\\n\"\nregister R1 0x79\nmemory 0x3005 x48",
+    )
+    .unwrap();
+    for engine in ENGINES {
+        let result = golden::run_case("greeter", &greeter(), &spec, engine);
+        assert!(result.passed(), "{:?}", result.failures);
+        assert_eq!(result.instructions, 5);
+    }
+
+    let spec = Spec::parse("input \"n\"\noutput \"Hello\\n\"\nregister R1 0x79").unwrap();
+    let result = golden::run_case("greeter", &greeter(), &spec, Engine::Interpreter);
+    assert_eq!(
+        result.failures,
+        [
+            Failure::Output {
+                expected: b"Hello\n".to_vec(),
+                actual: b"Hi\nProgram halted\n".to_vec(),
+            },
+            Failure::Register {
+                register: Registers::R1,
+                expected: 0x79,
+                actual: 0x6E,
+            },
+        ]
+    );
+}
+
+#[test]
+fn stops_runaway_and_faulting_programs() {
+    let spec = Spec::parse("max-instructions 100").unwrap();
+
+    /* BRnzp #-1 */
+    let spin = Image {
+        origin: 0x3000,
+        words: vec![0x0FFF],
+    };
+    let result = golden::run_case("spin", &spin, &spec, Engine::Interpreter);
+    assert_eq!(
+        result.failures,
+        [Failure::Stopped(StopReason::InstructionLimit(100))]
+    );
+
+    let spec = Spec::parse("max-instructions 100\ndetect-loops").unwrap();
+    let result = golden::run_case("spin", &spin, &spec, Engine::Interpreter);
+    assert_eq!(
+        result.failures,
+        [Failure::Stopped(StopReason::InfiniteLoop { pc: 0x3000 })]
+    );
+    assert_eq!(result.instructions, 0);
+
+    /* RTI is not supported */
+    let rti = Image {
+        origin: 0x3000,
+        words: vec![0x8000],
+    };
+    let result = golden::run_case("rti", &rti, &spec, Engine::Interpreter);
+    assert!(matches!(
+        result.failures[..],
+        [Failure::Fault(VmError {
+            pc: 0x3000,
+            instr: 0x8000,
+            opcode: Some(Opcodes::RTI),
+            cause: Fault::IllegalOpcode,
+        })]
+    ));
+}
+
+#[test]
+fn runs_a_directory_and_reports_diffs() {
+    let dir = scratch_dir("directory");
+    fs::write(dir.join("a_pass.obj"), greeter().to_bytes()).unwrap();
+    fs::write(
+        dir.join("a_pass.spec"),
+        "input \"y\"\noutput \"Hi\\nProgram halted\\n\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("b_fail.obj"), greeter().to_bytes()).unwrap();
+    fs::write(dir.join("b_fail.spec"), "output \"Hi\\nthere\\n\"\n").unwrap();
+    fs::write(dir.join("c_missing.spec"), "").unwrap();
+    fs::write(dir.join("notes.txt"), "not a spec").unwrap();
+
+    let results = golden::run_directory(&dir, Engine::Interpreter).unwrap();
+    let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["a_pass", "b_fail", "c_missing"]);
+
+    let report = golden::report(&results);
+    assert!(report.contains("PASS a_pass (5 instructions)"), "{report}");
+    assert!(report.contains("FAIL b_fail"), "{report}");
+    assert!(
+        report.contains("    line 2:\n      - \"there\\n\"\n      + \"Program halted\\n\""),
+        "{report}"
+    );
+    assert!(
+        report.contains("FAIL c_missing (0 instructions)\n  could not load image"),
+        "{report}"
+    );
+    assert!(report.ends_with("1/3 passed\n"), "{report}");
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use std::time::Duration;
+
+use common::ENGINES;
+use virtual_machine::libs::{
+    grade::{self, GradeError, GradingScript},
+    loader::Image,
+    vm::Engine,
+};
+
+/*
+     ADD R2, R0, R1
+     BRn LOOP        ; a "bug": negative sums spin forever
+     LD  R3, RESULT
+     ADD R3, R3, R2
+     ST  R3, RESULT
+     HALT
+LOOP BRnzp LOOP
+RESULT .FILL #0
+*/
+fn submission() -> Image {
+    Image {
+        origin: 0x3000,
+        words: vec![
+            0x1401, 0x0804, 0x2604, 0x16C2, 0x3602, 0xF025, 0x0FFF, 0x0000,
+        ],
+    }
+}
+
+const SCRIPT: &str = "\
+# defaults for every case
+max-instructions 1000
+
+case small 5
+  set-register R0 #3
+  set-register R1 #4
+  register R2 #7
+  memory x3007 #7
+
+case preloaded 3
+  set-register R0 #1
+  set-register R1 #1
+  set-memory x3007 #40
+  memory x3007 #42
+
+case negative 2
+  set-register R0 #-5
+  set-register R1 #1
+  timeout-ms 10000
+  register R2 #-4
+";
+
+#[test]
+fn parses_cases_with_defaults() {
+    let script = GradingScript::parse(SCRIPT).unwrap();
+
+    let names: Vec<(&str, u32)> = script
+        .cases
+        .iter()
+        .map(|case| (case.name.as_str(), case.points))
+        .collect();
+    assert_eq!(names, [("small", 5), ("preloaded", 3), ("negative", 2)]);
+    assert!(
+        script
+            .cases
+            .iter()
+            .all(|case| case.spec.max_instructions == 1000)
+    );
+    assert_eq!(script.cases[2].spec.timeout, Some(Duration::from_secs(10)));
+    assert_eq!(script.cases[1].spec.setup_memory, [(0x3007, 40)]);
+
+    assert!(matches!(
+        GradingScript::parse("timeout-ms 5"),
+        Err(GradeError::NoCases)
+    ));
+    assert!(matches!(
+        GradingScript::parse("case broken\n"),
+        Err(GradeError::Parse { line: 1, .. })
+    ));
+    assert!(matches!(
+        GradingScript::parse("case ok 1\n  register R9 1\n"),
+        Err(GradeError::Parse { line: 2, .. })
+    ));
+}
+
+#[test]
+fn scores_each_case_in_a_fresh_machine() {
+    let script = GradingScript::parse(SCRIPT).unwrap();
+
+    for engine in ENGINES {
+        let report = grade::grade(&submission(), &script, engine);
+
+        /* the memory written by "small" must not leak into "preloaded" */
+        let earned: Vec<u32> = report.cases.iter().map(|case| case.earned).collect();
+        assert_eq!(earned, [5, 3, 0]);
+        assert_eq!((report.score(), report.total()), (8, 10));
+    }
+}
+
+#[test]
+fn reports_the_failed_check_and_last_instructions() {
+    let script = GradingScript::parse(SCRIPT).unwrap();
+    let report = grade::grade(&submission(), &script, Engine::Interpreter);
+
+    let text = report.to_text();
+    assert!(text.contains("PASS small 5/5 (6 instructions)"), "{text}");
+    assert!(
+        text.contains(
+            "FAIL negative 0/2 (1000 instructions)\n  \
+             did not halt within 1000 instructions\n  \
+             last instructions:\n    0x3006: 0x0fff BR\n"
+        ),
+        "{text}"
+    );
+    assert!(text.ends_with("score: 8/10\n"), "{text}");
+
+    let json = report.to_json();
+    assert!(
+        json.starts_with("{\"score\":8,\"total\":10,\"cases\":["),
+        "{json}"
+    );
+    assert!(
+        json.contains(
+            "{\"name\":\"negative\",\"points\":2,\"earned\":0,\"passed\":false,\
+             \"instructions\":1000,\"failures\":[\"did not halt within 1000 instructions\"],\
+             \"trace\":[\"0x3006: 0x0fff BR\""
+        ),
+        "{json}"
+    );
+    assert!(json.ends_with("]}]}"), "{json}");
+}
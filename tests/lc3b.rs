@@ -0,0 +1,181 @@
+mod common;
+
+use common::machine_with;
+use virtual_machine::libs::{
+    constants::{LC3B_INTERRUPT_VECTOR_TABLE, TRAP_VECTOR_TABLE},
+    os::{Os, OsError},
+    protection::Privilege,
+    types::{ConditionalFlags, Registers},
+    vm::{Engine, Exception, Isa, Vm},
+};
+
+/* machine setup selecting the LC-3b */
+fn lc3b(vm: &mut Vm) {
+    vm.set_isa(Isa::Lc3b);
+}
+
+#[test]
+fn bytes_are_loaded_sign_extended_and_stored_in_place() {
+    let (mut vm, _) = machine_with(
+        Engine::default(),
+        &[
+            0x2041, // LDB R0, R1, #1
+            0x3042, // STB R0, R1, #2
+            0x3043, // STB R0, R1, #3
+            0x6440, // LDW R2, R1, #0
+            0x7442, // STW R2, R1, #2
+        ],
+        lc3b,
+    );
+    vm.register_storage.locations[Registers::R1 as usize] = 0x3100;
+    vm.write_memory(0x3100, 0x80FF);
+
+    vm.run(3).unwrap();
+    let regs = &vm.register_storage.locations;
+    assert_eq!(regs[Registers::R0 as usize], 0xFF80);
+    assert_eq!(regs[Registers::COND as usize], ConditionalFlags::NEG as u16);
+    assert_eq!(vm.memory.locations[0x3102], 0x8080);
+
+    vm.run(2).unwrap();
+    assert_eq!(
+        vm.register_storage.locations[Registers::R2 as usize],
+        0x80FF
+    );
+    assert_eq!(vm.memory.locations[0x3104], 0x80FF);
+    assert_eq!(vm.pc(), 0x300A);
+}
+
+#[test]
+fn xor_and_shifts_set_the_condition_codes_but_lea_does_not() {
+    let (mut vm, _) = machine_with(
+        Engine::default(),
+        &[
+            0x923F, // XOR R1, R0, #-1
+            0xD474, // RSHFA R2, R1, #4
+            0xD604, // LSHF R3, R0, #4
+            0xD858, // RSHFL R4, R1, #8
+            0xEA03, // LEA R5, #3
+        ],
+        lc3b,
+    );
+    vm.register_storage.locations[Registers::R0 as usize] = 0x00F0;
+
+    vm.run(2).unwrap();
+    assert_eq!(
+        vm.register_storage.locations[Registers::R1 as usize],
+        0xFF0F
+    );
+    assert_eq!(
+        vm.register_storage.locations[Registers::R2 as usize],
+        0xFFF0
+    );
+    assert_eq!(
+        vm.register_storage.locations[Registers::COND as usize],
+        ConditionalFlags::NEG as u16
+    );
+
+    vm.run(3).unwrap();
+    let regs = &vm.register_storage.locations;
+    assert_eq!(regs[Registers::R3 as usize], 0x0F00);
+    assert_eq!(regs[Registers::R4 as usize], 0x00FF);
+    assert_eq!(regs[Registers::R5 as usize], 0x3010);
+    assert_eq!(regs[Registers::COND as usize], ConditionalFlags::POS as u16);
+}
+
+#[test]
+fn branch_and_subroutine_offsets_count_words() {
+    let (mut vm, _) = machine_with(
+        Engine::default(),
+        &[
+            0x4802, // JSR #2
+            0x0000, // skipped
+            0x0000, // skipped
+            0x1260, // ADD R1, R1, #0
+            0x05FB, // BRz #-5
+        ],
+        lc3b,
+    );
+
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 0x3006);
+    assert_eq!(
+        vm.register_storage.locations[Registers::R7 as usize],
+        0x3002
+    );
+
+    vm.run(2).unwrap();
+    assert_eq!(vm.pc(), 0x3000);
+}
+
+#[test]
+fn native_traps_print_byte_strings() {
+    let (mut vm, console) = machine_with(
+        Engine::default(),
+        &[
+            0xE002, // LEA R0, #2
+            0xF022, // PUTS
+            0xF025, // HALT
+            0x6948, // "Hi"
+            0x0021, // "!"
+        ],
+        lc3b,
+    );
+
+    vm.run_to_halt(10).unwrap();
+    assert_eq!(console.output(), b"Hi!Program halted\n");
+    assert_eq!(
+        vm.register_storage.locations[Registers::R7 as usize],
+        0x3006
+    );
+}
+
+#[test]
+fn traps_and_exceptions_vector_through_word_tables() {
+    let (mut vm, _) = machine_with(
+        Engine::default(),
+        &[
+            0xF040, // TRAP x40
+            0xA000, // illegal
+        ],
+        lc3b,
+    );
+    vm.set_trap_table(true);
+    vm.set_exceptions(true);
+    vm.register_storage.locations[Registers::R6 as usize] = 0xF000;
+    vm.write_memory(TRAP_VECTOR_TABLE + 2 * 0x40, 0x1000);
+    vm.write_memory(0x1000, 0x8000); // RTI
+    let illegal = LC3B_INTERRUPT_VECTOR_TABLE + 2 * Exception::IllegalOpcode as u16;
+    vm.write_memory(illegal, 0x1100);
+
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 0x1000);
+    assert_eq!(vm.privilege(), Privilege::Supervisor);
+    assert_eq!(
+        vm.register_storage.locations[Registers::R6 as usize],
+        0x2FFC
+    );
+    assert_eq!(vm.memory.locations[0x2FFC], 0x3002);
+    assert_eq!(
+        vm.register_storage.locations[Registers::R7 as usize],
+        0x3002
+    );
+
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 0x3002);
+    assert_eq!(vm.privilege(), Privilege::User);
+    assert_eq!(
+        vm.register_storage.locations[Registers::R6 as usize],
+        0xF000
+    );
+
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 0x1100);
+    assert_eq!(vm.memory.locations[0x2FFC], 0x3004);
+
+    let mut vm = Vm::new();
+    vm.set_isa(Isa::Lc3b);
+    assert!(matches!(
+        Os::bundled().boot(&mut vm),
+        Err(OsError::UnsupportedIsa(Isa::Lc3b))
+    ));
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{ENGINES, machine_with};
+use virtual_machine::libs::{
+    limits::{Limits, StopReason},
+    loader::Image,
+    timer,
+    types::Registers,
+    vm::Engine,
+};
+
+fn stop(words: &[u16], limits: Limits) -> (StopReason, u64) {
+    let [interpreter, threaded] = ENGINES.map(|engine| {
+        let (mut vm, _) = machine_with(engine, words, |vm| vm.set_limits(limits));
+        (vm.run_with_limits().unwrap(), vm.instruction_count)
+    });
+    assert_eq!(interpreter, threaded);
+    interpreter
+}
+
+/*
+     AND R0, R0, #0
+     ADD R0, R0, #5
+LOOP ADD R0, R0, #-1
+     BRp LOOP
+     HALT
+*/
+const COUNTDOWN: [u16; 5] = [0x5020, 0x1025, 0x103F, 0x03FE, 0xF025];
+
+/* LOOP ADD R1, R1, #1; ST R1, #1; BRnzp LOOP */
+const COUNTER: [u16; 3] = [0x1261, 0x3201, 0x0FFD];
+
+/* LOOP NOT R0, R0; BRnzp LOOP */
+const FLIP: [u16; 2] = [0x903F, 0x0FFE];
+
+#[test]
+fn halting_programs_are_not_flagged() {
+    let limits = Limits {
+        detect_loops: true,
+        ..Limits::default()
+    };
+    assert_eq!(stop(&COUNTDOWN, limits), (StopReason::Halted, 13));
+}
+
+#[test]
+fn stops_at_the_instruction_limit() {
+    let limits = Limits {
+        max_instructions: Some(500),
+        detect_loops: true,
+        ..Limits::default()
+    };
+    /* the counter is stored every iteration, so its states never repeat */
+    assert_eq!(
+        stop(&COUNTER, limits),
+        (StopReason::InstructionLimit(500), 500)
+    );
+}
+
+#[test]
+fn detects_self_branches_and_repeated_states() {
+    let limits = Limits {
+        max_instructions: Some(10_000),
+        detect_loops: true,
+        ..Limits::default()
+    };
+
+    /* BRnzp #-1 */
+    assert_eq!(
+        stop(&[0x0FFF], limits),
+        (StopReason::InfiniteLoop { pc: 0x3000 }, 0)
+    );
+    /* LEA R2, #0; JMP R2 */
+    assert_eq!(
+        stop(&[0xE400, 0xC080], limits),
+        (StopReason::InfiniteLoop { pc: 0x3001 }, 1)
+    );
+    /* R0 alternates between two values */
+    assert_eq!(
+        stop(&FLIP, limits),
+        (StopReason::InfiniteLoop { pc: 0x3000 }, 4)
+    );
+
+    /* without detection only the instruction limit stops it */
+    let limits = Limits {
+        detect_loops: false,
+        ..limits
+    };
+    assert_eq!(
+        stop(&FLIP, limits),
+        (StopReason::InstructionLimit(10_000), 10_000)
+    );
+}
+
+#[test]
+fn stops_on_output_and_time() {
+    /* LOOP LEA R0, MSG; PUTS; BRnzp LOOP; MSG .STRINGZ "ab" */
+    let program = [0xE002, 0xF022, 0x0FFD, 0x61, 0x62, 0x00];
+    let limits = Limits {
+        max_output_bytes: Some(7),
+        ..Limits::default()
+    };
+    let (mut vm, console) = machine_with(Engine::Interpreter, &program, |vm| vm.set_limits(limits));
+    assert_eq!(vm.run_with_limits().unwrap(), StopReason::OutputLimit(7));
+    assert_eq!(console.output(), b"abababab");
+    assert_eq!(vm.output_bytes(), 8);
+
+    let limits = Limits {
+        timeout: Some(Duration::from_millis(20)),
+        ..Limits::default()
+    };
+    let (mut vm, _) = machine_with(Engine::Threaded, &FLIP, |vm| vm.set_limits(limits));
+    assert_eq!(
+        vm.run_with_limits().unwrap(),
+        StopReason::Timeout(Duration::from_millis(20))
+    );
+}
+
+/*
+     LD R0, PERIOD
+     STI R0, TMRR
+     LD R0, CONTROL
+     STI R0, TMCR
+IDLE BRnzp IDLE
+     ...
+with the service routine at x1000 halting: the idle loop only ends through
+the timer interrupt
+*/
+#[test]
+fn idle_loops_waiting_for_an_interrupt_are_not_flagged() {
+    let limits = Limits {
+        max_instructions: Some(1000),
+        detect_loops: true,
+        ..Limits::default()
+    };
+    let words = [
+        0x2006, 0xB006, 0x2006, 0xB006, 0x0FFF, 0x0000, 0x0000, 10, 0xFE0A, 0x4203, 0xFE08,
+    ];
+
+    for engine in ENGINES {
+        let (mut vm, _) = machine_with(engine, &words, |vm| {
+            vm.set_limits(limits);
+            vm.set_exceptions(true);
+            vm.register_storage.locations[Registers::R6 as usize] = 0x5000;
+            timer::attach_timer(&mut vm.memory.bus).unwrap();
+        });
+        vm.write_memory(0x0181, 0x1000);
+        vm.load_image(&Image {
+            origin: 0x1000,
+            words: vec![0xF025],
+        });
+
+        assert_eq!(vm.run_with_limits().unwrap(), StopReason::Halted);
+        assert!(vm.instruction_count > 10);
+    }
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{ENGINES, machine};
+use virtual_machine::libs::{
+    constants::{CLOCK_ENABLE, MCR},
+    instructions::Revision,
+    limits::StopReason,
+    loader::Image,
+    protection::Privilege,
+    translate::Translator,
+    types::Registers,
+    vm::Vm,
+};
+
+#[test]
+fn machines_start_with_the_clock_running() {
+    let vm = Vm::new();
+    assert_eq!(vm.memory.locations[MCR as usize], CLOCK_ENABLE);
+    assert!(!vm.halted());
+}
+
+#[test]
+fn halt_stops_the_run_loop() {
+    /* ADD R0, R0, #1; HALT; ADD R0, R0, #1 */
+    let program = [0x1021, 0xF025, 0x1021];
+
+    for engine in ENGINES {
+        let (mut vm, console) = machine(engine, &program);
+
+        vm.run(100).unwrap();
+        assert!(vm.halted());
+        assert_eq!(console.output(), b"Program halted\n");
+        assert_eq!((vm.pc(), vm.instruction_count), (0x3002, 2));
+
+        /* a stopped machine does nothing */
+        vm.step().unwrap();
+        assert_eq!(vm.instruction_count, 2);
+
+        /* until the clock is started again */
+        vm.write_memory(MCR, CLOCK_ENABLE);
+        vm.step().unwrap();
+        assert_eq!(vm.register_storage.locations[Registers::R0 as usize], 2);
+    }
+}
+
+#[test]
+fn clearing_the_clock_bit_halts() {
+    /* AND R0, R0, #0; STI R0, MCR; ADD R1, R1, #1; .FILL xFFFE */
+    let program = [0x5020, 0xB201, 0x1261, 0xFFFE];
+
+    for engine in ENGINES {
+        let (mut vm, console) = machine(engine, &program);
+        vm.set_privilege(Privilege::Supervisor);
+
+        assert_eq!(vm.run_with_limits().unwrap(), StopReason::Halted);
+        assert_eq!(vm.instruction_count, 2);
+        assert_eq!(vm.register_storage.locations[Registers::R1 as usize], 0);
+        assert!(console.output().is_empty());
+    }
+}
+
+#[test]
+fn translated_programs_stop_with_the_clock() {
+    let image = Image {
+        origin: 0x3000,
+        words: vec![0x1021, 0xF025],
+    };
+    let source = Translator::new(&image, Revision::Legacy, false).emit(false);
+
+    assert!(source.contains("memory.locations[0xfffe] |= 0x8000;"));
+    assert!(source.contains("if mem.locations[0xfffe] & 0x8000 == 0 {"));
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::{ENGINES, machine_with};
+use virtual_machine::libs::{
+    error::Fault,
+    numeric,
+    types::{ConditionalFlags, Registers},
+    vm::Engine,
+};
+
+#[test]
+fn prints_r0_in_each_format() {
+    /* TRAP x26; TRAP x27; TRAP x28; HALT */
+    let program = [0xF026, 0xF027, 0xF028, 0xF025];
+
+    for engine in ENGINES {
+        for (value, expected) in [
+            (0xFFFF, "-165535xFFFF"),
+            (0x0000, "00x0000"),
+            (0x8000, "-3276832768x8000"),
+            (0x04D2, "12341234x04D2"),
+        ] {
+            let (mut vm, console) = machine_with(engine, &program, numeric::install);
+            vm.register_storage.locations[Registers::R0 as usize] = value;
+            vm.run(10).unwrap();
+
+            assert_eq!(
+                String::from_utf8(console.output()).unwrap(),
+                format!("{expected}Program halted\n")
+            );
+        }
+    }
+}
+
+#[test]
+fn reads_numbers_and_sets_the_condition_codes() {
+    /* TRAP x29; ADD R1, R0, #0; TRAP x29; ADD R2, R0, #0; TRAP x2A; HALT */
+    let program = [0xF029, 0x1220, 0xF029, 0x1420, 0xF02A, 0xF025];
+
+    for engine in ENGINES {
+        let (mut vm, console) = machine_with(engine, &program, numeric::install);
+        console.push_input(b"  -42\n65535 xBEEF\n");
+        vm.run(5).unwrap();
+
+        let regs = &vm.register_storage.locations;
+        assert_eq!(regs[Registers::R1 as usize], -42i16 as u16);
+        assert_eq!(regs[Registers::R2 as usize], 65535);
+        assert_eq!(regs[Registers::R0 as usize], 0xBEEF);
+        assert_eq!(regs[Registers::COND as usize], ConditionalFlags::NEG as u16);
+    }
+}
+
+#[test]
+fn malformed_input_reads_as_zero() {
+    for (input, base) in [
+        ("12a", "decimal"),
+        ("70000", "decimal"),
+        ("", "decimal"),
+        ("x12345", "hex"),
+        ("xG", "hex"),
+    ] {
+        let vector = if base == "decimal" { 0xF029 } else { 0xF02A };
+        let (mut vm, console) = machine_with(Engine::Interpreter, &[vector], numeric::install);
+        console.push_input(input.as_bytes());
+        vm.register_storage.locations[Registers::R0 as usize] = 7;
+        vm.step().unwrap();
+
+        assert_eq!(
+            vm.register_storage.locations[Registers::R0 as usize],
+            0,
+            "{input}"
+        );
+        assert_eq!(
+            vm.register_storage.locations[Registers::COND as usize],
+            ConditionalFlags::ZRO as u16
+        );
+    }
+}
+
+#[test]
+fn strict_machines_do_not_know_the_vectors() {
+    let (mut vm, _) = machine_with(Engine::Interpreter, &[0xF026], numeric::install);
+    numeric::uninstall(&mut vm);
+
+    let err = vm.step().unwrap_err();
+    assert_eq!(err.cause, Fault::UnknownTrap(0x26));
+}
+
+#[test]
+fn parsers_accept_their_documented_ranges() {
+    assert_eq!(numeric::parse_decimal("-32768"), Some(0x8000));
+    assert_eq!(numeric::parse_decimal("-32769"), None);
+    assert_eq!(numeric::parse_hex("X7fff"), Some(0x7FFF));
+    assert_eq!(numeric::parse_hex("12"), Some(0x12));
+    assert_eq!(numeric::parse_hex("x+12"), None);
+}
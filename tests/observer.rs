@@ -0,0 +1,41 @@
+#![cfg(feature = "instrument")]
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use common::ENGINES;
+use virtual_machine::libs::{loader::Image, observer::Observer, vm::Vm};
+
+#[derive(Default)]
+struct WriteLog(RefCell<Vec<(u16, u16)>>);
+
+impl Observer for WriteLog {
+    fn memory_write(&self, address: u16, value: u16) {
+        self.0.borrow_mut().push((address, value));
+    }
+}
+
+#[test]
+fn observer_sees_memory_writes_on_both_engines() {
+    /*
+        ADD R0, R0, #7
+        ST  R0, #1
+        BRnzp #-1
+    */
+    let image = Image {
+        origin: 0x3000,
+        words: vec![0x1027, 0x3001, 0x0FFF],
+    };
+
+    for engine in ENGINES {
+        let mut vm = Vm::with_engine(engine);
+        vm.load_image(&image);
+
+        let log = Rc::new(WriteLog::default());
+        vm.set_observer(log.clone());
+        vm.run(3).unwrap();
+
+        assert_eq!(*log.0.borrow(), vec![(0x3003, 7)], "{engine:?}");
+    }
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use common::ENGINES;
+use virtual_machine::libs::{
+    asm,
+    console::BufferConsole,
+    constants::{CLOCK_ENABLE, MCR, TRAP_VECTOR_TABLE},
+    os::Os,
+    protection::{Privilege, Protection},
+    types::Registers,
+    vm::{Engine, Vm},
+};
+
+/* boot the bundled OS and load `source` as the user program */
+fn boot(engine: Engine, source: &str, input: &[u8]) -> (Vm, BufferConsole) {
+    let console = BufferConsole::with_input(input);
+    let mut vm = Vm::with_engine(engine);
+    vm.set_console(console.clone());
+    Os::bundled().boot(&mut vm).unwrap();
+
+    let program = asm::assemble(source).unwrap();
+    vm.load_image(&program.image);
+    vm.set_pc(program.image.origin);
+    (vm, console)
+}
+
+#[test]
+fn trap_routines_drive_the_devices() {
+    let source = r#"
+        .ORIG x3000
+        LEA R0, HELLO
+        PUTS
+        LEA R0, PACKED
+        PUTSP
+        GETC
+        OUT
+        IN
+        ADD R2, R0, #0
+        HALT
+HELLO   .STRINGZ "hi "
+PACKED  .FILL x6F6E
+        .FILL x0021
+        .FILL 0
+        .END
+    "#;
+
+    for engine in ENGINES {
+        let (mut vm, console) = boot(engine, source, b"xy");
+        vm.run(10_000).unwrap();
+
+        assert!(vm.halted());
+        assert_eq!(
+            String::from_utf8(console.output()).unwrap(),
+            "hi no!xEnter a character: y\nProgram halted\n"
+        );
+        assert_eq!(
+            vm.register_storage.locations[Registers::R2 as usize],
+            b'y' as u16
+        );
+    }
+}
+
+#[test]
+fn routines_return_to_the_caller_in_user_mode() {
+    let source = "
+        .ORIG x3000
+        AND R1, R1, #0
+        ADD R1, R1, #5
+        ADD R0, R0, #0
+        OUT
+        ADD R2, R1, #0
+        HALT
+        .END
+    ";
+
+    for engine in ENGINES {
+        let (mut vm, _) = boot(engine, source, b"");
+        vm.register_storage.locations[Registers::R0 as usize] = b'!' as u16;
+        vm.register_storage.locations[Registers::R6 as usize] = 0xFE00;
+        vm.run(4).unwrap();
+
+        /* OUT entered the routine in supervisor mode, with the return address in R7 */
+        assert_eq!(vm.privilege(), Privilege::Supervisor);
+        assert_eq!(
+            vm.register_storage.locations[Registers::R7 as usize],
+            0x3004
+        );
+
+        while vm.pc() != 0x3004 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.privilege(), Privilege::User);
+        assert_eq!(
+            vm.register_storage.locations[Registers::R6 as usize],
+            0xFE00
+        );
+
+        vm.step().unwrap();
+        assert_eq!(vm.register_storage.locations[Registers::R2 as usize], 5);
+    }
+}
+
+#[test]
+fn halt_continues_after_the_clock_is_restarted() {
+    let source = "
+        .ORIG x3000
+        HALT
+        ADD R1, R1, #1
+        HALT
+        .END
+    ";
+
+    for engine in ENGINES {
+        let (mut vm, console) = boot(engine, source, b"");
+        vm.run(1_000).unwrap();
+        assert!(vm.halted());
+
+        vm.write_memory(MCR, CLOCK_ENABLE);
+        vm.run(1_000).unwrap();
+        assert!(vm.halted());
+        assert_eq!(vm.register_storage.locations[Registers::R1 as usize], 1);
+        assert_eq!(console.output(), b"Program halted\nProgram halted\n");
+    }
+}
+
+#[test]
+fn programs_can_replace_trap_routines() {
+    /* point OUT at a routine that writes every character twice */
+    let source = "
+        .ORIG x3000
+        LEA R1, DOUBLE
+        STI R1, OUT_ENTRY
+        LD R0, CHAR
+        OUT
+        HALT
+DOUBLE  ST R1, SAVE
+DISPLAY LDI R1, DSR_ADDR
+        BRzp DISPLAY
+        STI R0, DDR_ADDR
+        STI R0, DDR_ADDR
+        LD R1, SAVE
+        RTI
+OUT_ENTRY .FILL x0021
+DSR_ADDR  .FILL xFE04
+DDR_ADDR  .FILL xFE06
+CHAR      .FILL x0041
+SAVE      .FILL 0
+        .END
+    ";
+
+    for engine in ENGINES {
+        let (mut vm, console) = boot(engine, source, b"");
+        vm.run(1_000).unwrap();
+
+        assert_eq!(
+            vm.memory.locations[TRAP_VECTOR_TABLE as usize + 0x21],
+            0x3005
+        );
+        assert_eq!(console.output(), b"AAProgram halted\n");
+    }
+}
+
+#[test]
+fn faults_report_through_the_os() {
+    let cases = [
+        ("TRAP x40", "Unknown trap vector\nProgram halted\n"),
+        (".FILL xD000", "Illegal opcode\nProgram halted\n"),
+        ("RTI", "Privilege mode violation\nProgram halted\n"),
+        ("STI R0, OS", "Access violation\nProgram halted\n"),
+    ];
+
+    for engine in ENGINES {
+        for (instruction, message) in cases {
+            let source = format!(".ORIG x3000\n{instruction}\nHALT\nOS .FILL x0200\n.END");
+            let (mut vm, console) = boot(engine, &source, b"");
+            vm.set_protection(Some(Protection::lc3()));
+            vm.run(1_000).unwrap();
+
+            assert!(vm.halted(), "{instruction}");
+            assert_eq!(String::from_utf8(console.output()).unwrap(), message);
+        }
+    }
+}
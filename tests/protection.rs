@@ -0,0 +1,292 @@
+mod common;
+
+use common::{ENGINES, machine_with, set_registers};
+use virtual_machine::libs::{
+    bus::{self, Device},
+    console::BufferConsole,
+    constants::{KBDR, KBSR},
+    error::{Fault, VmError},
+    protection::{Access, AccessKind, Privilege, Protection, Region},
+    snapshot::Snapshot,
+    types::{MemomryTrait, Opcodes, Registers},
+    vm::{Engine, Vm},
+};
+
+/* STR R1, R2, #0; ADD R3, R3, #1 */
+const STORE: [u16; 2] = [0x7280, 0x16E1];
+
+/* machine setup enforcing the LC-3 memory map */
+fn protect(vm: &mut Vm) {
+    vm.set_protection(Some(Protection::lc3()));
+}
+
+/* ADD R<register>, R<register>, #1; RTI, installed as the routine for `vector` */
+fn install_handler(vm: &mut Vm, vector: u16, address: u16, register: u16) {
+    vm.write_memory(0x0100 + vector, address);
+    vm.write_memory(address, 0x1000 | register << 9 | register << 6 | 0x21);
+    vm.write_memory(address + 1, 0x8000);
+}
+
+#[test]
+fn user_programs_cannot_touch_system_space() {
+    for engine in ENGINES {
+        let (mut vm, _) = machine_with(engine, &STORE, protect);
+
+        set_registers(&mut vm, &[(Registers::R1, 0x1234), (Registers::R2, 0x2000)]);
+
+        assert_eq!(
+            vm.step().unwrap_err(),
+            VmError {
+                pc: 0x3000,
+                instr: 0x7280,
+                opcode: Some(Opcodes::STR),
+                cause: Fault::AccessViolation {
+                    address: 0x2000,
+                    kind: AccessKind::Write,
+                },
+            }
+        );
+        assert_eq!(vm.memory.locations[0x2000], 0);
+        assert_eq!(vm.instruction_count, 0);
+
+        vm.set_pc(0x3000);
+        vm.set_privilege(Privilege::Supervisor);
+        vm.run(2).unwrap();
+        assert_eq!(vm.memory.locations[0x2000], 0x1234);
+    }
+}
+
+#[test]
+fn checks_indirect_reads_and_fetches() {
+    for engine in ENGINES {
+        /* LDI R0, #0; .FILL xFE00 */
+        let (mut vm, _) = machine_with(engine, &[0xA000, 0xFE00], protect);
+        let err = vm.step().unwrap_err();
+        assert_eq!(
+            err.cause,
+            Fault::AccessViolation {
+                address: 0xFE00,
+                kind: AccessKind::Read,
+            }
+        );
+
+        /* JMP R2 into system space */
+        let (mut vm, _) = machine_with(engine, &[0xC080], protect);
+
+        set_registers(&mut vm, &[(Registers::R2, 0x0200)]);
+        vm.step().unwrap();
+        let err = vm.step().unwrap_err();
+        assert_eq!(err.pc, 0x0200);
+        assert_eq!(
+            err.cause,
+            Fault::AccessViolation {
+                address: 0x0200,
+                kind: AccessKind::Execute,
+            }
+        );
+    }
+}
+
+#[test]
+fn custom_regions_restrict_access_kinds() {
+    let protection = Protection {
+        regions: vec![Region {
+            start: 0x4000,
+            end: 0x40FF,
+            user: Access::READ | Access::EXECUTE,
+            supervisor: Access::ALL,
+        }],
+    };
+
+    /* LDR R0, R2, #5; STR R0, R2, #6 */
+    let (mut vm, _) = machine_with(Engine::Interpreter, &[0x6085, 0x7086], protect);
+
+    set_registers(&mut vm, &[(Registers::R2, 0x4000)]);
+    vm.set_protection(Some(protection));
+    vm.write_memory(0x4005, 7);
+
+    vm.step().unwrap();
+    assert_eq!(vm.register_storage.locations[Registers::R0 as usize], 7);
+    assert_eq!(
+        vm.step().unwrap_err().cause,
+        Fault::AccessViolation {
+            address: 0x4006,
+            kind: AccessKind::Write,
+        }
+    );
+}
+
+#[test]
+fn violations_vector_through_the_interrupt_table() {
+    for engine in ENGINES {
+        let (mut vm, _) = machine_with(engine, &STORE, protect);
+
+        set_registers(
+            &mut vm,
+            &[
+                (Registers::R1, 0x1234),
+                (Registers::R2, 0x2000),
+                (Registers::R6, 0x4000),
+            ],
+        );
+        vm.set_exceptions(true);
+        install_handler(&mut vm, 0x02, 0x1000, 5);
+
+        /* the store traps into the handler on the supervisor stack */
+        vm.step().unwrap();
+        assert_eq!(vm.pc(), 0x1000);
+        assert_eq!(vm.privilege(), Privilege::Supervisor);
+        assert_eq!(
+            vm.register_storage.locations[Registers::R6 as usize],
+            0x2FFE
+        );
+        assert_eq!(vm.saved_usp, 0x4000);
+        assert_eq!(vm.memory.locations[0x2FFF], 0x8002);
+        assert_eq!(vm.memory.locations[0x2FFE], 0x3001);
+        assert_eq!(vm.memory.locations[0x2000], 0);
+
+        /* the handler returns past the faulting store */
+        vm.run(3).unwrap();
+        let registers = &vm.register_storage.locations;
+        assert_eq!(registers[Registers::R5 as usize], 1);
+        assert_eq!(registers[Registers::R3 as usize], 1);
+        assert_eq!(registers[Registers::R6 as usize], 0x4000);
+        assert_eq!(vm.privilege(), Privilege::User);
+        assert_eq!(vm.saved_ssp, 0x3000);
+        assert_eq!((vm.pc(), vm.instruction_count), (0x3002, 4));
+    }
+}
+
+#[test]
+fn rti_and_reserved_opcodes_raise_exceptions() {
+    for engine in ENGINES {
+        /* RTI in user mode is a privilege violation */
+        let (mut vm, _) = machine_with(engine, &[0x8000, 0x16E1], protect);
+
+        set_registers(&mut vm, &[(Registers::R6, 0x4000)]);
+        vm.set_exceptions(true);
+        install_handler(&mut vm, 0x00, 0x1100, 4);
+        vm.run(4).unwrap();
+        assert_eq!(vm.register_storage.locations[Registers::R4 as usize], 1);
+        assert_eq!(vm.register_storage.locations[Registers::R3 as usize], 1);
+
+        /* so is the reserved opcode, even without memory protection */
+        let (mut vm, _) = machine_with(engine, &[0xD000, 0x16E1], protect);
+
+        set_registers(&mut vm, &[(Registers::R6, 0x4000)]);
+        vm.set_protection(None);
+        vm.set_exceptions(true);
+        install_handler(&mut vm, 0x01, 0x1200, 4);
+        vm.run(4).unwrap();
+        assert_eq!(vm.register_storage.locations[Registers::R4 as usize], 1);
+        assert_eq!(vm.register_storage.locations[Registers::R3 as usize], 1);
+        assert_eq!(vm.pc(), 0x3002);
+    }
+}
+
+#[test]
+fn snapshots_keep_the_privilege_state() {
+    let registers = [
+        (Registers::R1, 0x1234),
+        (Registers::R2, 0x2000),
+        (Registers::R6, 0x4000),
+    ];
+    let (mut vm, _) = machine_with(Engine::Interpreter, &STORE, protect);
+
+    set_registers(&mut vm, &registers);
+    vm.set_exceptions(true);
+    install_handler(&mut vm, 0x02, 0x1000, 5);
+    vm.step().unwrap();
+
+    let snapshot = Snapshot::from_bytes(&Snapshot::capture(&vm).to_bytes()).unwrap();
+    let (mut restored, _) = machine_with(Engine::Interpreter, &[], protect);
+    restored.set_exceptions(true);
+    snapshot.restore(&mut restored);
+    assert_eq!(restored.privilege(), Privilege::Supervisor);
+    assert_eq!(restored.psr(), vm.psr());
+
+    vm.run(3).unwrap();
+    restored.run(3).unwrap();
+    assert_eq!(Snapshot::capture(&restored), Snapshot::capture(&vm));
+    assert_eq!(restored.privilege(), Privilege::User);
+}
+
+/* a device register holding a pointer into system space, counting its reads */
+#[derive(Default)]
+struct Pointer {
+    reads: u16,
+}
+
+impl Device for Pointer {
+    fn name(&self) -> &str {
+        "pointer"
+    }
+
+    fn peek(&self, _offset: u16) -> u16 {
+        0x0400
+    }
+
+    fn read(&mut self, offset: u16) -> u16 {
+        self.reads += 1;
+        self.peek(offset)
+    }
+
+    fn write(&mut self, _offset: u16, _value: u16) {}
+}
+
+#[test]
+fn indirect_pointers_are_peeked_through_the_bus() {
+    for engine in ENGINES {
+        /* LDI R0, #1; STI R0, #0, both through the device at x3002 */
+        let (mut vm, _) = machine_with(engine, &[0xA001, 0xB000], protect);
+        vm.memory.locations[0x3002] = 0x4000;
+        let pointer = vm
+            .memory
+            .bus
+            .attach(0x3002, 0x3002, Pointer::default())
+            .unwrap();
+
+        let err = vm.step().unwrap_err();
+        assert_eq!(
+            err.cause,
+            Fault::AccessViolation {
+                address: 0x0400,
+                kind: AccessKind::Read,
+            },
+            "{engine:?}"
+        );
+
+        vm.set_pc(0x3001);
+        let err = vm.step().unwrap_err();
+        assert_eq!(
+            err.cause,
+            Fault::AccessViolation {
+                address: 0x0400,
+                kind: AccessKind::Write,
+            },
+            "{engine:?}"
+        );
+        /* the checks did not read the device */
+        assert_eq!(pointer.borrow().reads, 0, "{engine:?}");
+    }
+}
+
+#[test]
+fn supervisor_loads_from_the_keyboard_take_the_typed_key() {
+    for engine in ENGINES {
+        /* LDI R0, #1; LDI R1, #1; .FILL KBSR; .FILL KBDR */
+        let (mut vm, _) = machine_with(engine, &[0xA001, 0xA201, KBSR, KBDR], protect);
+        vm.set_privilege(Privilege::Supervisor);
+        bus::attach_console_devices(&mut vm.memory.bus, BufferConsole::with_input(b"k")).unwrap();
+
+        vm.run(2).unwrap();
+        let registers = &vm.register_storage.locations;
+        assert_eq!(registers[Registers::R0 as usize], 0x8000, "{engine:?}");
+        assert_eq!(
+            registers[Registers::R1 as usize],
+            u16::from(b'k'),
+            "{engine:?}"
+        );
+        assert_eq!(vm.memory.read(KBSR), 0, "{engine:?}");
+    }
+}
@@ -0,0 +1,171 @@
+/*
+independent, table-driven model of the LC-3 ISA (Patt & Patel, appendix A),
+used as the oracle for differential testing. it deliberately shares no code
+with `Instructions`
+*/
+
+pub const N: u16 = 0b100;
+pub const Z: u16 = 0b010;
+pub const P: u16 = 0b001;
+
+/* the machine control register; clearing bit 15 stops the clock */
+pub const MCR: usize = 0xFFFE;
+
+/* operand fields of an instruction word, decoded once */
+#[derive(Debug, Clone, Copy)]
+pub struct Fields {
+    pub dr: usize,
+    pub sr1: usize,
+    pub sr2: usize,
+    pub imm_mode: bool,
+    pub imm5: u16,
+    pub offset6: u16,
+    pub offset9: u16,
+    pub offset11: u16,
+    pub long_jsr: bool,
+    pub nzp: u16,
+}
+
+impl Fields {
+    pub fn decode(word: u16) -> Self {
+        Self {
+            dr: ((word >> 9) & 0b111) as usize,
+            sr1: ((word >> 6) & 0b111) as usize,
+            sr2: (word & 0b111) as usize,
+            imm_mode: word & (1 << 5) != 0,
+            imm5: sext(word, 5),
+            offset6: sext(word, 6),
+            offset9: sext(word, 9),
+            offset11: sext(word, 11),
+            long_jsr: word & (1 << 11) != 0,
+            nzp: (word >> 9) & 0b111,
+        }
+    }
+}
+
+fn sext(word: u16, bits: u32) -> u16 {
+    let shift = 16 - bits;
+    (((word << shift) as i16) >> shift) as u16
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /* executed; the memory word written, if any */
+    Retired(Option<(u16, u16)>),
+    /* RTI in user mode / the reserved opcode */
+    Illegal,
+    /* TRAPs are outside the model */
+    Unsupported,
+    /* the clock is stopped, nothing executes */
+    Halted,
+}
+
+pub struct Reference {
+    pub regs: [u16; 8],
+    pub pc: u16,
+    /* N, Z or P */
+    pub cc: u16,
+    pub memory: Vec<u16>,
+}
+
+type Semantics = fn(&mut Reference, Fields) -> Outcome;
+
+/* indexed by opcode */
+const TABLE: [(&str, Semantics); 16] = [
+    ("BR", |m, f| {
+        if f.nzp & m.cc != 0 {
+            m.pc = m.pc.wrapping_add(f.offset9);
+        }
+        Outcome::Retired(None)
+    }),
+    ("ADD", |m, f| {
+        let operand = if f.imm_mode { f.imm5 } else { m.regs[f.sr2] };
+        m.set(f.dr, m.regs[f.sr1].wrapping_add(operand))
+    }),
+    ("LD", |m, f| {
+        let value = m.memory[m.pc.wrapping_add(f.offset9) as usize];
+        m.set(f.dr, value)
+    }),
+    ("ST", |m, f| {
+        m.write(m.pc.wrapping_add(f.offset9), m.regs[f.dr])
+    }),
+    ("JSR", |m, f| {
+        let temp = m.pc;
+        m.pc = if f.long_jsr {
+            m.pc.wrapping_add(f.offset11)
+        } else {
+            m.regs[f.sr1]
+        };
+        m.regs[7] = temp;
+        Outcome::Retired(None)
+    }),
+    ("AND", |m, f| {
+        let operand = if f.imm_mode { f.imm5 } else { m.regs[f.sr2] };
+        m.set(f.dr, m.regs[f.sr1] & operand)
+    }),
+    ("LDR", |m, f| {
+        let value = m.memory[m.regs[f.sr1].wrapping_add(f.offset6) as usize];
+        m.set(f.dr, value)
+    }),
+    ("STR", |m, f| {
+        m.write(m.regs[f.sr1].wrapping_add(f.offset6), m.regs[f.dr])
+    }),
+    ("RTI", |_, _| Outcome::Illegal),
+    ("NOT", |m, f| m.set(f.dr, !m.regs[f.sr1])),
+    ("LDI", |m, f| {
+        let pointer = m.memory[m.pc.wrapping_add(f.offset9) as usize];
+        let value = m.memory[pointer as usize];
+        m.set(f.dr, value)
+    }),
+    ("STI", |m, f| {
+        let pointer = m.memory[m.pc.wrapping_add(f.offset9) as usize];
+        m.write(pointer, m.regs[f.dr])
+    }),
+    ("JMP", |m, f| {
+        m.pc = m.regs[f.sr1];
+        Outcome::Retired(None)
+    }),
+    ("RES", |_, _| Outcome::Illegal),
+    /* LEA sets the condition codes, as in the original LC-3 */
+    ("LEA", |m, f| m.set(f.dr, m.pc.wrapping_add(f.offset9))),
+    ("TRAP", |_, _| Outcome::Unsupported),
+];
+
+impl Reference {
+    pub fn new(regs: [u16; 8], pc: u16, memory: Vec<u16>) -> Self {
+        Self {
+            regs,
+            pc,
+            cc: Z,
+            memory,
+        }
+    }
+
+    pub fn mnemonic(word: u16) -> &'static str {
+        TABLE[(word >> 12) as usize].0
+    }
+
+    pub fn step(&mut self) -> Outcome {
+        if self.memory[MCR] & 0x8000 == 0 {
+            return Outcome::Halted;
+        }
+        let word = self.memory[self.pc as usize];
+        self.pc = self.pc.wrapping_add(1);
+        (TABLE[(word >> 12) as usize].1)(self, Fields::decode(word))
+    }
+
+    fn set(&mut self, dr: usize, value: u16) -> Outcome {
+        self.regs[dr] = value;
+        self.cc = match value {
+            0 => Z,
+            v if v & 0x8000 != 0 => N,
+            _ => P,
+        };
+        Outcome::Retired(None)
+    }
+
+    fn write(&mut self, address: u16, value: u16) -> Outcome {
+        self.memory[address as usize] = value;
+        Outcome::Retired(Some((address, value)))
+    }
+}
@@ -0,0 +1,134 @@
+use virtual_machine::libs::{
+    bus,
+    console::{BufferConsole, Console},
+    loader::Image,
+    replay::{InputEvent, InputRecording, RecordingConsole, ReplayConsole},
+    vm::{Engine, Vm},
+};
+
+/*
+LOOP GETC
+     ADD R1, R1, #1
+     ADD R1, R1, R0    ; input decides how long the program runs
+     OUT
+     BRnzp LOOP
+*/
+fn echo_machine(engine: Engine) -> Vm {
+    let mut vm = Vm::with_engine(engine);
+    vm.load_image(&Image {
+        origin: 0x3000,
+        words: vec![0xF020, 0x1261, 0x1240, 0xF021, 0x0FFB],
+    });
+    vm
+}
+
+#[test]
+fn replay_reproduces_recorded_session() {
+    let mut recorded = echo_machine(Engine::Interpreter);
+    let live_output = BufferConsole::with_input(b"lc3");
+    let console = RecordingConsole::new(live_output.clone());
+    let recording = console.recording();
+    recorded.set_console(console);
+    recorded.run(20).unwrap();
+
+    let recording = InputRecording::from_text(&recording.borrow().to_text()).unwrap();
+    assert_eq!(
+        recording.events[..2],
+        [
+            InputEvent {
+                instruction: 0,
+                byte: Some(b'l')
+            },
+            InputEvent {
+                instruction: 5,
+                byte: Some(b'c')
+            },
+        ]
+    );
+    assert_eq!(recording.events[3].byte, None);
+
+    /* replay on the other engine, with nothing typed on the host */
+    let mut replayed = echo_machine(Engine::Threaded);
+    let replay_output = BufferConsole::new();
+    let console = ReplayConsole::new(recording, replay_output.clone());
+    let status = console.status();
+    replayed.set_console(console);
+    replayed.run(20).unwrap();
+
+    assert_eq!(replay_output.output(), live_output.output());
+    assert_eq!(
+        replayed.register_storage.locations,
+        recorded.register_storage.locations
+    );
+    assert_eq!(status.borrow().consumed, 4);
+    assert_eq!(status.borrow().divergence, None);
+}
+
+#[test]
+fn replay_reports_divergence() {
+    let recording = InputRecording::from_text("# lc3 input recording v1\n3 0x61\n").unwrap();
+
+    let mut vm = echo_machine(Engine::Interpreter);
+    let console = ReplayConsole::new(recording, BufferConsole::new());
+    let status = console.status();
+    vm.set_console(console);
+    vm.run(1).unwrap();
+
+    let divergence = status.borrow().divergence.unwrap();
+    assert_eq!(divergence.expected_instruction, 3);
+    assert_eq!(divergence.actual_instruction, 0);
+}
+
+/*
+LOOP LDI R0, KBSR   ; poll until a key is ready
+     BRzp LOOP
+     LDI R1, KBDR
+     ADD R2, R2, R1
+     BRnzp LOOP
+*/
+fn keyboard_machine(engine: Engine, console: impl Console + 'static) -> Vm {
+    let mut vm = Vm::with_engine(engine);
+    vm.set_console(console);
+    let shared = vm.share_console();
+    bus::attach_console_devices(&mut vm.memory.bus, shared).unwrap();
+    vm.load_image(&Image {
+        origin: 0x3000,
+        words: vec![0xA004, 0x07FE, 0xA203, 0x1481, 0x0FFB, 0xFE00, 0xFE02],
+    });
+    vm
+}
+
+#[test]
+fn keyboard_input_is_timestamped_and_empty_polls_are_not_recorded() {
+    let console = RecordingConsole::new(BufferConsole::with_input(b"ab"));
+    let recording = console.recording();
+    let mut recorded = keyboard_machine(Engine::Interpreter, console);
+    recorded.run(50).unwrap();
+
+    let recording = recording.borrow().clone();
+    assert_eq!(
+        recording.events,
+        [
+            InputEvent {
+                instruction: 0,
+                byte: Some(b'a')
+            },
+            InputEvent {
+                instruction: 5,
+                byte: Some(b'b')
+            },
+        ]
+    );
+
+    let console = ReplayConsole::new(recording, BufferConsole::new());
+    let status = console.status();
+    let mut replayed = keyboard_machine(Engine::Threaded, console);
+    replayed.run(50).unwrap();
+
+    assert_eq!(
+        replayed.register_storage.locations,
+        recorded.register_storage.locations
+    );
+    assert_eq!(status.borrow().consumed, 2);
+    assert_eq!(status.borrow().divergence, None);
+}
@@ -0,0 +1,188 @@
+mod common;
+/* `cli translate --revision strict -o tests/translated/jump.rs` of `JUMP` */
+#[rustfmt::skip]
+#[path = "translated/jump.rs"]
+mod jump;
+
+use common::{ENGINES, machine_with};
+use virtual_machine::libs::{
+    console::BufferConsole,
+    golden::{self, Spec},
+    instructions::Revision,
+    loader::Image,
+    translate::Translator,
+    types::{
+        ConditionalFlags, MemomryTrait, Memory, RegisterStorage, RegisterStorageTrait, Registers,
+    },
+    vm::{Engine, Vm},
+};
+
+/* clears the condition codes to Z, then loads x3000 with LEA */
+const PROGRAM: [u16; 2] = [
+    0x5260, // AND R1, R1, #0
+    0xE1FE, // LEA R0, #-2
+];
+
+/* reaches a LEA only through a register, so the translator cannot discover it */
+const JUMP: [u16; 7] = [
+    0x2403, // LD R2, TARGET
+    0x5020, // AND R0, R0, #0
+    0xC080, // JMP R2
+    0xF025, // HALT
+    0x3005, // TARGET .FILL x3005
+    0xE7FA, // LEA R3, #-6
+    0xF025, // HALT
+];
+
+fn cond(vm: &Vm) -> u16 {
+    vm.register_storage.locations[Registers::COND as usize]
+}
+
+#[test]
+fn legacy_lea_sets_the_condition_codes() {
+    for engine in ENGINES {
+        let (mut vm, _) = machine_with(engine, &PROGRAM, |vm| vm.set_revision(Revision::Legacy));
+        vm.run(2).unwrap();
+
+        assert_eq!(
+            vm.register_storage.locations[Registers::R0 as usize],
+            0x3000
+        );
+        assert_eq!(cond(&vm), ConditionalFlags::POS as u16, "{engine:?}");
+    }
+}
+
+#[test]
+fn strict_lea_leaves_the_condition_codes_alone() {
+    for engine in ENGINES {
+        let (mut vm, _) = machine_with(engine, &PROGRAM, |vm| vm.set_revision(Revision::Strict));
+        vm.run(2).unwrap();
+
+        assert_eq!(
+            vm.register_storage.locations[Registers::R0 as usize],
+            0x3000
+        );
+        assert_eq!(cond(&vm), ConditionalFlags::ZRO as u16, "{engine:?}");
+    }
+}
+
+#[test]
+fn loads_set_the_condition_codes_in_both_revisions() {
+    for engine in ENGINES {
+        for revision in [Revision::Legacy, Revision::Strict] {
+            let (mut vm, _) = machine_with(
+                engine,
+                &[
+                    0x5260, // AND R1, R1, #0
+                    0x2001, // LD R0, #1
+                    0x0000, // skipped
+                    0x8000, // x8000
+                ],
+                |vm| vm.set_revision(revision),
+            );
+            vm.run(2).unwrap();
+
+            assert_eq!(
+                vm.register_storage.locations[Registers::R0 as usize],
+                0x8000
+            );
+            assert_eq!(
+                cond(&vm),
+                ConditionalFlags::NEG as u16,
+                "{engine:?} {revision:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn changing_the_revision_recompiles_cached_blocks() {
+    let (mut vm, _) = machine_with(Engine::Threaded, &PROGRAM, |vm| {
+        vm.set_revision(Revision::Strict)
+    });
+    vm.run(2).unwrap();
+    assert_eq!(cond(&vm), ConditionalFlags::ZRO as u16);
+
+    vm.set_revision(Revision::Legacy);
+    vm.set_pc(0x3000);
+    vm.run(2).unwrap();
+    assert_eq!(cond(&vm), ConditionalFlags::POS as u16);
+}
+
+#[test]
+fn the_translator_follows_the_revision() {
+    let image = Image {
+        origin: 0x3000,
+        words: PROGRAM.to_vec(),
+    };
+    let lea = "rs.locations[0] = 0x3000;\n";
+
+    let legacy = Translator::new(&image, Revision::Legacy, false).emit(false);
+    assert!(legacy.contains(&format!("{lea}    rs.update_flags(0)?;")));
+
+    let strict = Translator::new(&image, Revision::Strict, false).emit(false);
+    assert!(strict.contains(lea));
+    assert!(!strict.contains("rs.update_flags(0)"));
+}
+
+#[test]
+fn translated_fallback_follows_the_revision() {
+    let image = Image {
+        origin: 0x3000,
+        words: JUMP.to_vec(),
+    };
+    assert_eq!(
+        Translator::new(&image, Revision::Strict, false).emit(false),
+        include_str!("translated/jump.rs"),
+        "regenerate tests/translated/jump.rs"
+    );
+
+    let (mut vm, _) = machine_with(Engine::Interpreter, &JUMP, |vm| {
+        vm.set_revision(Revision::Strict)
+    });
+    let mut register_storage = RegisterStorage::new();
+    register_storage.locations = vm.register_storage.locations;
+    vm.run_to_halt(100).unwrap();
+
+    let mut memory = Memory::new();
+    jump::load(&mut memory);
+    jump::run(
+        &mut register_storage,
+        &mut memory,
+        &mut BufferConsole::new(),
+    )
+    .unwrap();
+
+    assert_eq!(register_storage.locations, vm.register_storage.locations);
+    assert_eq!(register_storage.locations[Registers::R3 as usize], 0x3000);
+    assert_eq!(cond(&vm), ConditionalFlags::ZRO as u16);
+}
+
+#[test]
+fn golden_specs_select_the_revision() {
+    let mut words = PROGRAM.to_vec();
+    words.push(0xF025); // HALT
+    let image = Image {
+        origin: 0x3000,
+        words,
+    };
+    let expect_zero = format!("register COND {}", ConditionalFlags::ZRO as u16);
+
+    let spec = Spec::parse(&format!("revision strict\n{expect_zero}")).unwrap();
+    assert_eq!(spec.revision, Revision::Strict);
+    for engine in ENGINES {
+        assert!(golden::run_case("lea", &image, &spec, engine).passed());
+    }
+
+    let spec = Spec::parse(&expect_zero).unwrap();
+    assert!(!golden::run_case("lea", &image, &spec, Engine::Interpreter).passed());
+    assert!(Spec::parse("revision revised").is_err());
+}
+
+#[test]
+fn revisions_are_selected_by_name() {
+    assert_eq!(Vm::new().revision(), Revision::Legacy);
+    assert_eq!(Revision::from_name("legacy"), Some(Revision::Legacy));
+    assert_eq!(Revision::from_name("strict"), Some(Revision::Strict));
+    assert_eq!(Revision::from_name("revised"), None);
+}
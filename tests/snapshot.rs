@@ -0,0 +1,150 @@
+use virtual_machine::libs::{
+    bus,
+    console::BufferConsole,
+    constants::{KBDR, KBSR, TMCNT, TMCR, TMRR},
+    instructions::Revision,
+    loader::Image,
+    protection::{Access, Protection, Region},
+    snapshot::{Config, Snapshot, SnapshotError},
+    timer::{self, ENABLE},
+    types::MemomryTrait,
+    vm::{Engine, Vm},
+};
+
+/*
+LOOP ADD R1, R1, #1
+     ADD R0, R1, #15
+     ADD R0, R0, #15
+     ADD R0, R0, #15
+     OUT
+     BRnzp LOOP
+*/
+fn counting_machine() -> (Vm, BufferConsole) {
+    let console = BufferConsole::with_input(b"pending");
+    let mut vm = Vm::new();
+    vm.set_console(console.clone());
+    vm.load_image(&Image {
+        origin: 0x3000,
+        words: vec![0x1261, 0x106F, 0x102F, 0x102F, 0xF021, 0x0FFA],
+    });
+    (vm, console)
+}
+
+#[test]
+fn restored_machine_continues_identically() {
+    let (mut original, original_console) = counting_machine();
+    original.run(45).unwrap();
+
+    let bytes = Snapshot::capture(&original).to_bytes();
+    let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+    assert_eq!(snapshot, Snapshot::capture(&original));
+
+    let (mut restored, restored_console) = counting_machine();
+    restored.memory.locations.fill(0);
+    snapshot.restore(&mut restored);
+
+    original.run(100).unwrap();
+    restored.run(100).unwrap();
+
+    assert_eq!(Snapshot::capture(&restored), Snapshot::capture(&original));
+    assert_eq!(restored_console.output(), original_console.output());
+    assert_eq!(restored.instruction_count, 145);
+}
+
+#[test]
+fn rejects_foreign_and_future_files() {
+    let (vm, _) = counting_machine();
+    let mut bytes = Snapshot::capture(&vm).to_bytes();
+
+    assert!(matches!(
+        Snapshot::from_bytes(b"not a snapshot"),
+        Err(SnapshotError::BadMagic)
+    ));
+    assert!(matches!(
+        Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(SnapshotError::Truncated)
+    ));
+
+    bytes[8..10].copy_from_slice(&99u16.to_be_bytes());
+    assert!(matches!(
+        Snapshot::from_bytes(&bytes),
+        Err(SnapshotError::UnsupportedVersion(99))
+    ));
+}
+
+#[test]
+fn only_the_current_version_is_read() {
+    let (vm, _) = counting_machine();
+    let mut bytes = Snapshot::capture(&vm).to_bytes();
+
+    for version in [1u16, 2] {
+        bytes[8..10].copy_from_slice(&version.to_be_bytes());
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes),
+            Err(SnapshotError::UnsupportedVersion(v)) if v == version
+        ));
+    }
+}
+
+#[test]
+fn configuration_is_captured_and_restored() {
+    let (mut original, _) = counting_machine();
+    original.set_engine(Engine::Threaded);
+    original.set_revision(Revision::Strict);
+    original.set_exceptions(true);
+    original.set_trap_table(true);
+    original.set_arithmetic_extension(true);
+    original.set_protection(Some(Protection {
+        regions: vec![Region {
+            start: 0x4000,
+            end: 0x4FFF,
+            user: Access::READ | Access::EXECUTE,
+            supervisor: Access::ALL,
+        }],
+    }));
+    original.run(10).unwrap();
+
+    let snapshot = Snapshot::from_bytes(&Snapshot::capture(&original).to_bytes()).unwrap();
+    assert_eq!(snapshot.config, Config::capture(&original));
+
+    let (mut restored, _) = counting_machine();
+    snapshot.restore(&mut restored);
+    assert_eq!(Config::capture(&restored), snapshot.config);
+    assert_eq!(restored.engine(), Engine::Threaded);
+    assert_eq!(restored.protection(), original.protection());
+
+    original.run(100).unwrap();
+    restored.run(100).unwrap();
+    assert_eq!(Snapshot::capture(&restored), Snapshot::capture(&original));
+}
+
+#[test]
+fn device_state_is_captured_and_restored() {
+    let with_devices = |input: &[u8]| {
+        let (mut vm, _) = counting_machine();
+        timer::attach_timer(&mut vm.memory.bus).unwrap();
+        bus::attach_console_devices(&mut vm.memory.bus, BufferConsole::with_input(input)).unwrap();
+        vm
+    };
+
+    let mut original = with_devices(b"k");
+    original.write_memory(TMRR, 1000);
+    original.write_memory(TMCR, ENABLE);
+    /* polling KBSR latches the key */
+    original.memory.read(KBSR);
+    original.run(45).unwrap();
+
+    let snapshot = Snapshot::from_bytes(&Snapshot::capture(&original).to_bytes()).unwrap();
+    assert_eq!(snapshot, Snapshot::capture(&original));
+
+    let mut restored = with_devices(b"");
+    snapshot.restore(&mut restored);
+    assert_eq!(restored.memory.read(TMCNT), 1000 - 45);
+    assert_eq!(restored.memory.read(KBSR) >> 15, 1);
+    assert_eq!(restored.memory.read(KBDR), u16::from(b'k'));
+    original.memory.read(KBDR);
+
+    original.run(100).unwrap();
+    restored.run(100).unwrap();
+    assert_eq!(Snapshot::capture(&restored), Snapshot::capture(&original));
+}
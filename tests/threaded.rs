@@ -0,0 +1,133 @@
+mod common;
+
+use common::{ENGINES, Rng};
+use virtual_machine::libs::{loader::Image, types::Registers, vm::Vm};
+
+/* random instruction words, excluding TRAP (which does host I/O) */
+fn random_program(rng: &mut Rng, len: usize) -> Vec<u16> {
+    (0..len)
+        .map(|_| {
+            loop {
+                let instr = rng.word();
+                if instr >> 12 != 0xF {
+                    break instr;
+                }
+            }
+        })
+        .collect()
+}
+
+fn machines(image: &Image) -> (Vm, Vm) {
+    let [interpreter, threaded] = ENGINES.map(|engine| {
+        let mut vm = Vm::with_engine(engine);
+        vm.load_image(image);
+        vm.set_pc(image.origin);
+        vm
+    });
+    (interpreter, threaded)
+}
+
+fn assert_same_state(interpreter: &Vm, threaded: &Vm, context: &str) {
+    assert_eq!(
+        interpreter.register_storage.locations, threaded.register_storage.locations,
+        "registers diverged: {context}"
+    );
+    assert_eq!(
+        interpreter.instruction_count, threaded.instruction_count,
+        "instruction count diverged: {context}"
+    );
+    assert!(
+        interpreter.memory.locations == threaded.memory.locations,
+        "memory diverged: {context}"
+    );
+}
+
+#[test]
+fn counting_loop_matches_interpreter() {
+    /*
+        AND R0, R0, #0
+        ADD R1, R0, #10
+    LOOP ADD R0, R0, #3
+        ST  R0, RESULT
+        ADD R1, R1, #-1
+        BRp LOOP
+        BRnzp #-1
+    RESULT .FILL #0
+    */
+    let image = Image {
+        origin: 0x3000,
+        words: vec![
+            0x5020, 0x122A, 0x1023, 0x3003, 0x127F, 0x03FC, 0x0FFF, 0x0000,
+        ],
+    };
+    let (mut interpreter, mut threaded) = machines(&image);
+
+    interpreter.run(200).unwrap();
+    threaded.run(200).unwrap();
+
+    assert_same_state(&interpreter, &threaded, "counting loop");
+    assert_eq!(threaded.memory.locations[0x3007], 30);
+    assert_eq!(
+        threaded.register_storage.locations[Registers::R1 as usize],
+        0
+    );
+    assert!(threaded.block_cache().get(0x3002).is_some());
+}
+
+#[test]
+fn self_modifying_store_invalidates_block() {
+    /*
+        LD  R0, PATCH
+        ST  R0, TARGET
+    TARGET ADD R1, R1, #1   ; overwritten with ADD R1, R1, #5 before it runs
+        BRnzp #-1
+    PATCH ADD R1, R1, #5
+    */
+    let image = Image {
+        origin: 0x3000,
+        words: vec![0x2003, 0x3000, 0x1261, 0x0FFF, 0x1265],
+    };
+    let (mut interpreter, mut threaded) = machines(&image);
+
+    interpreter.run(3).unwrap();
+    threaded.run(3).unwrap();
+
+    assert_same_state(&interpreter, &threaded, "self-modifying store");
+    assert_eq!(
+        threaded.register_storage.locations[Registers::R1 as usize],
+        5
+    );
+}
+
+#[test]
+fn random_programs_match_interpreter() {
+    let mut rng = Rng(0x5EED_1C3A_0000_0001);
+
+    for seed in 0..200 {
+        let image = Image {
+            origin: 0x3000,
+            words: random_program(&mut rng, 256),
+        };
+        let (mut interpreter, mut threaded) = machines(&image);
+
+        for vm in [&mut interpreter, &mut threaded] {
+            for register in 0..8 {
+                vm.register_storage.locations[register] = 0x3000 + (register as u16) * 0x20;
+            }
+        }
+
+        /* run in uneven slices so budgets expire in the middle of blocks */
+        for slice in [1, 7, 64, 3, 500] {
+            let expected = interpreter.run(slice);
+            let actual = threaded.run(slice);
+            let context = format!("seed {seed}, pc {:#06x}", interpreter.pc());
+
+            assert_eq!(expected.is_ok(), actual.is_ok(), "{context}");
+            assert_same_state(&interpreter, &threaded, &context);
+
+            if expected.is_err() {
+                break;
+            }
+        }
+    }
+}
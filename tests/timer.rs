@@ -0,0 +1,127 @@
+mod common;
+
+use common::ENGINES;
+use virtual_machine::libs::{
+    bus::{self, Device},
+    console::BufferConsole,
+    loader::Image,
+    protection::Privilege,
+    timer::{self, COUNT_CYCLES, ENABLE, EXPIRED, INTERRUPT_ENABLE, PERIODIC, Timer},
+    types::Registers,
+    vm::{Engine, Vm},
+};
+
+/* TMCR, TMRR and TMCNT offsets */
+const CONTROL: u16 = 0;
+const RELOAD: u16 = 2;
+const COUNTER: u16 = 4;
+
+#[test]
+fn one_shot_timers_expire_and_stop() {
+    let mut timer = Timer::new();
+    timer.write(RELOAD, 3);
+    timer.write(CONTROL, ENABLE);
+
+    timer.tick(1);
+    timer.tick(1);
+    assert_eq!(timer.read(COUNTER), 1);
+    timer.tick(1);
+    assert_eq!(timer.read(CONTROL), EXPIRED);
+    assert_eq!(timer.read(COUNTER), 0);
+
+    /* stopped, and not enabled to interrupt */
+    timer.tick(1);
+    assert_eq!(timer.read(COUNTER), 0);
+    assert_eq!(timer.interrupt(), None);
+
+    timer.write(CONTROL, 0);
+    assert_eq!(timer.read(CONTROL), 0);
+}
+
+#[test]
+fn periodic_timers_reload_and_interrupt() {
+    let mut timer = Timer::new();
+    timer.write(RELOAD, 2);
+    timer.write(CONTROL, INTERRUPT_ENABLE | 5 << 8 | PERIODIC | ENABLE);
+
+    timer.tick(1);
+    assert_eq!(timer.interrupt(), None);
+    timer.tick(1);
+    let interrupt = timer.interrupt().unwrap();
+    assert_eq!((interrupt.vector, interrupt.priority), (0x81, 5));
+    assert_eq!(timer.read(COUNTER), 2);
+
+    /* acknowledged by rewriting the control register */
+    timer.write(CONTROL, INTERRUPT_ENABLE | 5 << 8 | PERIODIC | ENABLE);
+    assert_eq!(timer.interrupt(), None);
+    assert_eq!(timer.read(COUNTER), 2);
+}
+
+#[test]
+fn cycle_mode_counts_memory_accesses() {
+    let mut timer = Timer::new();
+    timer.write(RELOAD, 10);
+    timer.write(CONTROL, COUNT_CYCLES | ENABLE);
+
+    /* ADD, LDR, LDI */
+    for instr in [0x1021, 0x6040, 0xA000] {
+        timer.tick(bus::cycles(instr));
+    }
+    assert_eq!(timer.read(COUNTER), 4);
+}
+
+/*
+program: load the timer with a period of 10 and a periodic interrupt at
+priority 2, then count in R3 forever. the service routine counts in R4,
+acknowledges the timer and returns
+*/
+fn scheduler(engine: Engine) -> Vm {
+    let mut vm = Vm::with_engine(engine);
+    vm.set_console(BufferConsole::new());
+    vm.set_exceptions(true);
+    vm.register_storage.locations[Registers::R6 as usize] = 0x5000;
+    timer::attach_timer(&mut vm.memory.bus).unwrap();
+
+    vm.load_image(&Image {
+        origin: 0x3000,
+        words: vec![
+            0x2007, 0xB007, 0x2007, 0xB007, 0x16E1, 0x0FFE, 0x0000, 0x0000, 10, 0xFE0A, 0x4203,
+            0xFE08,
+        ],
+    });
+    vm.write_memory(0x0181, 0x1000);
+    vm.load_image(&Image {
+        origin: 0x1000,
+        words: vec![0x1921, 0xB001, 0x8000, 0xFE08],
+    });
+    vm
+}
+
+#[test]
+fn timer_interrupts_preempt_the_running_program() {
+    for engine in ENGINES {
+        let mut vm = scheduler(engine);
+
+        /* the timer starts with the 4th instruction and expires every 10 */
+        vm.run(13).unwrap();
+        assert_eq!(vm.pc(), 0x1000);
+        assert_eq!(vm.privilege(), Privilege::Supervisor);
+        assert_eq!(vm.psr() >> 8 & 0x7, 2);
+
+        vm.run(87).unwrap();
+        let registers = &vm.register_storage.locations;
+        assert_eq!(registers[Registers::R4 as usize], 9);
+        assert_eq!(registers[Registers::R6 as usize], 0x5000);
+        assert_eq!(vm.privilege(), Privilege::User);
+    }
+}
+
+#[test]
+fn interrupts_wait_for_a_lower_priority() {
+    let mut vm = scheduler(Engine::Interpreter);
+    vm.set_psr(vm.psr() | 2 << 8);
+
+    vm.run(100).unwrap();
+    assert_eq!(vm.register_storage.locations[Registers::R4 as usize], 0);
+    assert!(vm.pc() >= 0x3004);
+}
@@ -0,0 +1,97 @@
+/* `cli translate -o tests/translated/countdown.rs` of `countdown()` */
+#[rustfmt::skip]
+#[path = "translated/countdown.rs"]
+mod countdown;
+
+use virtual_machine::libs::{
+    console::BufferConsole,
+    instructions::Revision,
+    loader::Image,
+    translate::Translator,
+    types::{MemomryTrait, Memory, RegisterStorage, RegisterStorageTrait, Registers},
+    vm::Vm,
+};
+
+/*
+0x3000      LEA R0, MSG
+0x3001      PUTS
+0x3002      AND R1, R1, #0
+0x3003      ADD R1, R1, #3
+0x3004 LOOP JSR DIGIT
+0x3005      OUT
+0x3006      ADD R1, R1, #-1
+0x3007      BRp LOOP
+0x3008      HALT
+0x3009 DIGIT LD R0, ZERO
+0x300A      ADD R0, R0, R1
+0x300B      RET
+0x300C ZERO .FILL x30
+0x300D MSG  .STRINGZ "go\n"
+*/
+fn countdown() -> Image {
+    Image {
+        origin: 0x3000,
+        words: vec![
+            0xE00C, 0xF022, 0x5260, 0x1263, 0x4804, 0xF021, 0x127F, 0x03FC, 0xF025, 0x2002, 0x1001,
+            0xC1C0, 0x0030, 0x0067, 0x006F, 0x000A, 0x0000,
+        ],
+    }
+}
+
+#[test]
+fn discovers_branch_call_and_return_sites() {
+    /*
+    0x3000      AND R0, R0, #0
+    0x3001      JSR SUB
+    0x3002 LOOP ADD R0, R0, #-1
+    0x3003      BRp LOOP
+    0x3004      HALT
+    0x3005 SUB  ADD R0, R0, #5
+    0x3006      RET
+    */
+    let image = Image {
+        origin: 0x3000,
+        words: vec![0x5020, 0x4803, 0x103F, 0x03FE, 0xF025, 0x1025, 0xC1C0],
+    };
+    let translator = Translator::new(&image, Revision::Legacy, false);
+
+    assert_eq!(
+        translator.blocks().collect::<Vec<_>>(),
+        vec![0x3000, 0x3002, 0x3004, 0x3005]
+    );
+
+    let source = translator.emit(false);
+    assert!(source.contains("0x3005 => block_3005(rs, mem, console)?,"));
+    assert!(source.contains("Trap::execute_trap_instruction(rs, mem, console, 0x25 /* HALT */)?;"));
+    /* RET returns through the dispatcher */
+    assert!(source.contains("return Ok(rs.locations[7]);"));
+    assert!(!source.contains("fn main()"));
+}
+
+#[test]
+fn translated_code_matches_the_interpreter() {
+    let image = countdown();
+    assert_eq!(
+        Translator::new(&image, Revision::Legacy, false).emit(false),
+        include_str!("translated/countdown.rs"),
+        "regenerate tests/translated/countdown.rs"
+    );
+
+    let console = BufferConsole::new();
+    let mut vm = Vm::new();
+    vm.set_console(console.clone());
+    vm.load_image(&image);
+    let mut register_storage = RegisterStorage::new();
+    register_storage.locations = vm.register_storage.locations;
+    vm.run_to_halt(1000).unwrap();
+
+    let mut memory = Memory::new();
+    countdown::load(&mut memory);
+    let mut translated = BufferConsole::new();
+    countdown::run(&mut register_storage, &mut memory, &mut translated).unwrap();
+
+    assert_eq!(console.output(), b"go\n321Program halted\n");
+    assert_eq!(translated.output(), console.output());
+    assert_eq!(register_storage.locations, vm.register_storage.locations);
+    assert_eq!(register_storage.locations[Registers::R1 as usize], 0);
+}
@@ -0,0 +1,116 @@
+// translated from an LC-3 image: origin 0x3000, 17 words, 7 blocks
+#![allow(unreachable_code, unused_imports, clippy::all)]
+
+use virtual_machine::libs::{
+    instructions::{InstructionSet, InstructionSetError, Instructions},
+    console::Console,
+    trap::Trap,
+    types::{MemomryTrait, Memory, RegisterStorage, RegisterStorageTrait},
+};
+
+pub const ORIGIN: u16 = 0x3000;
+
+pub const IMAGE: [u16; 17] = [
+    0xe00c, 0xf022, 0x5260, 0x1263, 0x4804, 0xf021, 0x127f, 0x03fc,
+    0xf025, 0x2002, 0x1001, 0xc1c0, 0x0030, 0x0067, 0x006f, 0x000a,
+    0x0000,
+];
+
+pub fn load(memory: &mut Memory) {
+    for (offset, word) in IMAGE.iter().enumerate() {
+        memory.write(ORIGIN.wrapping_add(offset as u16), *word);
+    }
+    /* start the clock */
+    memory.locations[0xfffe] |= 0x8000;
+}
+
+/* runs from the current PC until the program stops the clock */
+pub fn run(rs: &mut RegisterStorage, mem: &mut Memory, console: &mut dyn Console) -> Result<(), InstructionSetError> {
+    loop {
+        let pc = rs.locations[8];
+        let next = match pc {
+            0x3000 => block_3000(rs, mem, console)?,
+            0x3002 => block_3002(rs, mem, console)?,
+            0x3004 => block_3004(rs, mem, console)?,
+            0x3005 => block_3005(rs, mem, console)?,
+            0x3006 => block_3006(rs, mem, console)?,
+            0x3008 => block_3008(rs, mem, console)?,
+            0x3009 => block_3009(rs, mem, console)?,
+            _ => {
+                /* not discovered statically: interpret a single instruction */
+                let instr = mem.read(pc);
+                rs.locations[8] = pc.wrapping_add(1);
+                Instructions::execute(rs, mem, console, instr)?;
+                rs.locations[8]
+            }
+        };
+        rs.locations[8] = next;
+        if mem.locations[0xfffe] & 0x8000 == 0 {
+            return Ok(());
+        }
+    }
+}
+
+fn block_3000(rs: &mut RegisterStorage, mem: &mut Memory, console: &mut dyn Console) -> Result<u16, InstructionSetError> {
+    // 0x3000: 0xe00c
+    rs.locations[0] = 0x300d;
+    rs.update_flags(0)?;
+    // 0x3001: 0xf022
+    rs.locations[7] = 0x3002;
+    Trap::execute_trap_instruction(rs, mem, console, 0x22 /* PUTS */)?;
+    return Ok(0x3002);
+}
+
+fn block_3002(rs: &mut RegisterStorage, _mem: &mut Memory, _console: &mut dyn Console) -> Result<u16, InstructionSetError> {
+    // 0x3002: 0x5260
+    rs.locations[1] = rs.locations[1] & 0x0000;
+    rs.update_flags(1)?;
+    // 0x3003: 0x1263
+    rs.locations[1] = rs.locations[1].wrapping_add(0x0003);
+    rs.update_flags(1)?;
+    // 0x3004: 0x4804
+    rs.locations[7] = 0x3005;
+    return Ok(0x3009);
+}
+
+fn block_3004(rs: &mut RegisterStorage, _mem: &mut Memory, _console: &mut dyn Console) -> Result<u16, InstructionSetError> {
+    // 0x3004: 0x4804
+    rs.locations[7] = 0x3005;
+    return Ok(0x3009);
+}
+
+fn block_3005(rs: &mut RegisterStorage, mem: &mut Memory, console: &mut dyn Console) -> Result<u16, InstructionSetError> {
+    // 0x3005: 0xf021
+    rs.locations[7] = 0x3006;
+    Trap::execute_trap_instruction(rs, mem, console, 0x21 /* OUT */)?;
+    return Ok(0x3006);
+}
+
+fn block_3006(rs: &mut RegisterStorage, _mem: &mut Memory, _console: &mut dyn Console) -> Result<u16, InstructionSetError> {
+    // 0x3006: 0x127f
+    rs.locations[1] = rs.locations[1].wrapping_add(0xffff);
+    rs.update_flags(1)?;
+    // 0x3007: 0x03fc
+    if rs.locations[9] & 0b001 != 0 {
+        return Ok(0x3004);
+    }
+    return Ok(0x3008);
+}
+
+fn block_3008(rs: &mut RegisterStorage, mem: &mut Memory, console: &mut dyn Console) -> Result<u16, InstructionSetError> {
+    // 0x3008: 0xf025
+    rs.locations[7] = 0x3009;
+    Trap::execute_trap_instruction(rs, mem, console, 0x25 /* HALT */)?;
+    return Ok(0x3009);
+}
+
+fn block_3009(rs: &mut RegisterStorage, mem: &mut Memory, _console: &mut dyn Console) -> Result<u16, InstructionSetError> {
+    // 0x3009: 0x2002
+    rs.locations[0] = mem.read(0x300c);
+    rs.update_flags(0)?;
+    // 0x300a: 0x1001
+    rs.locations[0] = rs.locations[0].wrapping_add(rs.locations[1]);
+    rs.update_flags(0)?;
+    // 0x300b: 0xc1c0
+    return Ok(rs.locations[7]);
+}
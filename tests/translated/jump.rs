@@ -0,0 +1,58 @@
+// translated from an LC-3 image: origin 0x3000, 7 words, 1 blocks
+#![allow(unreachable_code, unused_imports, clippy::all)]
+
+use virtual_machine::libs::{
+    instructions::{InstructionSet, InstructionSetError, Instructions},
+    console::Console,
+    trap::Trap,
+    types::{MemomryTrait, Memory, RegisterStorage, RegisterStorageTrait},
+};
+
+pub const ORIGIN: u16 = 0x3000;
+
+pub const IMAGE: [u16; 7] = [
+    0x2403, 0x5020, 0xc080, 0xf025, 0x3005, 0xe7fa, 0xf025,
+];
+
+pub fn load(memory: &mut Memory) {
+    for (offset, word) in IMAGE.iter().enumerate() {
+        memory.write(ORIGIN.wrapping_add(offset as u16), *word);
+    }
+    /* start the clock */
+    memory.locations[0xfffe] |= 0x8000;
+}
+
+/* runs from the current PC until the program stops the clock */
+pub fn run(rs: &mut RegisterStorage, mem: &mut Memory, console: &mut dyn Console) -> Result<(), InstructionSetError> {
+    loop {
+        let pc = rs.locations[8];
+        let next = match pc {
+            0x3000 => block_3000(rs, mem, console)?,
+            _ => {
+                /* not discovered statically: interpret a single instruction */
+                let instr = mem.read(pc);
+                rs.locations[8] = pc.wrapping_add(1);
+                match instr >> 12 {
+                    0xe => Instructions::effective_address(rs, instr)?,
+                    _ => Instructions::execute(rs, mem, console, instr)?,
+                }
+                rs.locations[8]
+            }
+        };
+        rs.locations[8] = next;
+        if mem.locations[0xfffe] & 0x8000 == 0 {
+            return Ok(());
+        }
+    }
+}
+
+fn block_3000(rs: &mut RegisterStorage, mem: &mut Memory, _console: &mut dyn Console) -> Result<u16, InstructionSetError> {
+    // 0x3000: 0x2403
+    rs.locations[2] = mem.read(0x3004);
+    rs.update_flags(2)?;
+    // 0x3001: 0x5020
+    rs.locations[0] = rs.locations[0] & 0x0000;
+    rs.update_flags(0)?;
+    // 0x3002: 0xc080
+    return Ok(rs.locations[2]);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::{ENGINES, machine};
+use virtual_machine::libs::{error::Fault, os::Os, trap::TrapContext, types::Registers};
+
+/* x26: double R0 */
+fn double(context: &mut TrapContext) -> Result<(), Fault> {
+    let value = context.register(Registers::R0);
+    context.set_register(Registers::R0, value.wrapping_mul(2));
+    context.update_flags(Registers::R0);
+    Ok(())
+}
+
+#[test]
+fn registered_handlers_serve_new_vectors() {
+    /* ADD R0, R0, #3; TRAP x26; TRAP x26; HALT */
+    let program = [0x1023, 0xF026, 0xF026, 0xF025];
+
+    for engine in ENGINES {
+        let (mut vm, _) = machine(engine, &program);
+        vm.register_trap(0x26, double);
+        vm.run(100).unwrap();
+
+        assert!(vm.halted());
+        assert_eq!(vm.register_storage.locations[Registers::R0 as usize], 12);
+        assert_eq!(
+            vm.register_storage.locations[Registers::R7 as usize],
+            0x3004
+        );
+        assert_eq!(vm.instruction_count, 4);
+    }
+}
+
+#[test]
+fn handlers_replace_native_routines_until_unregistered() {
+    /* ADD R0, R0, #15; ADD R0, R0, #15; ADD R0, R0, #15; OUT; OUT */
+    let program = [0x102F, 0x102F, 0x102F, 0xF021, 0xF021];
+
+    for engine in ENGINES {
+        let (mut vm, console) = machine(engine, &program);
+        vm.register_trap(0x21, |context: &mut TrapContext| {
+            let text = format!("<{}>", context.register(Registers::R0));
+            context.console.write_str(&text);
+            Ok(())
+        });
+        vm.run(4).unwrap();
+        assert!(vm.unregister_trap(0x21).is_some());
+        vm.run(1).unwrap();
+
+        assert_eq!(console.output(), b"<45>-");
+    }
+}
+
+#[test]
+fn handlers_take_priority_over_the_os() {
+    /* AND R0, R0, #0; ADD R0, R0, #4; TRAP x26; ADD R1, R0, #0; HALT */
+    let program = [0x5020, 0x1024, 0xF026, 0x1220, 0xF025];
+
+    for engine in ENGINES {
+        let (mut vm, console) = machine(engine, &program);
+        Os::bundled().boot(&mut vm).unwrap();
+        vm.register_trap(0x26, double);
+        vm.run(10_000).unwrap();
+
+        assert_eq!(vm.register_storage.locations[Registers::R1 as usize], 8);
+        assert_eq!(console.output(), b"Program halted\n");
+    }
+}
+
+#[test]
+fn code_written_by_handlers_is_recompiled() {
+    /* ADD R1, R1, #1; TRAP x26 */
+    let program = [0x1261, 0xF026];
+
+    for engine in ENGINES {
+        let (mut vm, _) = machine(engine, &program);
+        /* turn the ADD into ADD R1, R1, #5 */
+        vm.register_trap(0x26, |context: &mut TrapContext| {
+            context.write(0x3000, 0x1265)?;
+            Ok(())
+        });
+        vm.run(2).unwrap();
+        vm.set_pc(0x3000);
+        vm.step().unwrap();
+
+        assert_eq!(vm.register_storage.locations[Registers::R1 as usize], 6);
+    }
+}
+
+#[test]
+fn handler_failures_stop_the_machine_at_the_trap() {
+    /* ADD R0, R0, #1; TRAP x30 */
+    let program = [0x1021, 0xF030];
+
+    for engine in ENGINES {
+        let (mut vm, _) = machine(engine, &program);
+        vm.register_trap(0x30, |_: &mut TrapContext| Err(Fault::TrapFailed(0x30)));
+
+        let err = vm.run(10).unwrap_err();
+        assert_eq!((err.pc, err.instr), (0x3001, 0xF030));
+        assert_eq!(err.cause, Fault::TrapFailed(0x30));
+        assert_eq!(vm.instruction_count, 1);
+    }
+}